@@ -0,0 +1,43 @@
+//! Compares parsing into owned blocks with borrowed parsing. Run with `cargo bench`
+
+extern crate config_parser;
+
+use std::time::Instant;
+
+fn generate(servers: usize) -> String {
+    let mut s = String::new();
+    for i in 0..servers {
+        s.push_str(&format!("server net{} {{\n", i));
+        s.push_str(&format!("    connect irc{}.example.net 6697 tls;\n", i));
+        s.push_str("    nick BleghBot blegh \"I am BleghBot owned by MyAdmin\";\n");
+        s.push_str("    channel \"#freenode\";\n");
+        s.push_str("    channel \"#secret\" mypassword; # a comment\n");
+        s.push_str("    user MyAdmin {\n        allow all;\n    }\n");
+        s.push_str("}\n");
+    }
+    s
+}
+
+fn measure<F: FnMut()>(name: &str, iterations: u32, bytes: usize, mut f: F) {
+    f();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let per_iter = start.elapsed() / iterations;
+    let mb_per_sec = bytes as f64 / per_iter.as_secs_f64() / 1e6;
+    println!("{:<10} {:>12?}/iter {:>10.1} MB/s", name, per_iter, mb_per_sec);
+}
+
+fn main() {
+    let input = generate(2000);
+    let iterations = 20;
+    println!("input: {} bytes, {} iterations", input.len(), iterations);
+
+    measure("owned", iterations, input.len(), || {
+        config_parser::parse_string(input.clone()).unwrap();
+    });
+    measure("borrowed", iterations, input.len(), || {
+        config_parser::parse_str(&input).unwrap();
+    });
+}
@@ -0,0 +1,41 @@
+extern crate config_parser;
+
+use std::{env, fs, process};
+use config_parser::Schema;
+use config_parser::lsp::Server;
+
+const USAGE: &str = "usage: config-parser-lsp [--schema SCHEMA]
+
+Serves the Language Server Protocol over standard input and output.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut server = Server::new();
+    match args.as_slice() {
+        [] => {},
+        [flag, path] if flag == "--schema" => server = server.schema(load_schema(path)),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+    if let Err(e) = server.run_stdio() {
+        eprintln!("config-parser-lsp: {}", e);
+        process::exit(1);
+    }
+}
+
+fn load_schema(path: &str) -> Schema {
+    let source = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let cfg = config_parser::parse_string(source.clone()).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    Schema::from_config(&cfg).unwrap_or_else(|d| {
+        eprint!("{}", d.render(path, &source));
+        process::exit(1);
+    })
+}
@@ -0,0 +1,385 @@
+extern crate config_parser;
+
+use std::{env, fs, process};
+use std::io::{self, Read};
+use config_parser::{ConfigBlock, Diagnostic, ParseOptions, Schema, Severity};
+use config_parser::convert::{self, Converted};
+use config_parser::dialect::{Dialect, UnknownDialect};
+use config_parser::diff;
+use config_parser::formatter::{self, Style};
+use config_parser::merge::Merger;
+use config_parser::patch::Patch;
+use config_parser::path::Path;
+use config_parser::writer;
+
+const USAGE: &str = "usage:
+    config-parser [--dialect native|nginx|pulseaudio] COMMAND ...
+
+    config-parser check [--schema SCHEMA] [FILE...]
+    config-parser fmt [--check] [--indent N] [--tabs] [--align] [FILE...]
+    config-parser get PATH [FILE]
+    config-parser diff [--patch] OLD NEW
+    config-parser patch PATCH [FILE]
+    config-parser merge [--schema SCHEMA] BASE OVERLAY...
+    config-parser convert --to json|yaml|toml [--idiomatic] [FILE]
+    config-parser convert --from json|yaml|toml [--idiomatic] [FILE]
+
+Without FILE, or with -, standard input is read. fmt rewrites files in place and
+prints the result when reading standard input. convert warns about everything the
+idiomatic mapping loses. diff compares the trees, not the text, and fails if they
+differ; with --patch it prints a patch, which patch applies. Patches ending in .json
+are read as JSON. merge layers the overlays onto the base in order, with merge
+strategies from the schema. --dialect reads nginx.conf files or PulseAudio scripts for
+check, get, diff, patch, merge and convert --to.";
+
+enum Failure {
+    /// The command line is wrong
+    Usage(String),
+    /// The command ran, but the input is invalid. Details have been printed already
+    Failed
+}
+
+struct Input {
+    name: String,
+    path: Option<String>,
+    source: String
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match run(&args) {
+        Ok(()) => 0,
+        Err(Failure::Failed) => 1,
+        Err(Failure::Usage(msg)) => {
+            eprintln!("config-parser: {}\n\n{}", msg, USAGE);
+            2
+        }
+    };
+    process::exit(code);
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    let (dialect, args) = match args {
+        [flag, name, rest @ ..] if flag == "--dialect" => {
+            (name.parse().map_err(|e: UnknownDialect| Failure::Usage(e.to_string()))?, rest)
+        },
+        _ => (Dialect::Native, args)
+    };
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command, rest),
+        None => return Err(Failure::Usage(String::from("missing command")))
+    };
+    match &**command {
+        "check" => check(rest, dialect),
+        "fmt" if dialect != Dialect::Native => Err(Failure::Usage(String::from("fmt only supports the native syntax"))),
+        "fmt" => fmt(rest),
+        "get" => get(rest, dialect),
+        "diff" => diff(rest, dialect),
+        "patch" => patch(rest, dialect),
+        "merge" => merge(rest, dialect),
+        "convert" => convert(rest, dialect),
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        other => Err(Failure::Usage(format!("unknown command `{}`", other)))
+    }
+}
+
+fn check(args: &[String], dialect: Dialect) -> Result<(), Failure> {
+    let mut schema = None;
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &**arg {
+            "--schema" => match args.next() {
+                Some(path) => schema = Some(load_schema(path)?),
+                None => return Err(Failure::Usage(String::from("--schema needs a file")))
+            },
+            _ => files.push(file_arg(arg)?)
+        }
+    }
+    let mut ok = true;
+    for input in read_inputs(&files)? {
+        let cfg = match parse(&input, dialect) {
+            Ok(cfg) => cfg,
+            Err(_) => {
+                ok = false;
+                continue;
+            }
+        };
+        if let Some(ref schema) = schema {
+            for d in schema.validate(&cfg) {
+                ok &= d.severity != Severity::Error;
+                eprint!("{}", d.render(&input.name, &input.source));
+            }
+        }
+    }
+    if ok { Ok(()) } else { Err(Failure::Failed) }
+}
+
+fn fmt(args: &[String]) -> Result<(), Failure> {
+    let mut check_only = false;
+    let mut style = Style::new();
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &**arg {
+            "--check" => check_only = true,
+            "--tabs" => style = style.tabs(true),
+            "--align" => style = style.align_params(true),
+            "--indent" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(width) => style = style.indent_width(width),
+                None => return Err(Failure::Usage(String::from("--indent needs a number")))
+            },
+            _ => files.push(file_arg(arg)?)
+        }
+    }
+    let mut ok = true;
+    for input in read_inputs(&files)? {
+        let formatted = match formatter::format(&input.source, &style) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprint!("{}", Diagnostic::from(&e).render(&input.name, &input.source));
+                ok = false;
+                continue;
+            }
+        };
+        if check_only {
+            if formatted != input.source {
+                eprintln!("{}: not formatted", input.name);
+                ok = false;
+            }
+            continue;
+        }
+        match input.path {
+            Some(ref path) if formatted != input.source => {
+                if let Err(e) = fs::write(path, &formatted) {
+                    eprintln!("{}: {}", input.name, e);
+                    ok = false;
+                }
+            },
+            Some(_) => {},
+            None => print!("{}", formatted)
+        }
+    }
+    if ok { Ok(()) } else { Err(Failure::Failed) }
+}
+
+fn get(args: &[String], dialect: Dialect) -> Result<(), Failure> {
+    let (path, file) = match args {
+        [path] => (path, None),
+        [path, file] => (path, Some(file_arg(file)?)),
+        _ => return Err(Failure::Usage(String::from("get expects a path and at most one file")))
+    };
+    let path: Path = path.parse().map_err(|e: config_parser::path::PathError| Failure::Usage(e.to_string()))?;
+    let input = read_input(file)?;
+    let cfg = parse(&input, dialect)?;
+    let matches = path.select(&cfg);
+    for block in &matches {
+        let params: Vec<_> = block.params().iter().map(|p| writer::quote(p)).collect();
+        println!("{}", params.join(" "));
+    }
+    if matches.is_empty() { Err(Failure::Failed) } else { Ok(()) }
+}
+
+fn diff(args: &[String], dialect: Dialect) -> Result<(), Failure> {
+    let (as_patch, args) = match args.split_first() {
+        Some((flag, rest)) if flag == "--patch" => (true, rest),
+        _ => (false, args)
+    };
+    let (old, new) = match args {
+        [old, new] => (read_input(Some(file_arg(old)?))?, read_input(Some(file_arg(new)?))?),
+        _ => return Err(Failure::Usage(String::from("diff expects two files")))
+    };
+    let (old_cfg, new_cfg) = (parse(&old, dialect)?, parse(&new, dialect)?);
+    let diff = diff::diff(&old_cfg, &new_cfg);
+    if as_patch {
+        print!("{}", Patch::from_diff(&diff));
+    } else {
+        print!("{}", diff.render(&old.name, &new.name));
+    }
+    if diff.is_empty() { Ok(()) } else { Err(Failure::Failed) }
+}
+
+fn patch(args: &[String], dialect: Dialect) -> Result<(), Failure> {
+    let (patch_file, file) = match args {
+        [patch] => (file_arg(patch)?, None),
+        [patch, file] => (file_arg(patch)?, Some(file_arg(file)?)),
+        _ => return Err(Failure::Usage(String::from("patch expects a patch and at most one file")))
+    };
+    let patch_input = read_input(Some(patch_file))?;
+    let patch = if patch_file.ends_with(".json") {
+        Patch::from_json(&patch_input.source).map_err(|e| {
+            eprintln!("{}: {}", patch_input.name, e);
+            Failure::Failed
+        })?
+    } else {
+        Patch::from_config(&parse(&patch_input, Dialect::Native)?).map_err(|d| {
+            eprint!("{}", d.render(&patch_input.name, &patch_input.source));
+            Failure::Failed
+        })?
+    };
+    let input = read_input(file)?;
+    let mut cfg = parse(&input, dialect)?;
+    match patch.apply(&mut cfg) {
+        Ok(()) => {
+            print!("{}", cfg);
+            Ok(())
+        },
+        Err(e) => {
+            eprintln!("{}: {}", patch_input.name, e);
+            Err(Failure::Failed)
+        }
+    }
+}
+
+fn merge(args: &[String], dialect: Dialect) -> Result<(), Failure> {
+    let mut merger = Merger::new();
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &**arg {
+            "--schema" => match args.next() {
+                Some(path) => merger = merger.schema(load_schema(path)?),
+                None => return Err(Failure::Usage(String::from("--schema needs a file")))
+            },
+            _ => files.push(file_arg(arg)?)
+        }
+    }
+    if files.len() < 2 {
+        return Err(Failure::Usage(String::from("merge expects a base and at least one overlay")));
+    }
+    let mut layers = vec![];
+    for input in read_inputs(&files)? {
+        layers.push(parse(&input, dialect)?);
+    }
+    let merged = merger.merge_all(&layers).expect("there are at least two layers");
+    print!("{}", merged);
+    Ok(())
+}
+
+fn convert(args: &[String], dialect: Dialect) -> Result<(), Failure> {
+    let mut to = None;
+    let mut from = None;
+    let mut idiomatic = false;
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &**arg {
+            "--to" | "--from" => {
+                let format = args.next().ok_or_else(|| Failure::Usage(format!("{} needs a format", arg)))?;
+                if arg == "--to" { to = Some(format) } else { from = Some(format) }
+            },
+            "--idiomatic" => idiomatic = true,
+            _ if file.is_none() => file = Some(file_arg(arg)?),
+            _ => return Err(Failure::Usage(String::from("convert expects at most one file")))
+        }
+    }
+    let input = read_input(file)?;
+    let output = match (to.map(|f| &**f), from.map(|f| &**f)) {
+        (Some(format), None) => {
+            let cfg = parse(&input, dialect)?;
+            match (format, idiomatic) {
+                ("json", false) => Ok(lossless(convert::json::to_string(&cfg) + "\n")),
+                ("json", true) => {
+                    let converted = convert::json::to_idiomatic_string(&cfg);
+                    Ok(Converted { value: converted.value + "\n", warnings: converted.warnings })
+                },
+                ("yaml", false) => convert::yaml::to_string(&cfg).map(lossless).map_err(|e| e.to_string()),
+                ("yaml", true) => convert::yaml::to_idiomatic_string(&cfg).map_err(|e| e.to_string()),
+                ("toml", false) => convert::toml::to_string(&cfg).map(lossless).map_err(|e| e.to_string()),
+                ("toml", true) => convert::toml::to_idiomatic_string(&cfg).map_err(|e| e.to_string()),
+                (other, _) => return Err(Failure::Usage(format!("unknown format `{}`", other)))
+            }
+        },
+        (None, Some(format)) => {
+            let source = &input.source;
+            let result = match (format, idiomatic) {
+                ("json", false) => convert::json::from_str(source).map(lossless),
+                ("json", true) => convert::json::from_idiomatic_str(source),
+                ("yaml", false) => convert::yaml::from_str(source).map(lossless),
+                ("yaml", true) => convert::yaml::from_idiomatic_str(source),
+                ("toml", false) => convert::toml::from_str(source).map(lossless),
+                ("toml", true) => convert::toml::from_idiomatic_str(source),
+                (other, _) => return Err(Failure::Usage(format!("unknown format `{}`", other)))
+            };
+            result
+                .map(|c| Converted { value: c.value.to_string(), warnings: c.warnings })
+                .map_err(|e| e.to_string())
+        },
+        _ => return Err(Failure::Usage(String::from("convert expects either --to or --from")))
+    };
+    match output {
+        Ok(output) => {
+            for warning in &output.warnings {
+                eprint!("{}", warning.render(&input.name, &input.source));
+            }
+            print!("{}", output.value);
+            Ok(())
+        },
+        Err(e) => {
+            eprintln!("{}: cannot convert: {}", input.name, e);
+            Err(Failure::Failed)
+        }
+    }
+}
+
+fn lossless<T>(value: T) -> Converted<T> {
+    Converted { value, warnings: vec![] }
+}
+
+/// Rejects unknown options, `-` stays as standard input
+fn file_arg(arg: &str) -> Result<&str, Failure> {
+    if arg.starts_with('-') && arg != "-" {
+        Err(Failure::Usage(format!("unknown option `{}`", arg)))
+    } else {
+        Ok(arg)
+    }
+}
+
+fn read_inputs(files: &[&str]) -> Result<Vec<Input>, Failure> {
+    if files.is_empty() {
+        return Ok(vec![read_input(None)?]);
+    }
+    files.iter().map(|f| read_input(Some(f))).collect()
+}
+
+fn read_input(file: Option<&str>) -> Result<Input, Failure> {
+    let result = match file {
+        None | Some("-") => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).map(|_| Input {
+                name: String::from("<stdin>"),
+                path: None,
+                source
+            })
+        },
+        Some(path) => fs::read_to_string(path).map(|source| Input {
+            name: path.to_owned(),
+            path: Some(path.to_owned()),
+            source
+        })
+    };
+    result.map_err(|e| {
+        eprintln!("{}: {}", file.unwrap_or("<stdin>"), e);
+        Failure::Failed
+    })
+}
+
+/// Parses the input, printing the error if there is one
+fn parse(input: &Input, dialect: Dialect) -> Result<ConfigBlock, Failure> {
+    dialect.parse_str(&input.source, &ParseOptions::default()).map_err(|e| {
+        eprint!("{}", Diagnostic::from(&e).render(&input.name, &input.source));
+        Failure::Failed
+    })
+}
+
+fn load_schema(path: &str) -> Result<Schema, Failure> {
+    let input = read_input(Some(path))?;
+    let cfg = parse(&input, Dialect::Native)?;
+    Schema::from_config(&cfg).map_err(|d| {
+        eprint!("{}", d.render(&input.name, &input.source));
+        Failure::Failed
+    })
+}
@@ -0,0 +1,286 @@
+//! Parsing without copying
+//!
+//! Blocks parsed from a `&str` borrow their names and parameters from the input. Only
+//! string literals containing escape sequences need to be allocated.
+
+use std::borrow::Cow;
+use std::{iter, mem, slice};
+use super::config;
+use super::error::Result;
+use super::lexer::{Lexer, Literal};
+use super::options::ParseOptions;
+use super::parser::{self, Events, Tree};
+use super::span::Span;
+
+/// Represents a block in the config file or the document root, borrowing from the input
+///
+/// This mirrors `config::ConfigBlock`, see there for details.
+#[derive(Debug, Clone)]
+pub struct ConfigBlock<'a> {
+    name: Cow<'a, str>,
+    params: Vec<Cow<'a, str>>,
+    inner: Vec<ConfigBlock<'a>>,
+    span: Option<Span>
+}
+
+impl <'a> ConfigBlock<'a> {
+    /// Creates a new ConfigBlock. This probably isn't very useful to you.
+    pub fn new(name: Cow<'a, str>, params: Vec<Cow<'a, str>>, inner: Vec<ConfigBlock<'a>>) -> ConfigBlock<'a> {
+        ConfigBlock {
+            name,
+            params,
+            inner,
+            span: None
+        }
+    }
+
+    /// Adds a new sub block. This probably isn't very useful for you
+    pub fn add_block(&mut self, block: ConfigBlock<'a>) {
+        self.inner.push(block);
+    }
+
+    /// Returns an iterator of all inner config options with the specified name
+    pub fn matching<'b>(&'b self, name: &'b str) -> ConfigIter<'a, 'b> {
+        ConfigIter {
+            it: self.inner.iter(),
+            name
+        }
+    }
+
+    /// Returns the name of the option key
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns where this block was defined in the source
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Returns all inner config options
+    pub fn inner(&self) -> &Vec<ConfigBlock<'a>> {
+        &self.inner
+    }
+
+    /// Returns all parameters
+    pub fn params(&self) -> &Vec<Cow<'a, str>> {
+        &self.params
+    }
+
+    /// Returns the parameter count
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Returns true if there are no parameters
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Returns a parameter. Panics if the parameter index is too high
+    pub fn get(&self, i: usize) -> &str {
+        &self.params[i]
+    }
+
+    /// Returns a parameter. Returns None if the index is too high
+    pub fn get_opt(&self, i: usize) -> Option<&str> {
+        self.params.get(i).map(|p| &**p)
+    }
+
+    /// Copies everything borrowed from the input, producing a regular `ConfigBlock`.
+    /// Works without recursion, like dropping
+    pub fn into_owned(mut self) -> config::ConfigBlock {
+        // The copies of the blocks being converted, with their inner options still to do
+        let mut stack = vec![(self.take_owned(), mem::take(&mut self.inner).into_iter())];
+        loop {
+            let next = stack.last_mut().and_then(|&mut (_, ref mut inner)| inner.next());
+            match next {
+                Some(mut block) => {
+                    let inner = mem::take(&mut block.inner);
+                    stack.push((block.take_owned(), inner.into_iter()));
+                },
+                None => {
+                    let (done, _) = stack.pop().expect("the stack is never empty while converting");
+                    match stack.last_mut() {
+                        Some(&mut (ref mut parent, _)) => parent.add_block(done),
+                        None => return done
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves name and parameters into an owned block without inner options
+    fn take_owned(&mut self) -> config::ConfigBlock {
+        let params = mem::take(&mut self.params).into_iter().map(Cow::into_owned).collect();
+        let mut block = config::ConfigBlock::new(mem::take(&mut self.name).into_owned(), params, vec![]);
+        block.set_span(self.span);
+        block
+    }
+}
+
+impl <'a, 'b> PartialEq<ConfigBlock<'b>> for ConfigBlock<'a> {
+    fn eq(&self, other: &ConfigBlock<'b>) -> bool {
+        self.name == other.name && self.params == other.params && self.inner == other.inner
+    }
+}
+
+impl <'a> Eq for ConfigBlock<'a> {}
+
+impl <'a> Drop for ConfigBlock<'a> {
+    /// Drops inner blocks one by one instead of recursively, so deeply nested documents
+    /// can't overflow the stack
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.inner);
+        while let Some(mut block) = stack.pop() {
+            stack.append(&mut block.inner);
+        }
+    }
+}
+
+pub struct ConfigIter<'a, 'b> where 'a: 'b {
+    it: slice::Iter<'b, ConfigBlock<'a>>,
+    name: &'b str
+}
+
+impl <'a, 'b> iter::Iterator for ConfigIter<'a, 'b> {
+    type Item = &'b ConfigBlock<'a>;
+    fn next(&mut self) -> Option<&'b ConfigBlock<'a>> {
+        loop {
+            match self.it.next() {
+                Some(c) if c.name() == self.name => return Some(c),
+                Some(_) => {},
+                None => return None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.it.size_hint().1)
+    }
+}
+
+/// Collects literals as slices of the input, only allocating once an escape sequence
+/// is encountered
+struct BorrowedLiteral<'a> {
+    source: &'a str,
+    start: usize,
+    end: usize,
+    owned: Option<String>
+}
+
+impl <'a> Literal for BorrowedLiteral<'a> {
+    type Value = Cow<'a, str>;
+
+    fn begin(&mut self, offset: usize) {
+        self.start = offset;
+        self.end = offset;
+        self.owned = None;
+    }
+
+    fn push(&mut self, c: char, end: usize) {
+        match self.owned {
+            Some(ref mut s) => s.push(c),
+            None => self.end = end
+        }
+    }
+
+    fn push_escaped(&mut self, c: char) {
+        let (source, start, end) = (self.source, self.start, self.end);
+        self.owned.get_or_insert_with(|| String::from(&source[start..end])).push(c);
+    }
+
+    fn finish(&mut self) -> Cow<'a, str> {
+        match self.owned.take() {
+            Some(s) => Cow::Owned(s),
+            None => Cow::Borrowed(&self.source[self.start..self.end])
+        }
+    }
+}
+
+/// Returns the events of a config file, borrowing literals from the input
+pub fn events(input: &str) -> Events<'_, Cow<'_, str>> {
+    events_with(input, &ParseOptions::default())
+}
+
+/// Like `events()`, but with the given options
+pub fn events_with<'a>(input: &'a str, options: &ParseOptions) -> Events<'a, Cow<'a, str>> {
+    let literal = BorrowedLiteral {
+        source: input,
+        start: 0,
+        end: 0,
+        owned: None
+    };
+    Events::with_options(Box::new(Lexer::with_options(input.chars(), literal, options)), options)
+}
+
+/// Parses a config file, borrowing literals from the input
+pub fn parse(input: &str) -> Result<ConfigBlock<'_>> {
+    parse_with(input, &ParseOptions::default())
+}
+
+/// Like `parse()`, but with the given options
+pub fn parse_with<'a>(input: &'a str, options: &ParseOptions) -> Result<ConfigBlock<'a>> {
+    parser::build_tree(events_with(input, options))
+}
+
+impl <'a> Tree<Cow<'a, str>> for ConfigBlock<'a> {
+    fn root() -> ConfigBlock<'a> {
+        ConfigBlock::new(Cow::Borrowed(""), vec![], vec![])
+    }
+
+    fn option(name: Cow<'a, str>, params: Vec<Cow<'a, str>>, span: Span) -> ConfigBlock<'a> {
+        let mut block = ConfigBlock::new(name, params, vec![]);
+        block.span = Some(span);
+        block
+    }
+
+    fn add(&mut self, child: ConfigBlock<'a>) {
+        self.add_block(child);
+    }
+
+    fn close(&mut self, end: Span) {
+        let start = self.span.map(|s| s.start).unwrap_or(end.start);
+        self.span = Some(Span::new(start, end.end));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_it_borrowing_literals() {
+        let cfg = parse("server \"free node\" { nick Bot; }").unwrap();
+        let server = &cfg.inner()[0];
+        assert!(matches!(server.params()[0], Cow::Borrowed("free node")));
+        assert!(matches!(server.inner()[0].params()[0], Cow::Borrowed("Bot")));
+    }
+
+    #[test]
+    fn test_it_allocating_escaped_strings() {
+        let cfg = parse("channel \"a\\\"b\\nc\" öäü;").unwrap();
+        let channel = &cfg.inner()[0];
+        assert!(matches!(channel.params()[0], Cow::Owned(ref s) if s == "a\"b\nc"));
+        assert!(matches!(channel.params()[1], Cow::Borrowed("öäü")));
+    }
+
+    #[test]
+    fn test_it_matching_the_owned_parser() {
+        let input = "test shit;\noption some {\n    crap \"x y\"; # comment\n    /* more */ inner { a; }\n};\n";
+        let borrowed = parse(input).unwrap();
+        let owned = super::super::parse_string(String::from(input)).unwrap();
+        assert_eq!(borrowed.matching("option").next().unwrap().get(0), "some");
+        assert_eq!(borrowed.inner()[1].span(), owned.inner()[1].span());
+        assert_eq!(borrowed.into_owned(), owned);
+    }
+
+    #[test]
+    fn test_it_handling_deeply_nested_input() {
+        let depth = 200000;
+        let input = format!("{}{}", "a {".repeat(depth), "}".repeat(depth));
+        assert_eq!(parse(&input).unwrap().inner().len(), 1);
+        assert_eq!(parse(&input).unwrap().into_owned().inner().len(), 1);
+    }
+}
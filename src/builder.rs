@@ -0,0 +1,137 @@
+use super::config::ConfigBlock;
+
+/// Builds a ConfigBlock step by step. Created by `ConfigBlock::builder()`
+///
+/// ```
+/// use config_parser::ConfigBlock;
+///
+/// let server = ConfigBlock::builder("server")
+///     .param("freenode")
+///     .child(ConfigBlock::builder("nick").param("Bot"))
+///     .build();
+///
+/// assert_eq!(server.matching("nick").next().unwrap().get(0), "Bot");
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigBuilder {
+    name: String,
+    params: Vec<String>,
+    inner: Vec<ConfigBlock>
+}
+
+impl ConfigBuilder {
+    /// Creates a new builder for a block with the specified name
+    pub fn new<S: Into<String>>(name: S) -> ConfigBuilder {
+        ConfigBuilder {
+            name: name.into(),
+            params: vec![],
+            inner: vec![]
+        }
+    }
+
+    /// Appends a parameter
+    pub fn param<S: Into<String>>(mut self, value: S) -> ConfigBuilder {
+        self.params.push(value.into());
+        self
+    }
+
+    /// Appends all parameters of an iterator
+    pub fn params<I, S>(mut self, values: I) -> ConfigBuilder where
+            I: IntoIterator<Item=S>,
+            S: Into<String> {
+        self.params.extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    /// Appends a sub block. Accepts both finished blocks and other builders
+    pub fn child<B: Into<ConfigBlock>>(mut self, block: B) -> ConfigBuilder {
+        self.inner.push(block.into());
+        self
+    }
+
+    /// Appends all sub blocks of an iterator
+    pub fn children<I, B>(mut self, blocks: I) -> ConfigBuilder where
+            I: IntoIterator<Item=B>,
+            B: Into<ConfigBlock> {
+        self.inner.extend(blocks.into_iter().map(Into::into));
+        self
+    }
+
+    /// Finishes the block
+    pub fn build(self) -> ConfigBlock {
+        ConfigBlock::new(self.name, self.params, self.inner)
+    }
+}
+
+impl From<ConfigBuilder> for ConfigBlock {
+    fn from(builder: ConfigBuilder) -> ConfigBlock {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::config::ConfigBlock;
+
+    #[test]
+    fn test_building_a_nested_block() {
+        let built = ConfigBlock::builder("")
+            .child(ConfigBlock::builder("server")
+                .param("freenode")
+                .child(ConfigBlock::builder("connect").params(vec!["irc.freenode.net", "6697", "tls"]))
+                .children(vec!["#a", "#b"].into_iter().map(|c| ConfigBlock::builder("channel").param(c))))
+            .build();
+
+        assert_eq!(built, ConfigBlock::new(String::new(), vec![], vec![
+            ConfigBlock::new(String::from("server"), vec![String::from("freenode")], vec![
+                ConfigBlock::new(String::from("connect"), vec![
+                    String::from("irc.freenode.net"), String::from("6697"), String::from("tls")
+                ], vec![]),
+                ConfigBlock::new(String::from("channel"), vec![String::from("#a")], vec![]),
+                ConfigBlock::new(String::from("channel"), vec![String::from("#b")], vec![])
+            ])
+        ]));
+    }
+
+    #[test]
+    fn test_macro_matches_builder() {
+        let from_macro = config! {
+            server freenode {
+                connect "irc.freenode.net" 6697 tls;
+                nick Bot;
+                user MyAdmin {
+                    allow all;
+                };
+            }
+            blah;
+        };
+
+        let built = ConfigBlock::builder("")
+            .child(ConfigBlock::builder("server")
+                .param("freenode")
+                .child(ConfigBlock::builder("connect").param("irc.freenode.net").param("6697").param("tls"))
+                .child(ConfigBlock::builder("nick").param("Bot"))
+                .child(ConfigBlock::builder("user").param("MyAdmin")
+                    .child(ConfigBlock::builder("allow").param("all"))))
+            .child(ConfigBlock::builder("blah"))
+            .build();
+
+        assert_eq!(from_macro, built);
+    }
+
+    #[test]
+    fn test_macro_literal_names_and_negative_numbers() {
+        let cfg = config! { "!channel" x; offset -5 2.5; "my key" v; };
+        let built = ConfigBlock::builder("")
+            .child(ConfigBlock::builder("!channel").param("x"))
+            .child(ConfigBlock::builder("offset").param("-5").param("2.5"))
+            .child(ConfigBlock::builder("my key").param("v"))
+            .build();
+        assert_eq!(cfg, built);
+    }
+
+    #[test]
+    fn test_empty_macro() {
+        assert_eq!(config!{}, ConfigBlock::builder("").build());
+    }
+}
@@ -0,0 +1,384 @@
+//! Conditional options
+//!
+//! With `ParseOptions::conditionals`, options named `if` and `else` choose between
+//! alternatives while parsing, like the settings of different deployments:
+//!
+//! ```text
+//! if env == prod {
+//!     connect irc.example.net 6697 tls;
+//! } else if (env == staging || env == test) && !local {
+//!     connect staging.example.net 6697 tls;
+//! } else {
+//!     connect localhost 6667;
+//! }
+//! ```
+//!
+//! With `Conditionals::Evaluate`, the inner options of the first branch whose condition
+//! holds take the place of the whole chain. `Conditionals::KeepBranches` keeps the `if`
+//! and `else` options in the tree, with parentheses as `(` and `)` parameters, for tools
+//! which need to see every branch. Conditions are checked for errors in both modes.
+//!
+//! A condition is made of words separated by spaces:
+//!
+//! * a variable given with `ParseOptions::variable` stands for its value, `env:NAME` for
+//!   the environment variable `NAME` and any other word for itself
+//! * `a == b` and `a != b` compare values
+//! * a value on its own holds if it is a variable or environment variable which is set
+//!   and neither empty, `0` nor `false`
+//! * `!`, `&&` and `||` combine conditions, binding in that order, and parentheses group
+//!   them. `!` may also be written in front of a word, as in `!local`
+//!
+//! A quoted word is always a value standing for itself, so `name == "!x"` and
+//! `op == "&&"` compare with literal text. The tree doesn't record quotes, so `resolve`
+//! and `evaluate` only know them while parsing. Conditions are evaluated before
+//! templates are resolved, so branches may define and use templates.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::env;
+use std::rc::Rc;
+use std::result;
+use super::config::ConfigBlock;
+use super::error::{Error, ErrorType, Result};
+use super::lexer::{Token, TokenType};
+use super::options::{Conditionals, ParseOptions};
+use super::span::Position;
+
+/// Where the parser found quoted words in conditions: the start of the option and the
+/// index of the parameter
+pub(crate) type Quoted = Rc<RefCell<HashSet<(Position, usize)>>>;
+
+/// Evaluates the conditions of the `if` and `else` options of a tree, keeping or
+/// dropping their branches depending on the options
+pub fn resolve(cfg: &ConfigBlock, options: &ParseOptions) -> Result<ConfigBlock> {
+    resolve_quoted(cfg, options, &HashSet::new())
+}
+
+/// Like `resolve`, reading the parameters found by `Tokens` as quoted words
+pub(crate) fn resolve_quoted(cfg: &ConfigBlock, options: &ParseOptions, quoted: &HashSet<(Position, usize)>) -> Result<ConfigBlock> {
+    let mut resolved = ConfigBlock::new(cfg.name().to_owned(), cfg.params().clone(), resolve_inner(cfg, options, quoted)?);
+    resolved.set_span(cfg.span());
+    Ok(resolved)
+}
+
+/// Evaluates a condition, given as the parameters of an `if` option, against the
+/// variables of the options and the environment
+pub fn evaluate(condition: &[String], options: &ParseOptions) -> result::Result<bool, String> {
+    let words: Vec<(&str, bool)> = condition.iter().map(|w| (&w[..], false)).collect();
+    evaluate_words(&words, options)
+}
+
+/// Like `evaluate`, for words which are marked as quoted or not
+fn evaluate_words(condition: &[(&str, bool)], options: &ParseOptions) -> result::Result<bool, String> {
+    let mut words = vec![];
+    for &(word, quoted) in condition {
+        let mut word = word;
+        while !quoted && word.len() > 1 && word.starts_with('!') && word != "!=" {
+            words.push(Word { text: "!", quoted: false });
+            word = &word[1..];
+        }
+        words.push(Word { text: word, quoted });
+    }
+    if words.is_empty() {
+        return Err(String::from("missing condition"));
+    }
+    let mut expression = Expression { words, next: 0, options };
+    let holds = expression.or()?;
+    match expression.peek() {
+        Some(word) => Err(format!("unexpected `{}` in condition", word)),
+        None => Ok(holds)
+    }
+}
+
+fn fail(at: &ConfigBlock, message: String) -> Error {
+    let pos = at.span().map(|s| s.start).unwrap_or_default();
+    Error::new(pos.line, pos.col, ErrorType::Condition(message), None)
+}
+
+fn resolve_inner(block: &ConfigBlock, options: &ParseOptions, quoted: &HashSet<(Position, usize)>) -> Result<Vec<ConfigBlock>> {
+    let keep = options.conditionals == Conditionals::KeepBranches;
+    let mut inner = vec![];
+    // Whether a branch of the current `if` chain was taken, None outside of chains
+    let mut chain = None;
+    for option in block.inner() {
+        // The condition starts after the `if` of `else if`
+        let (taken, condition) = match (option.name(), chain) {
+            ("if", _) => (false, Some(0)),
+            ("else", Some(taken)) => match option.params().first() {
+                None => (taken, None),
+                Some(first) if first == "if" => (taken, Some(1)),
+                Some(_) => return Err(fail(option, String::from("expected `if` or a block after `else`")))
+            },
+            ("else", None) => return Err(fail(option, String::from("`else` without `if`"))),
+            _ => {
+                chain = None;
+                inner.push(resolve_quoted(option, options, quoted)?);
+                continue;
+            }
+        };
+        let holds = match condition {
+            Some(skip) => {
+                let start = option.span().map(|s| s.start);
+                let words: Vec<(&str, bool)> = option.params().iter().enumerate().skip(skip)
+                    .map(|(i, w)| (&w[..], start.map(|s| quoted.contains(&(s, i))).unwrap_or(false)))
+                    .collect();
+                evaluate_words(&words, options).map_err(|e| fail(option, e))?
+            },
+            None => true
+        };
+        chain = condition.map(|_| taken || holds);
+        if keep {
+            inner.push(resolve_quoted(option, options, quoted)?);
+        } else if holds && !taken {
+            inner.extend(resolve_inner(option, options, quoted)?);
+        }
+    }
+    Ok(inner)
+}
+
+/// A value in a condition
+struct Value {
+    text: String,
+    /// Whether the value comes from a variable which is set
+    set: bool
+}
+
+impl Value {
+    fn holds(&self) -> bool {
+        self.set && !matches!(&*self.text, "" | "0" | "false")
+    }
+}
+
+/// A word of a condition. Quoted words are never operators or variables
+#[derive(Clone, Copy)]
+struct Word<'a> {
+    text: &'a str,
+    quoted: bool
+}
+
+/// A recursive descent parser evaluating a condition while reading it
+struct Expression<'a> {
+    words: Vec<Word<'a>>,
+    next: usize,
+    options: &'a ParseOptions
+}
+
+impl <'a> Expression<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.words.get(self.next).map(|w| w.text)
+    }
+
+    /// Returns the next word if it is unquoted
+    fn peek_operator(&self) -> Option<&'a str> {
+        self.words.get(self.next).filter(|w| !w.quoted).map(|w| w.text)
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.peek_operator() == Some(word);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> result::Result<bool, String> {
+        let mut holds = self.and()?;
+        while self.eat("||") {
+            // Both sides are always read, so that errors on the right are reported
+            holds = self.and()? || holds;
+        }
+        Ok(holds)
+    }
+
+    fn and(&mut self) -> result::Result<bool, String> {
+        let mut holds = self.not()?;
+        while self.eat("&&") {
+            holds = self.not()? && holds;
+        }
+        Ok(holds)
+    }
+
+    fn not(&mut self) -> result::Result<bool, String> {
+        if self.eat("!") {
+            return self.not().map(|holds| !holds);
+        }
+        if self.eat("(") {
+            let holds = self.or()?;
+            return match (self.peek_operator(), self.peek()) {
+                (Some(")"), _) => {
+                    self.next += 1;
+                    Ok(holds)
+                },
+                (_, Some(word)) => Err(format!("expected `)` instead of `{}`", word)),
+                (_, None) => Err(String::from("missing `)`"))
+            };
+        }
+        let left = self.value()?;
+        if self.eat("==") {
+            Ok(left.text == self.value()?.text)
+        } else if self.eat("!=") {
+            Ok(left.text != self.value()?.text)
+        } else {
+            Ok(left.holds())
+        }
+    }
+
+    fn value(&mut self) -> result::Result<Value, String> {
+        let word = match self.words.get(self.next) {
+            Some(word) if word.quoted => {
+                self.next += 1;
+                return Ok(Value { text: word.text.to_owned(), set: false });
+            },
+            Some(word) if ["(", ")", "!", "==", "!=", "&&", "||"].contains(&word.text) => {
+                return Err(format!("unexpected `{}` in condition", word.text));
+            },
+            Some(word) => word.text,
+            None => return Err(String::from("incomplete condition"))
+        };
+        self.next += 1;
+        if let Some(value) = self.options.variables.get(word) {
+            return Ok(Value { text: value.clone(), set: true });
+        }
+        Ok(match word.strip_prefix("env:").map(env::var_os) {
+            Some(Some(value)) => Value { text: value.to_string_lossy().into_owned(), set: true },
+            Some(None) => Value { text: String::new(), set: false },
+            None => Value { text: word.to_owned(), set: false }
+        })
+    }
+}
+
+/// Passes tokens on, turning the parentheses in the parameters of `if` and `else`
+/// options into `(` and `)` literals, which the parser accepts. Quoted words in the
+/// conditions are added to `quoted`
+pub(crate) struct Tokens<'a> {
+    tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>,
+    /// Where the current option starts, `None` before its name
+    option: Option<Position>,
+    /// The parameters of the current option so far
+    params: usize,
+    in_condition: bool,
+    quoted: Quoted
+}
+
+impl <'a> Tokens<'a> {
+    pub(crate) fn new(tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>, quoted: Quoted) -> Tokens<'a> {
+        Tokens { tokens, option: None, params: 0, in_condition: false, quoted }
+    }
+}
+
+impl <'a> Iterator for Tokens<'a> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Result<Token>> {
+        let mut token = match self.tokens.next()? {
+            Ok(token) => token,
+            Err(e) => return Some(Err(e))
+        };
+        match (&token.token_type, self.option) {
+            (&TokenType::Comment(_), _) => {},
+            (&TokenType::OpenBrace, _) | (&TokenType::CloseBrace, _) | (&TokenType::Semicolon, _) => self.option = None,
+            (name, None) => {
+                self.option = Some(token.position());
+                self.params = 0;
+                self.in_condition = matches!(name, TokenType::RawLiteral(name) if name == "if" || name == "else");
+            },
+            (word, Some(option)) => {
+                let paren = match *word {
+                    TokenType::OpenParen if self.in_condition => Some("("),
+                    TokenType::CloseParen if self.in_condition => Some(")"),
+                    TokenType::StringLiteral(_) if self.in_condition => {
+                        self.quoted.borrow_mut().insert((option, self.params));
+                        None
+                    },
+                    _ => None
+                };
+                if let Some(paren) = paren {
+                    token.token_type = TokenType::RawLiteral(String::from(paren));
+                }
+                self.params += 1;
+            }
+        }
+        Some(Ok(token))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(mode: Conditionals) -> ParseOptions {
+        ParseOptions::new().conditionals(mode).variable("env", "prod").variable("debug", "0")
+    }
+
+    fn parse(text: &str, mode: Conditionals) -> Result<ConfigBlock> {
+        ::parse_string_with(String::from(text), &options(mode))
+    }
+
+    #[test]
+    fn test_evaluate() {
+        env::set_var("CONFIG_PARSER_TEST_CONDITION", "yes");
+        let holds = |condition: &str| {
+            let words: Vec<String> = condition.split(' ').map(String::from).collect();
+            evaluate(&words, &options(Conditionals::Evaluate)).unwrap()
+        };
+        assert!(holds("env == prod"));
+        assert!(holds("env != dev"));
+        assert!(!holds("debug"));
+        assert!(holds("!debug"));
+        assert!(!holds("unknown"));
+        assert!(holds("env:CONFIG_PARSER_TEST_CONDITION == yes"));
+        assert!(!holds("env:CONFIG_PARSER_TEST_UNSET"));
+        assert!(holds("env == dev || env == prod && !debug"));
+        assert!(!holds("( env == dev || env == prod ) && debug"));
+        assert!(holds("! ( debug )"));
+    }
+
+    #[test]
+    fn test_branches() {
+        let text = "
+            if env == dev { port 1; }
+            else if (env == prod && !debug) {
+                port 2;
+                if (debug) { verbose; } else { quiet; }
+            }
+            else { port 3; }
+            if env == prod { extra; }
+            channel x;
+        ";
+        assert_eq!(parse(text, Conditionals::Evaluate).unwrap(), config! { port 2; quiet; extra; channel x; });
+
+        let kept = parse(text, Conditionals::KeepBranches).unwrap();
+        let names: Vec<&str> = kept.inner().iter().map(|b| b.name()).collect();
+        assert_eq!(names, ["if", "else", "else", "if", "channel"]);
+        assert_eq!(kept.inner()[1].params(), &["if", "(", "env", "==", "prod", "&&", "!debug", ")"]);
+        assert_eq!(kept.inner()[1].inner()[1].inner()[0], config! { verbose; }.inner()[0]);
+        assert_eq!(::parse_string(kept.to_string()).map(|cfg| cfg == kept), Ok(true));
+
+        let plain = ::parse_string(String::from("if a { b; } else { c; }")).unwrap();
+        assert_eq!(plain.inner().len(), 2);
+    }
+
+    #[test]
+    fn test_quoted_words() {
+        let options = options(Conditionals::Evaluate).variable("name", "!x").variable("op", "&&");
+        let text = "if name == \"!x\" && op == \"&&\" { a; }\nif (\"env\" == prod) { b; }\nif \"env\" != env { c; }";
+        assert_eq!(::parse_string_with(String::from(text), &options).unwrap(), config! { a; c; });
+        let text = "if name == \"x\" { a; } else if (\"(\" == \"(\") { b; }";
+        assert_eq!(::parse_string_with(String::from(text), &options).unwrap(), config! { b; });
+        assert_eq!(parse("if (env \")\" { }", Conditionals::Evaluate).unwrap_err().to_string(), "1:1: expected `)` instead of `)`");
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |text: &str| parse(text, Conditionals::KeepBranches).unwrap_err().to_string();
+        assert_eq!(message("if { a; }"), "1:1: missing condition");
+        assert_eq!(message("a;\nelse { b; }"), "2:1: `else` without `if`");
+        assert_eq!(message("if a { } else b { }"), "1:10: expected `if` or a block after `else`");
+        assert_eq!(message("if a { } else { } else { }"), "1:19: `else` without `if`");
+        assert_eq!(message("if (a == b { }"), "1:1: missing `)`");
+        assert_eq!(message("if a == { }"), "1:1: incomplete condition");
+        assert_eq!(message("if a b { }"), "1:1: unexpected `b` in condition");
+        assert_eq!(message("if a && || b { }"), "1:1: unexpected `||` in condition");
+        assert_eq!(message("x (a);").as_str(), "1:3: unexpected `(`, expected ; or {");
+    }
+}
@@ -1,23 +1,52 @@
-use std::{iter, slice};
+use std::{fmt, iter, mem, slice};
+use zeroize::Zeroize;
+use super::builder::ConfigBuilder;
+use super::span::Span;
+
+/// What `Debug` and the writer show instead of a secret parameter
+pub const REDACTED: &str = "***";
 
 /// Represents a block in the config file or the document root
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Two blocks are equal if their names, parameters and inner blocks are equal. Where they
+/// were found in the source does not matter, and neither does whether a parameter is a
+/// secret or a secret reference.
+#[derive(Clone)]
 pub struct ConfigBlock {
     name: String,
     params: Vec<String>,
-    inner: Vec<ConfigBlock>
+    inner: Vec<ConfigBlock>,
+    span: Option<Span>,
+    /// The parameters which are secrets or secret references, by index
+    marks: Vec<(usize, Mark)>
+}
+
+/// What a marked parameter holds
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mark {
+    /// A `secret(...)` reference read with `ParseOptions::secrets`
+    Reference,
+    /// A secret set with `set_secret_param`
+    Secret
 }
 
 impl ConfigBlock {
     /// Creates a new ConfigBlock. This probably isn't very useful to you.
     pub fn new(name: String, params: Vec<String>, inner: Vec<ConfigBlock>) -> ConfigBlock {
         ConfigBlock {
-            name: name,
-            params: params,
-            inner: inner
+            name,
+            params,
+            inner,
+            span: None,
+            marks: vec![]
         }
     }
 
+    /// Returns a builder for a block with the specified name
+    pub fn builder<S: Into<String>>(name: S) -> ConfigBuilder {
+        ConfigBuilder::new(name)
+    }
+
     /// Adds a new sub block. This probably isn't very useful for you
     pub fn add_block(&mut self, block: ConfigBlock) {
         self.inner.push(block);
@@ -27,7 +56,7 @@ impl ConfigBlock {
     pub fn matching<'a>(&'a self, name: &'a str) -> ConfigIter<'a> {
         ConfigIter {
             it: self.inner.iter(),
-            name: name
+            name
         }
     }
 
@@ -36,21 +65,167 @@ impl ConfigBlock {
         &self.name
     }
 
+    /// Returns where this block was defined in the source. The document root and blocks
+    /// created in code have no span
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Sets where this block was defined in the source
+    pub fn set_span(&mut self, span: Option<Span>) {
+        self.span = span;
+    }
+
     /// Returns all inner config options
-    pub fn inner<'a>(&'a self) -> &'a Vec<ConfigBlock> {
+    pub fn inner(&self) -> &Vec<ConfigBlock> {
         &self.inner
     }
 
+    /// Returns all inner config options for modification, e.g. to reorder them
+    pub fn inner_mut(&mut self) -> &mut Vec<ConfigBlock> {
+        &mut self.inner
+    }
+
+    /// Returns a mutable iterator of all inner config options with the specified name
+    pub fn matching_mut<'a>(&'a mut self, name: &'a str) -> ConfigIterMut<'a> {
+        ConfigIterMut {
+            it: self.inner.iter_mut(),
+            name
+        }
+    }
+
+    /// Inserts a sub block at the given position. Panics if the index is too high
+    pub fn insert_at(&mut self, index: usize, block: ConfigBlock) {
+        self.inner.insert(index, block);
+    }
+
+    /// Removes and returns the sub block at the given position. Panics if the index is too high
+    pub fn remove_at(&mut self, index: usize) -> ConfigBlock {
+        self.inner.remove(index)
+    }
+
+    /// Removes all inner config options with the specified name and returns them in order
+    pub fn remove_matching(&mut self, name: &str) -> Vec<ConfigBlock> {
+        let (removed, kept) = self.inner.drain(..).partition(|b| b.name == name);
+        self.inner = kept;
+        removed
+    }
+
+    /// Only keeps the inner config options for which the predicate returns true
+    pub fn retain<F>(&mut self, f: F) where F: FnMut(&ConfigBlock) -> bool {
+        self.inner.retain(f);
+    }
+
+    /// Renames the option key
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = name.into();
+    }
+
+    /// Returns all parameters
+    pub fn params(&self) -> &Vec<String> {
+        &self.params
+    }
+
+    /// Returns all parameters for modification. Secrets and secret references stay
+    /// marked by index, so call `clear_secrets` first when moving or removing secrets
+    pub fn params_mut(&mut self) -> &mut Vec<String> {
+        &mut self.params
+    }
+
+    /// Replaces a parameter. Panics if the parameter index is too high
+    pub fn set_param<S: Into<String>>(&mut self, i: usize, value: S) {
+        if self.is_secret(i) {
+            self.params[i].zeroize();
+        }
+        self.marks.retain(|&(m, _)| m != i);
+        self.params[i] = value.into();
+    }
+
+    /// Replaces a parameter with a secret, which `Debug`, the writer and the converters
+    /// show as `***` and which is overwritten with zeros when it is replaced or dropped.
+    /// Panics if the parameter index is too high
+    pub fn set_secret_param<S: Into<String>>(&mut self, i: usize, value: S) {
+        self.set_param(i, value);
+        self.marks.push((i, Mark::Secret));
+    }
+
+    /// Returns true if the parameter was set with `set_secret_param`
+    pub fn is_secret(&self, i: usize) -> bool {
+        self.marks.contains(&(i, Mark::Secret))
+    }
+
+    /// Returns true if the parameter is a secret reference read with
+    /// `ParseOptions::secrets`, which `secret::resolve` replaces
+    pub fn is_reference(&self, i: usize) -> bool {
+        self.marks.contains(&(i, Mark::Reference))
+    }
+
+    /// Marks a parameter as a secret reference
+    pub(crate) fn mark_reference(&mut self, i: usize) {
+        if i < self.params.len() && !self.marks.iter().any(|&(m, _)| m == i) {
+            self.marks.push((i, Mark::Reference));
+        }
+    }
+
+    /// Returns the parameters with secrets replaced by `***`
+    pub(crate) fn shown_params(&self) -> Vec<&str> {
+        self.params.iter().enumerate()
+            .map(|(i, p)| if self.is_secret(i) { REDACTED } else { p })
+            .collect()
+    }
+
+    /// Overwrites the secret parameters with zeros, leaving empty parameters, and forgets
+    /// that they were secrets. Secret references stay marked
+    pub fn clear_secrets(&mut self) {
+        for &(i, mark) in &self.marks {
+            if let (Mark::Secret, Some(secret)) = (mark, self.params.get_mut(i)) {
+                secret.zeroize();
+            }
+        }
+        self.marks.retain(|&(_, mark)| mark != Mark::Secret);
+    }
+
+    /// Overwrites the secrets with zeros and forgets all marks
+    pub(crate) fn clear_marks(&mut self) {
+        for (i, mark) in self.marks.drain(..) {
+            if let (Mark::Secret, Some(secret)) = (mark, self.params.get_mut(i)) {
+                secret.zeroize();
+            }
+        }
+    }
+
+    /// Appends a parameter
+    pub fn push_param<S: Into<String>>(&mut self, value: S) {
+        self.params.push(value.into());
+    }
+
     /// Returns the parameter count
     pub fn len(&self) -> usize {
         self.params.len()
     }
 
+    /// Returns true if there are no parameters
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
     /// Returns a parameter. Panics if the parameter index is too high
     pub fn get(&self, i: usize) -> &str {
         &self.params[i]
     }
 
+    /// Returns a copy of the block with other inner options, keeping the marks of secrets
+    /// and secret references
+    pub(crate) fn with_inner(&self, inner: Vec<ConfigBlock>) -> ConfigBlock {
+        ConfigBlock {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            inner,
+            span: self.span,
+            marks: self.marks.clone()
+        }
+    }
+
     /// Returns a parameter. Returns None if the index is too high
     pub fn get_opt(&self, i: usize) -> Option<&str> {
         if i < self.params.len() {
@@ -61,6 +236,38 @@ impl ConfigBlock {
     }
 }
 
+impl PartialEq for ConfigBlock {
+    fn eq(&self, other: &ConfigBlock) -> bool {
+        self.name == other.name && self.params == other.params && self.inner == other.inner
+    }
+}
+
+impl Eq for ConfigBlock {}
+
+impl fmt::Debug for ConfigBlock {
+    /// Like a derived implementation, but shows secret parameters as `***`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConfigBlock")
+            .field("name", &self.name)
+            .field("params", &self.shown_params())
+            .field("inner", &self.inner)
+            .field("span", &self.span)
+            .finish()
+    }
+}
+
+impl Drop for ConfigBlock {
+    /// Zeroes the secret parameters and drops inner blocks one by one instead of
+    /// recursively, so deeply nested documents can't overflow the stack
+    fn drop(&mut self) {
+        self.clear_marks();
+        let mut stack = mem::take(&mut self.inner);
+        while let Some(mut block) = stack.pop() {
+            stack.append(&mut block.inner);
+        }
+    }
+}
+
 pub struct ConfigIter<'a> {
     it: slice::Iter<'a, ConfigBlock>,
     name: &'a str
@@ -82,3 +289,78 @@ impl <'a> iter::Iterator for ConfigIter <'a> {
         (0, self.it.size_hint().1)
     }
 }
+
+pub struct ConfigIterMut<'a> {
+    it: slice::IterMut<'a, ConfigBlock>,
+    name: &'a str
+}
+
+impl <'a> iter::Iterator for ConfigIterMut <'a> {
+    type Item = &'a mut ConfigBlock;
+    fn next(&mut self) -> Option<&'a mut ConfigBlock> {
+        loop {
+            match self.it.next() {
+                Some(c) if c.name() == self.name => return Some(c),
+                Some(_) => {},
+                None => return None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.it.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block(name: &str, params: &[&str]) -> ConfigBlock {
+        ConfigBlock::new(String::from(name), params.iter().map(|p| String::from(*p)).collect(), vec![])
+    }
+
+    fn sample() -> ConfigBlock {
+        ConfigBlock::new(String::new(), vec![], vec![
+            block("channel", &["#a"]),
+            block("nick", &["Bot"]),
+            block("channel", &["#b", "secret"])
+        ])
+    }
+
+    #[test]
+    fn test_modifying_matching_blocks() {
+        let mut cfg = sample();
+        for c in cfg.matching_mut("channel") {
+            c.set_param(0, "#changed");
+            c.push_param("extra");
+        }
+        let params: Vec<&Vec<String>> = cfg.matching("channel").map(|c| c.params()).collect();
+        assert_eq!(params, vec![
+            &vec![String::from("#changed"), String::from("extra")],
+            &vec![String::from("#changed"), String::from("secret"), String::from("extra")]
+        ]);
+    }
+
+    #[test]
+    fn test_removing_and_inserting_blocks() {
+        let mut cfg = sample();
+        let removed = cfg.remove_matching("channel");
+        assert_eq!(removed, vec![block("channel", &["#a"]), block("channel", &["#b", "secret"])]);
+        assert_eq!(cfg.inner(), &vec![block("nick", &["Bot"])]);
+
+        cfg.insert_at(0, block("server", &["freenode"]));
+        assert_eq!(cfg.inner()[0].name(), "server");
+        assert_eq!(cfg.remove_at(1), block("nick", &["Bot"]));
+    }
+
+    #[test]
+    fn test_retain_and_rename() {
+        let mut cfg = sample();
+        cfg.retain(|b| b.len() == 1);
+        assert_eq!(cfg.inner().len(), 2);
+        cfg.inner_mut()[1].set_name("user");
+        cfg.inner_mut().reverse();
+        assert_eq!(cfg.inner(), &vec![block("user", &["Bot"]), block("channel", &["#a"])]);
+    }
+}
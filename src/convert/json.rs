@@ -0,0 +1,319 @@
+//! JSON input and output, see the `convert` module for the mappings
+
+use serde_json::{self, Map, Value};
+use config::ConfigBlock;
+use diagnostic::Diagnostic;
+use super::{join_pointer, lossy, ConvertError, Converted};
+
+/// The key holding the parameters of a block in the idiomatic mapping
+const PARAMS_KEY: &str = "@params";
+
+/// Returns the JSON representation of a block and everything inside it
+pub fn to_value(cfg: &ConfigBlock) -> Value {
+    let mut object = Map::new();
+    object.insert(String::from("name"), Value::String(cfg.name().to_owned()));
+    object.insert(String::from("params"), params_value(cfg));
+    object.insert(String::from("children"), Value::Array(cfg.inner().iter().map(to_value).collect()));
+    Value::Object(object)
+}
+
+/// Returns the indented JSON text of a block
+pub fn to_string(cfg: &ConfigBlock) -> String {
+    serde_json::to_string_pretty(&to_value(cfg)).expect("JSON values always serialize")
+}
+
+/// Reads a block from its JSON representation
+pub fn from_value(value: &Value) -> Result<ConfigBlock, ConvertError> {
+    block_from_value(value, "")
+}
+
+/// Reads a block from JSON text
+pub fn from_str(text: &str) -> Result<ConfigBlock, ConvertError> {
+    from_value(&parse(text)?)
+}
+
+/// Returns the idiomatic JSON representation of a block, with a warning for every
+/// change reading it back would show
+pub fn to_idiomatic_value(cfg: &ConfigBlock) -> Converted<Value> {
+    let mut warnings = vec![];
+    let value = idiomatic_block(cfg, &mut warnings);
+    Converted { value, warnings }
+}
+
+/// Returns the indented idiomatic JSON text of a block
+pub fn to_idiomatic_string(cfg: &ConfigBlock) -> Converted<String> {
+    let converted = to_idiomatic_value(cfg);
+    Converted {
+        value: serde_json::to_string_pretty(&converted.value).expect("JSON values always serialize"),
+        warnings: converted.warnings
+    }
+}
+
+/// Reads the root block from its idiomatic JSON representation, with a warning for
+/// every value whose type is lost
+pub fn from_idiomatic_value(value: &Value) -> Result<Converted<ConfigBlock>, ConvertError> {
+    let mut warnings = vec![];
+    let cfg = match *value {
+        Value::Object(ref object) => object_to_block(String::new(), object, "", &mut warnings)?,
+        _ => return Err(ConvertError::new("", "expected an object"))
+    };
+    Ok(Converted { value: cfg, warnings })
+}
+
+/// Reads the root block from idiomatic JSON text
+pub fn from_idiomatic_str(text: &str) -> Result<Converted<ConfigBlock>, ConvertError> {
+    from_idiomatic_value(&parse(text)?)
+}
+
+fn parse(text: &str) -> Result<Value, ConvertError> {
+    serde_json::from_str(text).map_err(|e| ConvertError::new("", format!("invalid JSON: {}", e)))
+}
+
+/// Returns the parameters, with secrets as `***`
+fn params_value(cfg: &ConfigBlock) -> Value {
+    Value::Array(cfg.shown_params().into_iter().map(|p| Value::String(p.to_owned())).collect())
+}
+
+fn idiomatic_block(cfg: &ConfigBlock, warnings: &mut Vec<Diagnostic>) -> Value {
+    let mut object = Map::new();
+    if !cfg.params().is_empty() {
+        object.insert(String::from(PARAMS_KEY), params_value(cfg));
+    }
+    let mut previous = None;
+    for child in cfg.inner() {
+        let name = child.name();
+        if name == PARAMS_KEY {
+            warnings.push(Diagnostic::warning(format!("an option called `{}` cannot be represented and is left out", name), child.span()));
+            continue;
+        }
+        if object.contains_key(name) {
+            if previous != Some(name) {
+                warnings.push(Diagnostic::warning(format!("`{}` is moved next to the first option of that name", name), child.span()));
+            }
+            previous = Some(name);
+            continue;
+        }
+        previous = Some(name);
+        let same: Vec<&ConfigBlock> = cfg.matching(name).collect();
+        let value = if same.len() == 1 {
+            option_value(child, false, warnings)
+        } else {
+            Value::Array(same.into_iter().map(|c| option_value(c, true, warnings)).collect())
+        };
+        object.insert(name.to_owned(), value);
+    }
+    Value::Object(object)
+}
+
+/// Returns the idiomatic value of an inner option, `repeated` if its name is not unique
+fn option_value(cfg: &ConfigBlock, repeated: bool, warnings: &mut Vec<Diagnostic>) -> Value {
+    if !cfg.inner().is_empty() {
+        return idiomatic_block(cfg, warnings);
+    }
+    match cfg.params().len() {
+        1 if !repeated => Value::String(cfg.shown_params()[0].to_owned()),
+        _ => params_value(cfg)
+    }
+}
+
+fn block_from_value(value: &Value, pointer: &str) -> Result<ConfigBlock, ConvertError> {
+    let object = match *value {
+        Value::Object(ref object) => object,
+        _ => return Err(ConvertError::new(pointer, "expected an object"))
+    };
+    let mut name = None;
+    let mut params = vec![];
+    let mut children = vec![];
+    for (key, value) in object {
+        let pointer = join_pointer(pointer, key);
+        match &**key {
+            "name" => match *value {
+                Value::String(ref s) => name = Some(s.clone()),
+                _ => return Err(ConvertError::new(&pointer, "expected a string"))
+            },
+            "params" => params = params_from_value(value, &pointer)?,
+            "children" => match *value {
+                Value::Array(ref items) => {
+                    for (i, item) in items.iter().enumerate() {
+                        children.push(block_from_value(item, &join_pointer(&pointer, i))?);
+                    }
+                },
+                _ => return Err(ConvertError::new(&pointer, "expected an array"))
+            },
+            _ => return Err(ConvertError::new(&pointer, format!("unknown key `{}`", key)))
+        }
+    }
+    match name {
+        Some(name) => Ok(ConfigBlock::new(name, params, children)),
+        None => Err(ConvertError::new(pointer, "missing `name`"))
+    }
+}
+
+fn object_to_block(name: String, object: &Map<String, Value>, pointer: &str, warnings: &mut Vec<Diagnostic>) -> Result<ConfigBlock, ConvertError> {
+    if object.is_empty() && !pointer.is_empty() {
+        warnings.push(lossy(pointer, "an empty object becomes an option without parameters"));
+    }
+    let mut params = vec![];
+    let mut children = vec![];
+    for (key, value) in object {
+        let pointer = join_pointer(pointer, key);
+        if key == PARAMS_KEY {
+            params = idiomatic_params(value, &pointer, warnings)?;
+            continue;
+        }
+        match *value {
+            Value::Array(ref items) if items.iter().any(|i| i.is_array() || i.is_object()) => {
+                for (i, item) in items.iter().enumerate() {
+                    children.push(option_from_value(key, item, &join_pointer(&pointer, i), warnings)?);
+                }
+            },
+            _ => children.push(option_from_value(key, value, &pointer, warnings)?)
+        }
+    }
+    Ok(ConfigBlock::new(name, params, children))
+}
+
+fn option_from_value(name: &str, value: &Value, pointer: &str, warnings: &mut Vec<Diagnostic>) -> Result<ConfigBlock, ConvertError> {
+    match *value {
+        Value::Null => {
+            warnings.push(lossy(pointer, "null becomes an option without parameters"));
+            Ok(ConfigBlock::new(name.to_owned(), vec![], vec![]))
+        },
+        Value::Array(_) => Ok(ConfigBlock::new(name.to_owned(), idiomatic_params(value, pointer, warnings)?, vec![])),
+        Value::Object(ref object) => object_to_block(name.to_owned(), object, pointer, warnings),
+        ref scalar => {
+            scalar_warning(scalar, pointer, warnings);
+            Ok(ConfigBlock::new(name.to_owned(), vec![param_from_value(scalar, pointer)?], vec![]))
+        }
+    }
+}
+
+fn idiomatic_params(value: &Value, pointer: &str, warnings: &mut Vec<Diagnostic>) -> Result<Vec<String>, ConvertError> {
+    let params = params_from_value(value, pointer)?;
+    if let Value::Array(ref items) = *value {
+        for (i, item) in items.iter().enumerate() {
+            scalar_warning(item, &join_pointer(pointer, i), warnings);
+        }
+    }
+    Ok(params)
+}
+
+fn scalar_warning(value: &Value, pointer: &str, warnings: &mut Vec<Diagnostic>) {
+    match *value {
+        Value::Number(_) => warnings.push(lossy(pointer, "a number becomes a string")),
+        Value::Bool(_) => warnings.push(lossy(pointer, "a boolean becomes a string")),
+        _ => {}
+    }
+}
+
+fn params_from_value(value: &Value, pointer: &str) -> Result<Vec<String>, ConvertError> {
+    match *value {
+        Value::Array(ref items) => items.iter().enumerate()
+            .map(|(i, item)| param_from_value(item, &join_pointer(pointer, i)))
+            .collect(),
+        _ => Err(ConvertError::new(pointer, "expected an array"))
+    }
+}
+
+fn param_from_value(value: &Value, pointer: &str) -> Result<String, ConvertError> {
+    match *value {
+        Value::String(ref s) => Ok(s.clone()),
+        Value::Number(ref n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(ConvertError::new(pointer, "expected a string, number or boolean"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lossless_mapping() {
+        let cfg = config! { server freenode { channel "#rust"; } };
+        assert_eq!(to_value(&cfg), json!({
+            "name": "", "params": [], "children": [
+                {"name": "server", "params": ["freenode"], "children": [
+                    {"name": "channel", "params": ["#rust"], "children": []}
+                ]}
+            ]
+        }));
+        assert_eq!(from_value(&to_value(&cfg)).unwrap(), cfg);
+    }
+
+    #[test]
+    fn test_hiding_secrets() {
+        let mut cfg = config! { key a b; pass x; };
+        cfg.inner_mut()[0].set_secret_param(1, "hunter2");
+        cfg.inner_mut()[1].set_secret_param(0, "hunter3");
+        let text = format!("{} {}", to_string(&cfg), to_idiomatic_string(&cfg).value);
+        assert!(!text.contains("hunter") && text.contains("\"***\""));
+    }
+
+    #[test]
+    fn test_reading_lossless_input() {
+        let cfg = from_str(r#"{"name": "", "children": [{"name": "port", "params": [6667, true]}]}"#).unwrap();
+        assert_eq!(cfg, config! { port "6667" "true"; });
+        let err = from_str(r#"{"name": "", "children": [{"name": "a", "params": [null]}]}"#).unwrap_err();
+        assert_eq!(err.to_string(), "/children/0/params/0: expected a string, number or boolean");
+        let err = from_str(r#"{"name": "", "child": []}"#).unwrap_err();
+        assert_eq!(err.to_string(), "/child: unknown key `child`");
+        assert!(from_str("{").unwrap_err().to_string().starts_with("invalid JSON"));
+    }
+
+    #[test]
+    fn test_idiomatic_mapping() {
+        let cfg = config! {
+            server freenode {
+                channel "#rust";
+                nick bot;
+                channel "#cpp" key;
+                ssl;
+            }
+            listen "127.0.0.1" 6697;
+        };
+        let converted = to_idiomatic_value(&cfg);
+        assert_eq!(converted.warnings, vec![Diagnostic::warning("`channel` is moved next to the first option of that name", None)]);
+        let value = converted.value;
+        assert_eq!(value, json!({
+            "server": {
+                "@params": ["freenode"],
+                "channel": [["#rust"], ["#cpp", "key"]],
+                "nick": "bot",
+                "ssl": []
+            },
+            "listen": ["127.0.0.1", "6697"]
+        }));
+        let back = from_idiomatic_value(&value).unwrap();
+        assert!(back.warnings.is_empty());
+        assert_eq!(back.value, config! {
+            server freenode { channel "#rust"; channel "#cpp" key; nick bot; ssl; }
+            listen "127.0.0.1" 6697;
+        });
+    }
+
+    #[test]
+    fn test_reading_idiomatic_input() {
+        let cfg = from_idiomatic_str(r#"{"debug": null, "port": 8080, "user": [{"@params": ["a"]}, {"@params": ["b"]}]}"#).unwrap();
+        assert_eq!(cfg.value, config! { debug; port 8080; user a; user b; });
+        let warnings: Vec<String> = cfg.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "warning: /debug: null becomes an option without parameters",
+            "warning: /port: a number becomes a string"
+        ]);
+        let err = from_idiomatic_str(r#"{"user": [{"@params": "a"}]}"#).unwrap_err();
+        assert_eq!(err.to_string(), "/user/0/@params: expected an array");
+    }
+
+    #[test]
+    fn test_idiomatic_warnings() {
+        let cfg = ::parse_string(String::from("a 1;\nb { @params x; }\na 2;")).unwrap();
+        let converted = to_idiomatic_value(&cfg);
+        assert_eq!(converted.value, json!({"a": [["1"], ["2"]], "b": {}}));
+        let warnings: Vec<String> = converted.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "2:5: warning: an option called `@params` cannot be represented and is left out",
+            "3:1: warning: `a` is moved next to the first option of that name"
+        ]);
+    }
+}
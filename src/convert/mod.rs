@@ -0,0 +1,112 @@
+//! Conversion of config trees to and from other formats
+//!
+//! Every block maps to an object with its name, its parameters and its inner blocks:
+//!
+//! ```text
+//! server freenode { channel "#rust"; }
+//! ```
+//!
+//! becomes
+//!
+//! ```text
+//! {"name": "", "params": [], "children": [
+//!     {"name": "server", "params": ["freenode"], "children": [
+//!         {"name": "channel", "params": ["#rust"], "children": []}
+//!     ]}
+//! ]}
+//! ```
+//!
+//! Nothing but the source positions is lost, so the mapping works for every tree, except
+//! that secrets are written as `***` and secret references are read back as ordinary
+//! strings, see the `secret` module. When reading it back, `params` and `children` may
+//! be left out, and numbers and booleans are accepted as parameters.
+//!
+//! # Idiomatic mapping
+//!
+//! The idiomatic mapping is easier to query, with tools like `jq`, and to write by hand.
+//! A block is an object keyed by the names of its inner options:
+//!
+//! * an option without inner options is its parameter if it has exactly one, and the
+//!   array of its parameters otherwise
+//! * an option with inner options is an object, with its parameters in `@params`
+//! * options whose name occurs more than once are collected into an array, where
+//!   options without inner options are always parameter arrays
+//!
+//! ```text
+//! {"server": {"@params": ["freenode"], "channel": [["#rust"], ["#cpp", "key"]], "nick": "bot"}}
+//! ```
+//!
+//! Options with the same name are grouped where the first of them appears, so the
+//! order of differently named options can change, and options called `@params` are
+//! left out. Otherwise reading the output back gives the same tree. When reading,
+//! `null` and `{}` stand for options without parameters, and numbers and booleans
+//! become strings.
+//!
+//! As these conversions can lose information, they return `Converted` values carrying
+//! a warning about each loss. Warnings about the input document name the JSON pointer
+//! of the affected value, since its source position is unknown.
+
+use std::fmt;
+use std::error;
+use super::diagnostic::Diagnostic;
+
+pub mod json;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+#[cfg(feature = "toml")]
+pub mod toml;
+
+/// Input that does not follow the mapping
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConvertError {
+    pointer: String,
+    message: String
+}
+
+impl ConvertError {
+    pub fn new<S: Into<String>>(pointer: &str, message: S) -> ConvertError {
+        ConvertError {
+            pointer: pointer.to_owned(),
+            message: message.into()
+        }
+    }
+
+    /// Returns the JSON pointer to the offending value, empty for the whole document
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+impl error::Error for ConvertError {}
+
+/// The result of a conversion which may lose information
+#[derive(Debug, Clone)]
+pub struct Converted<T> {
+    pub value: T,
+    /// Describes everything that did not survive the conversion
+    pub warnings: Vec<Diagnostic>
+}
+
+/// Returns a warning about a value of the input document
+fn lossy<S: fmt::Display>(pointer: &str, message: S) -> Diagnostic {
+    Diagnostic::warning(format!("{}: {}", pointer, message), None)
+}
+
+/// Appends a key or index to a JSON pointer
+fn join_pointer<K: fmt::Display>(pointer: &str, key: K) -> String {
+    format!("{}/{}", pointer, key.to_string().replace('~', "~0").replace('/', "~1"))
+}
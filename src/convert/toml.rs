@@ -0,0 +1,143 @@
+//! TOML input and output, see the `convert` module for the mappings
+//!
+//! TOML values are read like JSON values, with dates and times becoming strings. TOML
+//! writes the tables of a block after its other values, which reorders options when an
+//! option with inner options comes first, so this is reported as a warning.
+
+use serde_json::{self, Map};
+use toml::{self, Value};
+use config::ConfigBlock;
+use diagnostic::Diagnostic;
+use super::{join_pointer, json, lossy, ConvertError, Converted};
+
+/// Returns the TOML text of a block. Inner blocks become arrays of tables
+pub fn to_string(cfg: &ConfigBlock) -> Result<String, toml::ser::Error> {
+    toml::to_string(&json::to_value(cfg))
+}
+
+/// Reads a block from TOML text in the lossless mapping
+pub fn from_str(text: &str) -> Result<ConfigBlock, ConvertError> {
+    let value = to_json(&parse(text)?, "", &mut vec![]);
+    json::from_value(&value)
+}
+
+/// Returns the TOML text of a block in the idiomatic mapping
+pub fn to_idiomatic_string(cfg: &ConfigBlock) -> Result<Converted<String>, toml::ser::Error> {
+    let mut converted = json::to_idiomatic_value(cfg);
+    table_order_warnings(&converted.value, "", &mut converted.warnings);
+    Ok(Converted {
+        value: toml::to_string(&converted.value)?,
+        warnings: converted.warnings
+    })
+}
+
+/// Reads the root block from TOML text in the idiomatic mapping
+pub fn from_idiomatic_str(text: &str) -> Result<Converted<ConfigBlock>, ConvertError> {
+    let mut warnings = vec![];
+    let value = to_json(&parse(text)?, "", &mut warnings);
+    let mut converted = json::from_idiomatic_value(&value)?;
+    warnings.append(&mut converted.warnings);
+    converted.warnings = warnings;
+    Ok(converted)
+}
+
+fn parse(text: &str) -> Result<Value, ConvertError> {
+    text.parse().map_err(|e| ConvertError::new("", format!("invalid TOML: {}", e)))
+}
+
+fn to_json(value: &Value, pointer: &str, warnings: &mut Vec<Diagnostic>) -> serde_json::Value {
+    match *value {
+        Value::String(ref s) => serde_json::Value::String(s.clone()),
+        Value::Integer(i) => serde_json::Value::from(i),
+        Value::Float(f) => match serde_json::Number::from_f64(f) {
+            Some(number) => serde_json::Value::Number(number),
+            None => {
+                warnings.push(lossy(pointer, format!("`{}` becomes a string", f)));
+                serde_json::Value::String(f.to_string())
+            }
+        },
+        Value::Boolean(b) => serde_json::Value::Bool(b),
+        Value::Datetime(ref d) => {
+            warnings.push(lossy(pointer, "a date becomes a string"));
+            serde_json::Value::String(d.to_string())
+        },
+        Value::Array(ref items) => serde_json::Value::Array(items.iter().enumerate()
+            .map(|(i, item)| to_json(item, &join_pointer(pointer, i), warnings))
+            .collect()),
+        Value::Table(ref table) => {
+            let mut object = Map::new();
+            for (key, item) in table {
+                object.insert(key.clone(), to_json(item, &join_pointer(pointer, key), warnings));
+            }
+            serde_json::Value::Object(object)
+        }
+    }
+}
+
+/// Warns about objects where TOML moves a table before a value which follows it
+fn table_order_warnings(value: &serde_json::Value, pointer: &str, warnings: &mut Vec<Diagnostic>) {
+    let object = match *value {
+        serde_json::Value::Object(ref object) => object,
+        _ => return
+    };
+    let mut table = None;
+    for (key, item) in object {
+        let is_table = item.is_object()
+            || item.as_array().map(|a| !a.is_empty() && a.iter().all(|i| i.is_object())).unwrap_or(false);
+        if is_table {
+            table = table.or(Some(key));
+        } else if let Some(table) = table {
+            warnings.push(lossy(&join_pointer(pointer, key), format!("TOML writes this before the table `{}`", table)));
+        }
+        let pointer = join_pointer(pointer, key);
+        match *item {
+            serde_json::Value::Array(ref items) => {
+                for (i, item) in items.iter().enumerate() {
+                    table_order_warnings(item, &join_pointer(&pointer, i), warnings);
+                }
+            },
+            _ => table_order_warnings(item, &pointer, warnings)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_output() {
+        let cfg = config! { user bob { mode o; } };
+        assert_eq!(to_string(&cfg).unwrap(), "name = \"\"\nparams = []\n\n[[children]]\nname = \"user\"\nparams = [\"bob\"]\n\n\
+            [[children.children]]\nname = \"mode\"\nparams = [\"o\"]\nchildren = []\n");
+        assert_eq!(from_str(&to_string(&cfg).unwrap()).unwrap(), cfg);
+    }
+
+    #[test]
+    fn test_idiomatic_mapping() {
+        let cfg = config! {
+            log debug;
+            server a { channel x; channel y z; }
+            server b { nick bot; }
+        };
+        let converted = to_idiomatic_string(&cfg).unwrap();
+        assert!(converted.warnings.is_empty());
+        assert_eq!(converted.value, "log = \"debug\"\n\n[[server]]\n\"@params\" = [\"a\"]\nchannel = [[\"x\"], [\"y\", \"z\"]]\n\n\
+            [[server]]\n\"@params\" = [\"b\"]\nnick = \"bot\"\n");
+        assert_eq!(from_idiomatic_str(&converted.value).unwrap().value, cfg);
+    }
+
+    #[test]
+    fn test_lossy_conversions() {
+        let converted = to_idiomatic_string(&config! { server { nick bot; } log debug; }).unwrap();
+        assert_eq!(converted.warnings[0].to_string(), "warning: /log: TOML writes this before the table `server`");
+
+        let converted = from_idiomatic_str("zone = \"b\"\nstarted = 1979-05-27\n[db]\nport = 5432\n").unwrap();
+        assert_eq!(converted.value, config! { zone b; started "1979-05-27"; db { port 5432; } });
+        let warnings: Vec<String> = converted.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "warning: /started: a date becomes a string",
+            "warning: /db/port: a number becomes a string"
+        ]);
+    }
+}
@@ -0,0 +1,132 @@
+//! YAML input and output, see the `convert` module for the mappings
+//!
+//! YAML values are read like JSON values. Tags are dropped, and numeric and boolean
+//! keys become strings, with a warning each.
+
+use serde_json::{self, Map};
+use serde_yaml::{self, Value};
+use config::ConfigBlock;
+use diagnostic::Diagnostic;
+use super::{join_pointer, json, lossy, ConvertError, Converted};
+
+/// Returns the YAML text of a block
+pub fn to_string(cfg: &ConfigBlock) -> Result<String, serde_yaml::Error> {
+    serde_yaml::to_string(&json::to_value(cfg))
+}
+
+/// Reads a block from YAML text in the lossless mapping
+pub fn from_str(text: &str) -> Result<ConfigBlock, ConvertError> {
+    let value = to_json(&parse(text)?, "", &mut vec![])?;
+    json::from_value(&value)
+}
+
+/// Returns the YAML text of a block in the idiomatic mapping
+pub fn to_idiomatic_string(cfg: &ConfigBlock) -> Result<Converted<String>, serde_yaml::Error> {
+    let converted = json::to_idiomatic_value(cfg);
+    Ok(Converted {
+        value: serde_yaml::to_string(&converted.value)?,
+        warnings: converted.warnings
+    })
+}
+
+/// Reads the root block from YAML text in the idiomatic mapping
+pub fn from_idiomatic_str(text: &str) -> Result<Converted<ConfigBlock>, ConvertError> {
+    let mut warnings = vec![];
+    let value = to_json(&parse(text)?, "", &mut warnings)?;
+    let mut converted = json::from_idiomatic_value(&value)?;
+    warnings.append(&mut converted.warnings);
+    converted.warnings = warnings;
+    Ok(converted)
+}
+
+fn parse(text: &str) -> Result<Value, ConvertError> {
+    serde_yaml::from_str(text).map_err(|e| ConvertError::new("", format!("invalid YAML: {}", e)))
+}
+
+fn to_json(value: &Value, pointer: &str, warnings: &mut Vec<Diagnostic>) -> Result<serde_json::Value, ConvertError> {
+    Ok(match *value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(b),
+        Value::Number(ref n) => {
+            let number = if let Some(i) = n.as_i64() {
+                Some(serde_json::Number::from(i))
+            } else if let Some(u) = n.as_u64() {
+                Some(serde_json::Number::from(u))
+            } else {
+                n.as_f64().and_then(serde_json::Number::from_f64)
+            };
+            match number {
+                Some(number) => serde_json::Value::Number(number),
+                None => {
+                    warnings.push(lossy(pointer, format!("`{}` becomes a string", n)));
+                    serde_json::Value::String(n.to_string())
+                }
+            }
+        },
+        Value::String(ref s) => serde_json::Value::String(s.clone()),
+        Value::Sequence(ref items) => {
+            let mut array = vec![];
+            for (i, item) in items.iter().enumerate() {
+                array.push(to_json(item, &join_pointer(pointer, i), warnings)?);
+            }
+            serde_json::Value::Array(array)
+        },
+        Value::Mapping(ref mapping) => {
+            let mut object = Map::new();
+            for (key, item) in mapping {
+                let key = match *key {
+                    Value::String(ref s) => s.clone(),
+                    Value::Number(ref n) => {
+                        warnings.push(lossy(&join_pointer(pointer, n), "a number key becomes a string"));
+                        n.to_string()
+                    },
+                    Value::Bool(b) => {
+                        warnings.push(lossy(&join_pointer(pointer, b), "a boolean key becomes a string"));
+                        b.to_string()
+                    },
+                    _ => return Err(ConvertError::new(pointer, "keys must be strings, numbers or booleans"))
+                };
+                let item = to_json(item, &join_pointer(pointer, &key), warnings)?;
+                object.insert(key, item);
+            }
+            serde_json::Value::Object(object)
+        },
+        Value::Tagged(ref tagged) => {
+            warnings.push(lossy(pointer, format!("the tag `{}` is dropped", tagged.tag)));
+            to_json(&tagged.value, pointer, warnings)?
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_output() {
+        let cfg = config! { user bob; };
+        assert_eq!(to_string(&cfg).unwrap(), "name: ''\nparams: []\nchildren:\n- name: user\n  params:\n  - bob\n  children: []\n");
+        assert_eq!(from_str(&to_string(&cfg).unwrap()).unwrap(), cfg);
+    }
+
+    #[test]
+    fn test_idiomatic_mapping() {
+        let cfg = config! { server a { channel x; channel y z; nick bot; } };
+        let text = to_idiomatic_string(&cfg).unwrap().value;
+        assert_eq!(text, "server:\n  '@params':\n  - a\n  channel:\n  - - x\n  - - y\n    - z\n  nick: bot\n");
+        assert_eq!(from_idiomatic_str(&text).unwrap().value, cfg);
+    }
+
+    #[test]
+    fn test_lossy_input() {
+        let converted = from_idiomatic_str("server:\n  port: 6667\n  1: !secret x\n  users: [a, b]\n").unwrap();
+        assert_eq!(converted.value, ::parse_string(String::from("server { port 6667; 1 x; users a b; }")).unwrap());
+        let warnings: Vec<String> = converted.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "warning: /server/1: a number key becomes a string",
+            "warning: /server/1: the tag `!secret` is dropped",
+            "warning: /server/port: a number becomes a string"
+        ]);
+        assert_eq!(from_idiomatic_str("[1]: x").unwrap_err().to_string(), "keys must be strings, numbers or booleans");
+    }
+}
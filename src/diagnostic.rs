@@ -0,0 +1,141 @@
+use std::fmt;
+use super::error::Error;
+use super::span::{Position, Span};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning")
+        }
+    }
+}
+
+/// A message about a location in a config file, like a parse error or a failed check
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Identifies the kind of problem, like the name of the lint rule which reported it
+    pub code: Option<String>
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(message: S, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            code: None
+        }
+    }
+
+    pub fn warning<S: Into<String>>(message: S, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+            code: None
+        }
+    }
+
+    /// Sets the code, which is shown in brackets after the severity
+    pub fn with_code<S: Into<String>>(mut self, code: S) -> Diagnostic {
+        self.code = Some(code.into());
+        self
+    }
+
+    fn heading(&self) -> String {
+        match self.code {
+            Some(ref code) => format!("{}[{}]: {}", self.severity, code, self.message),
+            None => format!("{}: {}", self.severity, self.message)
+        }
+    }
+
+    /// Renders the diagnostic with the affected source line, in the style of rustc
+    ///
+    /// ```text
+    /// error: unexpected `}`, expected ; or {
+    ///  --> config.cfg:3:14
+    ///   |
+    /// 3 |     nick foo }
+    ///   |              ^
+    /// ```
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let mut out = format!("{}\n", self.heading());
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                out.push_str(&format!(" --> {}\n", file_name));
+                return out;
+            }
+        };
+        let start = span.start;
+        out.push_str(&format!(" --> {}:{}:{}\n", file_name, start.line, start.col));
+        let line = match source.lines().nth((start.line as usize).saturating_sub(1)) {
+            Some(line) => line,
+            None => return out
+        };
+        let number = start.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let col = (start.col as usize).max(1);
+        let width = if span.end.line == start.line && span.end.col >= start.col {
+            (span.end.col - start.col) as usize + 1
+        } else {
+            line.chars().count().saturating_sub(col - 1).max(1)
+        };
+        let indent: String = line.chars().take(col - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", number, line));
+        out.push_str(&format!("{} | {}{}\n", gutter, indent, "^".repeat(width)));
+        out
+    }
+}
+
+impl <'a> From<&'a Error> for Diagnostic {
+    fn from(e: &'a Error) -> Diagnostic {
+        let pos = e.position();
+        let message = match e.expected() {
+            Some(expected) => format!("{}, expected {}", e.error_type(), expected),
+            None => e.error_type().to_string()
+        };
+        let span = if pos == Position::default() { None } else { Some(Span::new(pos, pos)) };
+        Diagnostic::error(message, span)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}: {}", span.start, self.heading()),
+            None => write!(f, "{}", self.heading())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_the_source_line() {
+        let span = Span::new(Position::new(2, 5), Position::new(2, 8));
+        let d = Diagnostic::error("unknown option `nick`", Some(span));
+        assert_eq!(
+            d.render("bot.cfg", "server a {\n    nick Bot;\n}\n"),
+            "error: unknown option `nick`\n --> bot.cfg:2:5\n  |\n2 |     nick Bot;\n  |     ^^^^\n");
+    }
+
+    #[test]
+    fn converts_parse_errors() {
+        let err = ::parse_string(String::from("a {\n  b )\n}")).unwrap_err();
+        assert_eq!(Diagnostic::from(&err).to_string(), "2:5: error: unexpected `)`, expected ; or {");
+    }
+}
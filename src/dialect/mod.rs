@@ -0,0 +1,136 @@
+//! Parsers for the config files of programs with a similar syntax
+//!
+//! Each dialect turns its input into the tokens of this format and hands them to the
+//! regular parser. The resulting trees have spans, and the limits of `ParseOptions`
+//! apply. Comment styles and strictness are fixed by the dialect.
+//!
+//! ```
+//! use config_parser::ParseOptions;
+//! use config_parser::dialect::Dialect;
+//!
+//! let conf = "location ~ \\.php$ { fastcgi_pass unix:/run/php.sock; }";
+//! let cfg = Dialect::Nginx.parse_str(conf, &ParseOptions::default()).unwrap();
+//! assert_eq!(cfg.inner()[0].params(), &["~", "\\.php$"]);
+//! ```
+
+use std::fmt;
+use std::error;
+use std::iter::Peekable;
+use std::str::FromStr;
+use super::config::ConfigBlock;
+use super::error::{CodePos, Error, ErrorType, Result};
+use super::options::ParseOptions;
+
+pub mod nginx;
+pub mod pulseaudio;
+
+/// A config file syntax
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Dialect {
+    /// The syntax of this crate
+    Native,
+    /// nginx.conf, see the `nginx` module
+    Nginx,
+    /// PulseAudio `.pa` scripts, see the `pulseaudio` module
+    PulseAudio
+}
+
+/// A dialect name `FromStr` does not know
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UnknownDialect {
+    name: String
+}
+
+impl Dialect {
+    /// Parses a document written in this dialect
+    pub fn parse_str(self, text: &str, options: &ParseOptions) -> Result<ConfigBlock> {
+        match self {
+            Dialect::Native => super::parse_string_with(text.to_owned(), options),
+            Dialect::Nginx => nginx::parse_with(text.chars(), options),
+            Dialect::PulseAudio => pulseaudio::parse_with(text.chars(), options)
+        }
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = UnknownDialect;
+
+    /// Accepts `native`, `nginx` and `pulseaudio`
+    fn from_str(s: &str) -> ::std::result::Result<Dialect, UnknownDialect> {
+        match s {
+            "native" => Ok(Dialect::Native),
+            "nginx" => Ok(Dialect::Nginx),
+            "pulseaudio" => Ok(Dialect::PulseAudio),
+            _ => Err(UnknownDialect { name: s.to_owned() })
+        }
+    }
+}
+
+impl fmt::Display for UnknownDialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown dialect `{}`, expected native, nginx or pulseaudio", self.name)
+    }
+}
+
+impl error::Error for UnknownDialect {}
+
+/// Reads chars while tracking the position and the input size limit
+struct Cursor<I: Iterator<Item=char>> {
+    chars: Peekable<I>,
+    line: u32,
+    col: u16,
+    offset: usize,
+    max_input_size: Option<usize>,
+    max_literal_length: Option<usize>
+}
+
+impl <I> CodePos for Cursor<I> where I: Iterator<Item=char> {
+    fn location(&self) -> (u32, u16) {
+        (self.line, self.col)
+    }
+}
+
+impl <I> Cursor<I> where I: Iterator<Item=char> {
+    fn new(chars: I, options: &ParseOptions) -> Cursor<I> {
+        Cursor {
+            chars: chars.peekable(),
+            line: 1,
+            col: 0,
+            offset: 0,
+            max_input_size: options.max_input_size,
+            max_literal_length: options.max_literal_length
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Result<Option<char>> {
+        let c = match self.chars.next() {
+            Some(c) => c,
+            None => return Ok(None)
+        };
+        self.offset += c.len_utf8();
+        if let Some(max) = self.max_input_size {
+            if self.offset > max {
+                return Err(Error::from_state(self, ErrorType::InputTooLarge(max), None));
+            }
+        }
+        if c == '\n' {
+            self.line += 1;
+            self.col = 0;
+        } else {
+            self.col = self.col.saturating_add(1);
+        }
+        Ok(Some(c))
+    }
+
+    /// Fails if a literal has grown beyond the limit
+    fn check_length(&self, literal: &str) -> Result<()> {
+        match self.max_literal_length {
+            Some(max) if literal.len() > max => Err(Error::from_state(self, ErrorType::LiteralTooLong(max), None)),
+            _ => Ok(())
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! nginx.conf files
+//!
+//! Tokens are split like nginx does it: words end at whitespace, `;`, `{` and `}`, so
+//! parentheses in `if ($host = a)` and regular expressions in `location ~ ^/(a|b)/` are
+//! part of the words. `${name}` stays within its word, `'` quotes like `"`, and `#`
+//! starts a comment at the beginning of a word. A backslash only escapes quotes,
+//! backslashes, `t`, `r` and `n`, so regular expressions keep their backslashes.
+//! Semicolons are required and the parameters are kept as written, `$variables`
+//! included.
+//!
+//! `include` stays an option when parsing text. `parse_file` replaces it with the
+//! contents of the included files instead.
+
+use std::path::Path;
+use config::ConfigBlock;
+use error::{CodePos, Error, ErrorType, Result};
+use include;
+use lexer::{Token, TokenType};
+use options::ParseOptions;
+use parser;
+use super::{Cursor, Dialect};
+
+pub use include::LoadError;
+
+/// Parses an nginx config from chars. Only the limits of the options are used
+pub fn parse_with<I>(chars: I, options: &ParseOptions) -> Result<ConfigBlock> where I: Iterator<Item=char> {
+    let options = options.limits_only().trailing_semicolon(false);
+    let tokens = Tokens { cursor: Cursor::new(chars, &options), done: false };
+    parser::run_with(Box::new(tokens), &options)
+}
+
+/// Parses an nginx config
+pub fn parse_str(text: &str) -> Result<ConfigBlock> {
+    parse_with(text.chars(), &ParseOptions::default())
+}
+
+/// Parses an nginx config file and the files it includes
+///
+/// Like in nginx, relative include paths are resolved against the directory of the
+/// main file. `*`, `?` and `[...]` are supported in the last component of the path, with
+/// matches included in alphabetical order. Positions in the resulting tree refer to the
+/// file each option was read from.
+pub fn parse_file<P: AsRef<Path>>(path: P, options: &ParseOptions) -> ::std::result::Result<ConfigBlock, LoadError> {
+    include::load(path, Dialect::Nginx, options).map(|loaded| loaded.config)
+}
+
+struct Tokens<I: Iterator<Item=char>> {
+    cursor: Cursor<I>,
+    done: bool
+}
+
+impl <I> Iterator for Tokens<I> where I: Iterator<Item=char> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Result<Token>> {
+        if self.done {
+            return None;
+        }
+        let token = self.token();
+        if token.is_err() {
+            self.done = true;
+        }
+        token.transpose()
+    }
+}
+
+impl <I> Tokens<I> where I: Iterator<Item=char> {
+    fn token(&mut self) -> Result<Option<Token>> {
+        loop {
+            let c = match self.cursor.next()? {
+                Some(c) => c,
+                None => return Ok(None)
+            };
+            let (line, col) = self.cursor.location();
+            let ty = match c {
+                c if c.is_whitespace() => continue,
+                '#' => {
+                    while self.cursor.peek().map(|c| c != '\n').unwrap_or(false) {
+                        self.cursor.next()?;
+                    }
+                    continue;
+                },
+                '{' => TokenType::OpenBrace,
+                '}' => TokenType::CloseBrace,
+                ';' => TokenType::Semicolon,
+                '"' | '\'' => TokenType::StringLiteral(self.quoted(c)?),
+                c => TokenType::RawLiteral(self.word(c)?)
+            };
+            return Ok(Some(Token::new(line, col, ty)));
+        }
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            match self.cursor.next()? {
+                Some(c) if c == quote => break,
+                Some('\\') => {
+                    let c = self.cursor.next()?;
+                    self.escape(c, &mut value);
+                },
+                Some(c) => value.push(c),
+                None => return Err(Error::from_state(&self.cursor, ErrorType::UnexpectedEOF, Some("closing quote")))
+            }
+            self.cursor.check_length(&value)?;
+        }
+        match self.cursor.peek() {
+            Some(c) if !c.is_whitespace() && c != ';' && c != '{' && c != '}' => {
+                let (line, col) = self.cursor.location();
+                let token = Token::new(line, col + 1, TokenType::RawLiteral(c.to_string()));
+                Err(Error::new(line, col + 1, ErrorType::Unexpected(token), Some("whitespace")))
+            },
+            _ => Ok(value)
+        }
+    }
+
+    fn word(&mut self, first: char) -> Result<String> {
+        let mut value = String::new();
+        let mut c = first;
+        // Inside `${...}`
+        let mut in_variable = false;
+        loop {
+            match c {
+                '\\' => {
+                    let next = self.cursor.next()?;
+                    self.escape(next, &mut value);
+                },
+                '{' => in_variable = true,
+                '}' => in_variable = false,
+                _ => {}
+            }
+            if c != '\\' {
+                value.push(c);
+            }
+            self.cursor.check_length(&value)?;
+            c = match self.cursor.peek() {
+                Some('{') if value.ends_with('$') => '{',
+                Some('}') if in_variable => '}',
+                Some(c) if c.is_whitespace() || c == ';' || c == '{' || c == '}' => break,
+                Some(c) => c,
+                None => break
+            };
+            self.cursor.next()?;
+        }
+        Ok(value)
+    }
+
+    /// Appends the char following a backslash
+    fn escape(&self, c: Option<char>, value: &mut String) {
+        match c {
+            Some(c @ '"') | Some(c @ '\'') | Some(c @ '\\') => value.push(c),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('n') => value.push('\n'),
+            Some(c) => {
+                value.push('\\');
+                value.push(c);
+            },
+            None => value.push('\\')
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{env, fs};
+    use span::Position;
+
+    #[test]
+    fn test_real_world_syntax() {
+        let cfg = parse_str(r#"
+            # main context
+            user www-data;
+            http {
+                log_format main '$remote_addr - [$time_local] "$request"';
+                server {
+                    listen 443 ssl;
+                    return 301 https://${host}$request_uri;
+                    location ~* ^/(images|js)/.*\.(png|js)$ { expires 30d; }
+                    if ($http_user_agent ~ MSIE) { rewrite ^(.*)$ /msie/$1 break; }
+                    add_header X-Note "a \"quoted\" value";
+                }
+            }
+        "#).unwrap();
+        let server = &cfg.inner()[1].inner()[1];
+        assert_eq!(cfg.inner()[1].inner()[0].params(), &["main", "$remote_addr - [$time_local] \"$request\""]);
+        assert_eq!(server.inner()[1].params(), &["301", "https://${host}$request_uri"]);
+        assert_eq!(server.inner()[2].params(), &["~*", r"^/(images|js)/.*\.(png|js)$"]);
+        assert_eq!(server.inner()[3].params(), &["($http_user_agent", "~", "MSIE)"]);
+        assert_eq!(server.inner()[3].inner()[0].params(), &["^(.*)$", "/msie/$1", "break"]);
+        assert_eq!(server.inner()[4].params(), &["X-Note", "a \"quoted\" value"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_str("a b").unwrap_err().to_string(), "1:3: unexpected end of file, expected }");
+        assert_eq!(parse_str("a \"b\"c;").unwrap_err().to_string(), "1:6: unexpected `c`, expected whitespace");
+        let options = ParseOptions::new().max_literal_length(Some(3));
+        assert!(parse_with("abcd;".chars(), &options).is_err());
+    }
+
+    #[test]
+    fn test_ignoring_native_features() {
+        let options = ParseOptions::new().templates(true).profiles(&["p"]).secrets(true).max_depth(Some(1));
+        let cfg = parse_with("template t; profile p { a b; } c extends t;".chars(), &options).unwrap();
+        assert_eq!(cfg.inner().len(), 3);
+        assert!(parse_with("a { b { c; } }".chars(), &options).is_err());
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = env::temp_dir().join(format!("config-parser-nginx-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("nginx.conf"), "http {\n    include mime.types;\n    include conf.d/*.conf;\n}\n").unwrap();
+        fs::write(dir.join("mime.types"), "types { text/html html; }").unwrap();
+        fs::write(dir.join("conf.d/b.conf"), "server { listen 81; }").unwrap();
+        fs::write(dir.join("conf.d/a.conf"), "server { listen 80; }").unwrap();
+        fs::write(dir.join("conf.d/a.conf.bak"), "broken").unwrap();
+
+        let cfg = parse_file(dir.join("nginx.conf"), &ParseOptions::default()).unwrap();
+        let names: Vec<String> = cfg.inner()[0].inner().iter()
+            .map(|b| format!("{} {}", b.name(), b.inner()[0].params().join(" ")))
+            .collect();
+        assert_eq!(names, vec!["types html", "server 80", "server 81"]);
+
+        fs::write(dir.join("conf.d/c.conf"), "include missing.conf;").unwrap();
+        let err = parse_file(dir.join("nginx.conf"), &ParseOptions::default()).unwrap_err();
+        assert_eq!(err.file, dir.join("conf.d/c.conf"));
+        assert_eq!(err.error.position(), Position::new(1, 1));
+
+        fs::write(dir.join("conf.d/c.conf"), "include conf.d/c.conf;").unwrap();
+        let err = parse_file(dir.join("nginx.conf"), &ParseOptions::default()).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}:1:1: read error: includes nested deeper than 32 files", dir.join("conf.d/c.conf").display()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! PulseAudio `.pa` scripts
+//!
+//! Every line is a command, which becomes an option named like the command with the
+//! remaining words of the line as parameters. Words are split at whitespace outside of
+//! quotes and kept as written, so module arguments like
+//! `sink_properties="device.description='A B'"` stay one parameter and can be passed on
+//! unchanged. Lines starting with `#` are comments.
+//!
+//! `.ifexists FILE`, `.else` and `.endif` turn into blocks named `.ifexists` and `.else`,
+//! while `.include`, `.fail` and `.nofail` are options like any other command:
+//!
+//! ```text
+//! .ifexists module-udev-detect.so
+//! load-module module-udev-detect tsched=0
+//! .else
+//! load-module module-detect
+//! .endif
+//! ```
+//!
+//! is read as
+//!
+//! ```text
+//! .ifexists module-udev-detect.so {
+//!     load-module module-udev-detect tsched=0;
+//! }
+//! .else {
+//!     load-module module-detect;
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use config::ConfigBlock;
+use error::{CodePos, Error, ErrorType, Result};
+use lexer::{Token, TokenType};
+use options::ParseOptions;
+use parser;
+use super::Cursor;
+
+/// Parses a script from chars. Only the limits of the options are used
+pub fn parse_with<I>(chars: I, options: &ParseOptions) -> Result<ConfigBlock> where I: Iterator<Item=char> {
+    let options = options.limits_only().trailing_semicolon(false);
+    let tokens = Tokens {
+        cursor: Cursor::new(chars, &options),
+        queue: VecDeque::new(),
+        depth: 0,
+        done: false
+    };
+    parser::run_with(Box::new(tokens), &options)
+}
+
+/// Parses a script
+pub fn parse_str(text: &str) -> Result<ConfigBlock> {
+    parse_with(text.chars(), &ParseOptions::default())
+}
+
+struct Tokens<I: Iterator<Item=char>> {
+    cursor: Cursor<I>,
+    /// The remaining tokens of the current line
+    queue: VecDeque<Token>,
+    /// How many `.ifexists` are open
+    depth: usize,
+    done: bool
+}
+
+impl <I> Iterator for Tokens<I> where I: Iterator<Item=char> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Result<Token>> {
+        loop {
+            if let Some(token) = self.queue.pop_front() {
+                return Some(Ok(token));
+            }
+            if self.done {
+                return None;
+            }
+            match self.line() {
+                Ok(true) => {},
+                Ok(false) => {
+                    self.done = true;
+                    if self.depth > 0 {
+                        return Some(Err(Error::from_state(&self.cursor, ErrorType::UnexpectedEOF, Some(".endif"))));
+                    }
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl <I> Tokens<I> where I: Iterator<Item=char> {
+    /// Queues the tokens of the next line, returning false at the end of the input
+    fn line(&mut self) -> Result<bool> {
+        if self.cursor.peek().is_none() {
+            return Ok(false);
+        }
+        let mut words = vec![];
+        let mut end = self.cursor.location();
+        let mut comment = false;
+        loop {
+            let c = match self.cursor.next()? {
+                Some('\n') | None => break,
+                Some(c) => c
+            };
+            if comment || c.is_whitespace() {
+                continue;
+            }
+            if c == '#' && words.is_empty() {
+                comment = true;
+                continue;
+            }
+            let (line, col) = self.cursor.location();
+            let word = self.word(c)?;
+            words.push(Token::new(line, col, TokenType::RawLiteral(word)));
+            end = self.cursor.location();
+        }
+        let (line, col) = (end.0, end.1 + 1);
+        let command = match words.first() {
+            Some(&Token { token_type: TokenType::RawLiteral(ref command), .. }) => command.clone(),
+            _ => return Ok(true)
+        };
+        match &*command {
+            ".ifexists" => {
+                self.depth += 1;
+                self.queue.extend(words);
+                self.queue.push_back(Token::new(line, col, TokenType::OpenBrace));
+            },
+            ".else" | ".endif" if self.depth > 0 => {
+                let first = words[0].position();
+                if words.len() > 1 {
+                    let extra = words.swap_remove(1);
+                    let position = extra.position();
+                    return Err(Error::new(position.line, position.col, ErrorType::Unexpected(extra), Some("end of line")));
+                }
+                self.queue.push_back(Token::new(first.line, first.col, TokenType::CloseBrace));
+                if command == ".else" {
+                    self.queue.extend(words);
+                    self.queue.push_back(Token::new(line, col, TokenType::OpenBrace));
+                } else {
+                    self.depth -= 1;
+                }
+            },
+            ".else" | ".endif" => {
+                let word = words.swap_remove(0);
+                let position = word.position();
+                return Err(Error::new(position.line, position.col, ErrorType::Unexpected(word), Some(".ifexists before")));
+            },
+            _ => {
+                self.queue.extend(words);
+                self.queue.push_back(Token::new(line, col, TokenType::Semicolon));
+            }
+        }
+        Ok(true)
+    }
+
+    /// Reads a word up to whitespace outside of quotes, keeping quotes and backslashes
+    fn word(&mut self, first: char) -> Result<String> {
+        let mut value = String::new();
+        let mut quote = None;
+        let mut c = first;
+        loop {
+            value.push(c);
+            match (c, quote) {
+                ('\\', _) => match self.cursor.peek() {
+                    Some('\n') | None => {},
+                    Some(_) => {
+                        if let Some(escaped) = self.cursor.next()? {
+                            value.push(escaped);
+                        }
+                    }
+                },
+                ('"', None) | ('\'', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                _ => {}
+            }
+            self.cursor.check_length(&value)?;
+            c = match self.cursor.peek() {
+                Some('\n') | None => break,
+                Some(c) if c.is_whitespace() && quote.is_none() => break,
+                Some(c) => c
+            };
+            self.cursor.next()?;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let cfg = parse_str("#!/usr/bin/pulseaudio -nF\n\
+            .fail\n\
+            load-module module-alsa-sink device=hw:0 sink_properties=\"device.description='My Sink'\"\n\
+            \n\
+            .ifexists module-udev-detect.so\n\
+            load-module module-udev-detect\n\
+            .else\n\
+              ### Use the static hardware detection module\n\
+              load-module module-detect\n\
+            .endif\n\
+            set-default-sink alsa_output\n").unwrap();
+        let expected = ::parse_string(String::from(r#"
+            .fail;
+            load-module module-alsa-sink device=hw:0 "sink_properties=\"device.description='My Sink'\"";
+            .ifexists module-udev-detect.so { load-module module-udev-detect; }
+            .else { load-module module-detect; }
+            set-default-sink alsa_output;
+        "#)).unwrap();
+        assert_eq!(cfg, expected);
+        let span = cfg.inner()[2].span().unwrap();
+        assert_eq!((span.start, span.end), (::Position::new(5, 1), ::Position::new(7, 1)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_str("a\n.endif\n").unwrap_err().to_string(), "2:1: unexpected `.endif`, expected .ifexists before");
+        assert_eq!(parse_str(".ifexists a\nb\n").unwrap_err().to_string(), "3:0: unexpected end of file, expected .endif");
+        assert_eq!(parse_str(".ifexists a\n.endif b\n").unwrap_err().to_string(), "2:8: unexpected `b`, expected end of line");
+    }
+}
@@ -0,0 +1,413 @@
+//! Structural differences between two trees
+//!
+//! Inner options are matched by name and first parameter, in order, so repeated options
+//! like `channel` are compared with their counterparts instead of their neighbours. An
+//! option whose name occurs once on both sides is matched even if its first parameter
+//! changed. Matched options with different parameters are reported as changed, the
+//! differences inside them separately.
+//!
+//! ```
+//! # #[macro_use] extern crate config_parser;
+//! # use config_parser::diff;
+//! # fn main() {
+//! let old = config! { server a { port 6667; channel x; } };
+//! let new = config! { server a { port 6697; channel x; channel y; } };
+//! let changes: Vec<String> = diff::diff(&old, &new).changes().iter().map(|c| c.to_string()).collect();
+//! assert_eq!(changes, vec!["changed server[a]/port[6697]: 6667 -> 6697", "added server[a]/channel[y]"]);
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use super::config::ConfigBlock;
+use super::path::{Path, Segment};
+use super::span::Span;
+use super::writer::quote;
+
+/// The differences between two trees, see `diff`
+#[derive(Debug)]
+pub struct Diff<'a> {
+    old: &'a ConfigBlock,
+    new: &'a ConfigBlock,
+    changes: Vec<Change<'a>>
+}
+
+/// A single difference
+///
+/// Paths of added and changed options select them in the new tree. Removed options are
+/// named like in the old tree, below the path of their parent in the new one.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change<'a> {
+    Added { path: Path, new: &'a ConfigBlock },
+    Removed { path: Path, old: &'a ConfigBlock },
+    /// The parameters differ. Changes of inner options are reported on their own
+    Changed { path: Path, old: &'a ConfigBlock, new: &'a ConfigBlock }
+}
+
+/// How the inner options of two blocks correspond
+enum Pair {
+    Both(usize, usize),
+    Old(usize),
+    New(usize)
+}
+
+/// Compares two trees
+pub fn diff<'a>(old: &'a ConfigBlock, new: &'a ConfigBlock) -> Diff<'a> {
+    let mut changes = vec![];
+    collect(old, new, &Path::root(), &mut changes);
+    Diff { old, new, changes }
+}
+
+impl <'a> Diff<'a> {
+    /// Returns the changes in document order
+    pub fn changes(&self) -> &[Change<'a>] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the old tree
+    pub fn before(&self) -> &'a ConfigBlock {
+        self.old
+    }
+
+    /// Returns the new tree
+    pub fn after(&self) -> &'a ConfigBlock {
+        self.new
+    }
+
+    /// Renders the changes like a unified diff, with a hunk for each changed option of
+    /// the root block. Unchanged options are left out, except for the blocks enclosing
+    /// a change. Returns an empty string if the trees are equal.
+    pub fn render(&self, old_name: &str, new_name: &str) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+        render_inner(self.old, self.new, 0, &Path::root(), &mut out);
+        out
+    }
+}
+
+impl <'a> Change<'a> {
+    pub fn path(&self) -> &Path {
+        match *self {
+            Change::Added { ref path, .. } | Change::Removed { ref path, .. } | Change::Changed { ref path, .. } => path
+        }
+    }
+
+    /// Returns the option in the old tree, unless it was added
+    pub fn before(&self) -> Option<&'a ConfigBlock> {
+        match *self {
+            Change::Added { .. } => None,
+            Change::Removed { old, .. } | Change::Changed { old, .. } => Some(old)
+        }
+    }
+
+    /// Returns the option in the new tree, unless it was removed
+    pub fn after(&self) -> Option<&'a ConfigBlock> {
+        match *self {
+            Change::Removed { .. } => None,
+            Change::Added { new, .. } | Change::Changed { new, .. } => Some(new)
+        }
+    }
+
+    /// Returns the span of the option in the new tree, or in the old one if it was removed
+    pub fn span(&self) -> Option<Span> {
+        self.after().or_else(|| self.before()).and_then(|b| b.span())
+    }
+}
+
+impl <'a> fmt::Display for Change<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added { ref path, .. } => write!(f, "added {}", path),
+            Change::Removed { ref path, .. } => write!(f, "removed {}", path),
+            Change::Changed { ref path, old, new } => {
+                write!(f, "changed {}: {} -> {}", path, params(old), params(new))
+            }
+        }
+    }
+}
+
+fn params(block: &ConfigBlock) -> String {
+    let params: Vec<_> = block.params().iter().map(|p| quote(p)).collect();
+    params.join(" ")
+}
+
+fn collect<'a>(old: &'a ConfigBlock, new: &'a ConfigBlock, path: &Path, changes: &mut Vec<Change<'a>>) {
+    let (old_segments, new_segments) = (segments(old), segments(new));
+    for pair in pair(old, new) {
+        match pair {
+            Pair::Both(i, j) => {
+                let (o, n) = (&old.inner()[i], &new.inner()[j]);
+                let path = path.join(new_segments[j].clone());
+                if o.params() != n.params() {
+                    changes.push(Change::Changed { path: path.clone(), old: o, new: n });
+                }
+                collect(o, n, &path, changes);
+            },
+            Pair::Old(i) => changes.push(Change::Removed { path: path.join(old_segments[i].clone()), old: &old.inner()[i] }),
+            Pair::New(j) => changes.push(Change::Added { path: path.join(new_segments[j].clone()), new: &new.inner()[j] })
+        }
+    }
+}
+
+/// The name and first parameter of an option
+fn key(block: &ConfigBlock) -> (&str, Option<&str>) {
+    (block.name(), block.get_opt(0))
+}
+
+/// Returns how often each name occurs among the blocks and where it occurs last
+fn names(blocks: &[ConfigBlock]) -> HashMap<&str, (usize, usize)> {
+    let mut names = HashMap::new();
+    for (i, b) in blocks.iter().enumerate() {
+        let entry = names.entry(b.name()).or_insert((0, i));
+        *entry = (entry.0 + 1, i);
+    }
+    names
+}
+
+/// Matches the inner options and merges both lists, keeping the order of each
+fn pair(old: &ConfigBlock, new: &ConfigBlock) -> Vec<Pair> {
+    let (old, new) = (old.inner(), new.inner());
+    let mut used = vec![false; old.len()];
+    let mut matches: Vec<Option<usize>> = vec![None; new.len()];
+    let mut unused: HashMap<(&str, Option<&str>), VecDeque<usize>> = HashMap::new();
+    for (i, b) in old.iter().enumerate() {
+        unused.entry(key(b)).or_default().push_back(i);
+    }
+    for (j, b) in new.iter().enumerate() {
+        if let Some(i) = unused.get_mut(&key(b)).and_then(VecDeque::pop_front) {
+            used[i] = true;
+            matches[j] = Some(i);
+        }
+    }
+    // Options occurring once on both sides are the same option, whatever their parameters
+    let (old_names, new_names) = (names(old), names(new));
+    for (j, b) in new.iter().enumerate() {
+        if matches[j].is_some() || new_names[b.name()].0 != 1 {
+            continue;
+        }
+        if let Some(&(1, i)) = old_names.get(b.name()) {
+            if !used[i] {
+                used[i] = true;
+                matches[j] = Some(i);
+            }
+        }
+    }
+
+    let mut pairs = vec![];
+    let mut next_old = 0;
+    for (j, matched) in matches.into_iter().enumerate() {
+        match matched {
+            Some(i) => {
+                while next_old < i {
+                    if !used[next_old] {
+                        pairs.push(Pair::Old(next_old));
+                    }
+                    next_old += 1;
+                }
+                next_old = next_old.max(i + 1);
+                pairs.push(Pair::Both(i, j));
+            },
+            None => pairs.push(Pair::New(j))
+        }
+    }
+    pairs.extend((next_old..old.len()).filter(|&i| !used[i]).map(Pair::Old));
+    pairs
+}
+
+/// Returns the segments selecting the inner options of `parent`, with an index where
+/// other options share name and first parameter
+pub(crate) fn segments(parent: &ConfigBlock) -> Vec<Segment> {
+    let inner = parent.inner();
+    let names = names(inner);
+    let mut keys = HashMap::new();
+    for b in inner {
+        *keys.entry(key(b)).or_insert(0) += 1;
+    }
+    let (mut seen_names, mut seen_keys) = (HashMap::new(), HashMap::new());
+    inner.iter().map(|b| {
+        let name_index = count(&mut seen_names, b.name());
+        let key_index = count(&mut seen_keys, key(b));
+        let mut segment = Segment::new(b.name());
+        // Without parameters, the segment matches all options of the name
+        let (matching, index) = match b.get_opt(0) {
+            Some(param) => {
+                segment = segment.param(param);
+                (keys[&key(b)], key_index)
+            },
+            None => (names[b.name()].0, name_index)
+        };
+        if matching > 1 {
+            segment = segment.index(index);
+        }
+        segment
+    }).collect()
+}
+
+/// Counts an occurrence of `key`, returning how often it occurred before
+fn count<K: Hash + Eq>(seen: &mut HashMap<K, usize>, key: K) -> usize {
+    let n = seen.entry(key).or_insert(0);
+    *n += 1;
+    *n - 1
+}
+
+fn render_inner(old: &ConfigBlock, new: &ConfigBlock, depth: usize, path: &Path, out: &mut String) {
+    let (old_segments, new_segments) = (segments(old), segments(new));
+    for pair in pair(old, new) {
+        if depth == 0 {
+            let segment = match pair {
+                Pair::Both(i, j) if old.inner()[i] == new.inner()[j] => continue,
+                Pair::Both(_, j) | Pair::New(j) => &new_segments[j],
+                Pair::Old(i) => &old_segments[i]
+            };
+            out.push_str(&format!("@@ {} @@\n", path.join(segment.clone())));
+        }
+        match pair {
+            Pair::Both(i, j) => {
+                let (o, n) = (&old.inner()[i], &new.inner()[j]);
+                if o == n {
+                    continue;
+                }
+                let replaced = o.inner().is_empty() || n.inner().is_empty()
+                    || (o.params() != n.params() && o.inner() == n.inner());
+                if replaced {
+                    render_block(o, '-', depth, out);
+                    render_block(n, '+', depth, out);
+                    continue;
+                }
+                if o.params() != n.params() {
+                    render_line(&format!("{} {{", header(o)), '-', depth, out);
+                    render_line(&format!("{} {{", header(n)), '+', depth, out);
+                } else {
+                    render_line(&format!("{} {{", header(n)), ' ', depth, out);
+                }
+                render_inner(o, n, depth + 1, &path.join(new_segments[j].clone()), out);
+                render_line("}", ' ', depth, out);
+            },
+            Pair::Old(i) => render_block(&old.inner()[i], '-', depth, out),
+            Pair::New(j) => render_block(&new.inner()[j], '+', depth, out)
+        }
+    }
+}
+
+/// Returns the name and parameters as written before the braces of a block
+fn header(block: &ConfigBlock) -> String {
+    let mut header = quote(block.name()).into_owned();
+    for param in block.params() {
+        header.push(' ');
+        header.push_str(&quote(param));
+    }
+    header
+}
+
+fn render_block(block: &ConfigBlock, marker: char, depth: usize, out: &mut String) {
+    for line in block.to_string().lines() {
+        render_line(line, marker, depth, out);
+    }
+}
+
+fn render_line(line: &str, marker: char, depth: usize, out: &mut String) {
+    out.push(marker);
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use span::Position;
+
+    fn changes(old: &ConfigBlock, new: &ConfigBlock) -> Vec<String> {
+        diff(old, new).changes().iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_matching() {
+        let old = config! {
+            server a { channel x; channel y; nick bot; }
+            server b { channel z; }
+        };
+        let new = config! {
+            server b { channel z; }
+            server a { channel y; channel w; nick bot2; }
+        };
+        assert_eq!(changes(&old, &new), vec![
+            "removed server[a]/channel[x]",
+            "added server[a]/channel[w]",
+            "changed server[a]/nick[bot2]: bot -> bot2"
+        ]);
+        assert!(diff(&old, &old).is_empty());
+
+        let old = config! { user a; user a x; };
+        let new = config! { user a y; user a x; };
+        assert_eq!(changes(&old, &new), vec!["changed user[a]#0: a -> a y"]);
+    }
+
+    #[test]
+    fn test_segments() {
+        let cfg = config! { user; user a; user a; user b; nick; };
+        let segments = segments(&cfg);
+        let shown: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+        assert_eq!(shown, vec!["user#0", "user[a]#0", "user[a]#1", "user[b]", "nick"]);
+        for (i, segment) in segments.into_iter().enumerate() {
+            assert_eq!(segment.indices(&cfg), vec![i]);
+        }
+    }
+
+    #[test]
+    fn test_many_siblings() {
+        let mut old = ConfigBlock::new(String::new(), vec![], vec![]);
+        for i in 0..5000 {
+            old.add_block(ConfigBlock::new(String::from("channel"), vec![format!("c{}", i)], vec![]));
+        }
+        let mut new = old.clone();
+        new.inner_mut().reverse();
+        new.inner_mut()[0].push_param("key");
+        assert_eq!(changes(&old, &new), vec!["changed channel[c4999]: c4999 -> c4999 key"]);
+    }
+
+    #[test]
+    fn test_spans() {
+        let old = ::parse_string(String::from("server a {\n    port 1;\n}\n")).unwrap();
+        let new = ::parse_string(String::from("server a {\n    tls;\n    port 2;\n}\n")).unwrap();
+        let diff = diff(&old, &new);
+        let spans: Vec<Position> = diff.changes().iter().map(|c| c.span().unwrap().start).collect();
+        assert_eq!(spans, vec![Position::new(2, 5), Position::new(3, 5)]);
+        assert_eq!(diff.changes()[1].before().unwrap().span().unwrap().start, Position::new(2, 5));
+    }
+
+    #[test]
+    fn test_render() {
+        let old = config! {
+            nick bot;
+            server a { port 1; channel x { key k; } }
+        };
+        let new = config! {
+            nick bot;
+            server a { port 2; channel x { key k; } channel y; }
+            user q;
+        };
+        assert_eq!(diff(&old, &new).render("old.cfg", "new.cfg"), "\
+--- old.cfg
++++ new.cfg
+@@ server[a] @@
+ server a {
+-    port 1;
++    port 2;
++    channel y;
+ }
+@@ user[q] @@
++user q;
+");
+        assert_eq!(diff(&old, &old).render("a", "b"), "");
+    }
+}
@@ -1,4 +1,6 @@
 use super::lexer::Token;
+use super::span::Position;
+use std::{error, fmt, io};
 use std::result;
 
 pub type Result<T> = result::Result<T, Error>;
@@ -7,7 +9,54 @@ pub type Result<T> = result::Result<T, Error>;
 pub enum ErrorType {
     UnexpectedEOF,
     Unexpected(Token),
-    MissingParameter(String)
+    MissingParameter(String),
+    /// Reading the input failed
+    Io(io::ErrorKind, String),
+    /// The input is not valid UTF-8
+    InvalidUtf8,
+    /// Blocks are nested deeper than the configured limit
+    NestingTooDeep(usize),
+    /// The input is longer than the configured number of bytes
+    InputTooLarge(usize),
+    /// The document contains more blocks and options than the configured limit
+    TooManyBlocks(usize),
+    /// An option has more parameters than the configured limit
+    TooManyParameters(usize),
+    /// A literal is longer than the configured number of bytes
+    LiteralTooLong(usize),
+    /// A template could not be resolved. The error is reported at the option using the
+    /// template; the position of the template is given if it is involved
+    Template(String, Option<Position>),
+    /// An `if` or `else` option is malformed
+    Condition(String),
+    /// A profile is malformed. The position of an earlier definition is given if it is
+    /// involved
+    Profile(String, Option<Position>),
+    /// A secret reference could not be resolved
+    Secret(String)
+}
+
+impl fmt::Display for ErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorType::UnexpectedEOF => write!(f, "unexpected end of file"),
+            ErrorType::Unexpected(ref t) => write!(f, "unexpected {}", t.token_type),
+            ErrorType::MissingParameter(ref p) => write!(f, "missing parameter {}", p),
+            ErrorType::Io(_, ref msg) => write!(f, "read error: {}", msg),
+            ErrorType::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ErrorType::NestingTooDeep(max) => write!(f, "blocks nested deeper than {} levels", max),
+            ErrorType::InputTooLarge(max) => write!(f, "input larger than {} bytes", max),
+            ErrorType::TooManyBlocks(max) => write!(f, "more than {} options", max),
+            ErrorType::TooManyParameters(max) => write!(f, "more than {} parameters", max),
+            ErrorType::LiteralTooLong(max) => write!(f, "literal longer than {} bytes", max),
+            ErrorType::Template(ref msg, Some(pos)) => write!(f, "{}, see {}", msg, pos),
+            ErrorType::Template(ref msg, None) => write!(f, "{}", msg),
+            ErrorType::Condition(ref msg) => write!(f, "{}", msg),
+            ErrorType::Profile(ref msg, Some(pos)) => write!(f, "{}, see {}", msg, pos),
+            ErrorType::Profile(ref msg, None) => write!(f, "{}", msg),
+            ErrorType::Secret(ref msg) => write!(f, "{}", msg)
+        }
+    }
 }
 
 pub trait CodePos {
@@ -26,9 +75,9 @@ impl Error {
     pub fn new(line: u32, col: u16, etype: ErrorType, expected: Option<&'static str>) -> Error {
         Error {
             error_type: etype,
-            line: line,
-            col: col,
-            expected: expected
+            line,
+            col,
+            expected
         }
     }
 
@@ -36,4 +85,30 @@ impl Error {
         let p = pos.location();
         Error::new(p.0, p.1, etype, expected)
     }
+
+    pub fn error_type(&self) -> &ErrorType {
+        &self.error_type
+    }
+
+    /// Returns where the error occurred
+    pub fn position(&self) -> Position {
+        Position::new(self.line, self.col)
+    }
+
+    /// Returns a description of what the parser expected instead, if known
+    pub fn expected(&self) -> Option<&'static str> {
+        self.expected
+    }
 }
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.error_type)?;
+        match self.expected {
+            Some(e) => write!(f, ", expected {}", e),
+            None => Ok(())
+        }
+    }
+}
+
+impl error::Error for Error {}
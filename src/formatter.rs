@@ -0,0 +1,501 @@
+//! Rewrites config files in a canonical layout while keeping their comments
+//!
+//! Unlike the `Display` implementation of `ConfigBlock`, which only knows the parsed
+//! tree, the formatter works on the token stream. Comments stay where they were, at the
+//! end of a line or on lines of their own, and blank lines between options are kept up
+//! to a limit. Everything else is normalized: indentation, spacing, quoting of
+//! parameters and stray semicolons, like the one in `};`.
+//!
+//! ```
+//! use config_parser::formatter::{self, Style};
+//!
+//! let source = "server  freenode{\n\tconnect \"irc.freenode.net\" 6697; # TLS\n};";
+//! let formatted = formatter::format(source, &Style::new()).unwrap();
+//! assert_eq!(formatted, "server freenode {\n    connect irc.freenode.net 6697; # TLS\n}\n");
+//! ```
+
+use std::iter::Peekable;
+use std::{mem, vec};
+use super::error::Result;
+use super::lexer::{Lexer, TokenType};
+use super::options::ParseOptions;
+use super::parser::{self, Events};
+use super::secret;
+use super::span::Span;
+use super::writer::quote;
+
+/// Where the opening brace of a block goes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BraceStyle {
+    /// `server a {`
+    SameLine,
+    /// The brace on a line of its own, indented like the option
+    NextLine
+}
+
+/// The layout produced by the formatter
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Style {
+    indent_width: usize,
+    tabs: bool,
+    braces: BraceStyle,
+    align: bool,
+    max_blank_lines: usize,
+    blank_lines_around_blocks: bool
+}
+
+impl Style {
+    /// Creates the default style: four spaces, braces on the same line, no alignment and
+    /// at most one blank line in a row
+    pub fn new() -> Style {
+        Style::default()
+    }
+
+    /// Sets the number of spaces per indentation level
+    pub fn indent_width(mut self, width: usize) -> Style {
+        self.indent_width = width;
+        self
+    }
+
+    /// Sets whether to indent with one tab per level instead of spaces
+    pub fn tabs(mut self, tabs: bool) -> Style {
+        self.tabs = tabs;
+        self
+    }
+
+    pub fn brace_style(mut self, braces: BraceStyle) -> Style {
+        self.braces = braces;
+        self
+    }
+
+    /// Sets whether the parameters of consecutive options without blocks start in the
+    /// same column. A blank line or a block ends such a group
+    pub fn align_params(mut self, align: bool) -> Style {
+        self.align = align;
+        self
+    }
+
+    /// Sets how many blank lines in a row are kept. Blank lines at the start and end of
+    /// a block are always removed
+    pub fn max_blank_lines(mut self, max: usize) -> Style {
+        self.max_blank_lines = max;
+        self
+    }
+
+    /// Sets whether blocks are separated from their siblings by a blank line. Comments
+    /// directly above a block stay attached to it
+    pub fn blank_lines_around_blocks(mut self, blank: bool) -> Style {
+        self.blank_lines_around_blocks = blank;
+        self
+    }
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            indent_width: 4,
+            tabs: false,
+            braces: BraceStyle::SameLine,
+            align: false,
+            max_blank_lines: 1,
+            blank_lines_around_blocks: false
+        }
+    }
+}
+
+/// Formats a config file with the default parse options
+pub fn format(source: &str, style: &Style) -> Result<String> {
+    format_with(source, style, &ParseOptions::default())
+}
+
+/// Formats a config file. Fails with the parse error if the file is invalid under the
+/// given options
+pub fn format_with(source: &str, style: &Style, options: &ParseOptions) -> Result<String> {
+    let lexer = Lexer::with_options(source.chars(), String::new(), options);
+    parser::build(Events::with_options(parser::adapt(Box::new(lexer), options, &Default::default(), &Default::default()), options))?;
+    let mut lexer = Lexer::with_options(source.chars(), String::new(), options).keep_comments(true);
+    let mut tokens = vec![];
+    while let Some(token) = lexer.next() {
+        let token = token?;
+        let span = Span::new(token.position(), lexer.token_end());
+        tokens.push((token, span));
+    }
+    let tokens: Vec<Tok> = parser::adapt_spanned(tokens, options)?.into_iter()
+        .map(|(token, span)| Tok { ty: token.token_type, line: span.start.line, end_line: span.end.line })
+        .collect();
+    let items = Builder { tokens: tokens.into_iter().peekable(), last_line: 0 }.items();
+    let mut printer = Printer { style, out: String::with_capacity(source.len()) };
+    printer.items(&items, 0);
+    Ok(printer.out)
+}
+
+struct Tok {
+    ty: TokenType,
+    line: u32,
+    end_line: u32
+}
+
+enum Item {
+    Directive {
+        name: String,
+        params: Vec<String>,
+        block: Option<Block>,
+        /// Comment at the end of the line with the `;` or `}`
+        comment: Option<String>,
+        blank_lines: usize
+    },
+    Comment { text: String, blank_lines: usize }
+}
+
+struct Block {
+    /// Comment at the end of the line with the `{`
+    comment: Option<String>,
+    items: Vec<Item>
+}
+
+impl Item {
+    fn blank_lines(&self) -> usize {
+        match *self {
+            Item::Directive { blank_lines, .. } | Item::Comment { blank_lines, .. } => blank_lines
+        }
+    }
+
+    fn is_block(&self) -> bool {
+        matches!(*self, Item::Directive { block: Some(_), .. })
+    }
+
+    fn is_comment(&self) -> bool {
+        matches!(*self, Item::Comment { .. })
+    }
+}
+
+/// Groups the tokens of a valid file into options and comments
+struct Builder {
+    tokens: Peekable<vec::IntoIter<Tok>>,
+    last_line: u32
+}
+
+impl Builder {
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.tokens.next();
+        if let Some(ref t) = t {
+            self.last_line = t.end_line;
+        }
+        t
+    }
+
+    /// Reads options up to the end of the current block, leaving the `}`
+    fn items(&mut self) -> Vec<Item> {
+        let mut items = vec![];
+        loop {
+            let (blank_lines, same_line) = match self.tokens.peek() {
+                Some(&Tok { ty: TokenType::CloseBrace, .. }) | None => return items,
+                Some(t) => ((t.line.saturating_sub(self.last_line)).saturating_sub(1) as usize, t.line == self.last_line)
+            };
+            let t = self.next().unwrap();
+            match t.ty {
+                TokenType::Comment(text) => {
+                    let text = String::from(text.trim_end());
+                    if same_line {
+                        if let Some(&mut Item::Directive { comment: ref mut c @ None, .. }) = items.last_mut() {
+                            *c = Some(text);
+                            continue;
+                        }
+                    }
+                    items.push(Item::Comment { text, blank_lines });
+                },
+                TokenType::RawLiteral(name) | TokenType::StringLiteral(name) => {
+                    let name = quote(&name).into_owned();
+                    items.push(self.directive(name, blank_lines));
+                },
+                // Stray semicolons, everything else has been rejected by the parser
+                _ => {}
+            }
+        }
+    }
+
+    fn directive(&mut self, name: String, blank_lines: usize) -> Item {
+        let mut params: Vec<String> = vec![];
+        let mut comments: Vec<String> = vec![];
+        // Whether the last parameter is the `(` of a condition, which hugs the next one
+        let mut open = false;
+        while let Some(&TokenType::RawLiteral(_)) | Some(&TokenType::StringLiteral(_)) | Some(&TokenType::Comment(_))
+                = self.tokens.peek().map(|t| &t.ty) {
+            let ty = self.next().map(|t| t.ty);
+            let was_open = mem::replace(&mut open, false);
+            match ty {
+                // The parentheses of conditions, which the adapters pass on as literals
+                Some(TokenType::RawLiteral(ref p)) if p == ")" => match params.last_mut() {
+                    Some(last) => last.push(')'),
+                    None => params.push(p.clone())
+                },
+                Some(TokenType::RawLiteral(p)) => {
+                    open = p == "(";
+                    // Other unquoted literals with parentheses are secret references
+                    let text = match secret::referenced(&p) {
+                        Some(reference) => secret::call(reference),
+                        None if open => p,
+                        None => quote(&p).into_owned()
+                    };
+                    push_param(&mut params, text, was_open);
+                },
+                Some(TokenType::StringLiteral(p)) => push_param(&mut params, quote(&p).into_owned(), was_open),
+                Some(TokenType::Comment(ref text)) if text.starts_with("/*") => params.push(text.clone()),
+                Some(TokenType::Comment(text)) => comments.push(String::from(text.trim_end())),
+                _ => unreachable!()
+            }
+        }
+        let block = match self.tokens.peek().map(|t| &t.ty) {
+            // A missing semicolon, accepted by lenient parsing
+            Some(&TokenType::CloseBrace) | None => None,
+            _ => match self.next().map(|t| t.ty) {
+                Some(TokenType::OpenBrace) => {
+                    let open_line = self.last_line;
+                    if let Some(&Tok { ty: TokenType::Comment(_), line, .. }) = self.tokens.peek() {
+                        if line == open_line {
+                            if let Some(TokenType::Comment(text)) = self.next().map(|t| t.ty) {
+                                comments.push(String::from(text.trim_end()));
+                            }
+                        }
+                    }
+                    let items = self.items();
+                    self.next();
+                    Some(Block { comment: join(&mut comments), items })
+                },
+                _ => None
+            }
+        };
+        Item::Directive { name, params, block, comment: join(&mut comments), blank_lines }
+    }
+}
+
+/// Appends a parameter, or attaches it to the last one if that is an opening parenthesis
+fn push_param(params: &mut Vec<String>, text: String, after_open: bool) {
+    match params.last_mut() {
+        Some(last) if after_open => last.push_str(&text),
+        _ => params.push(text)
+    }
+}
+
+fn join(comments: &mut Vec<String>) -> Option<String> {
+    if comments.is_empty() {
+        None
+    } else {
+        let joined = comments.join(" ");
+        comments.clear();
+        Some(joined)
+    }
+}
+
+struct Printer<'a> {
+    style: &'a Style,
+    out: String
+}
+
+impl <'a> Printer<'a> {
+    fn items(&mut self, items: &[Item], depth: usize) {
+        let widths = self.name_widths(items);
+        // Whether an item is a block or a comment directly above one
+        let mut starts_block = vec![false; items.len()];
+        for i in (0..items.len()).rev() {
+            starts_block[i] = items[i].is_block()
+                || (items[i].is_comment() && i + 1 < items.len() && items[i + 1].blank_lines() == 0 && starts_block[i + 1]);
+        }
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                let mut blank_lines = item.blank_lines().min(self.style.max_blank_lines);
+                if self.style.blank_lines_around_blocks {
+                    let after_block = items[i - 1].is_block();
+                    let before_block = starts_block[i] && !(items[i - 1].is_comment() && starts_block[i - 1]);
+                    if after_block || before_block {
+                        blank_lines = blank_lines.max(1);
+                    }
+                }
+                for _ in 0..blank_lines {
+                    self.out.push('\n');
+                }
+            }
+            self.indent(depth);
+            match *item {
+                Item::Comment { ref text, .. } => {
+                    self.out.push_str(text);
+                    self.out.push('\n');
+                },
+                Item::Directive { ref name, ref params, ref block, ref comment, .. } => {
+                    self.out.push_str(name);
+                    if !params.is_empty() {
+                        for _ in name.chars().count()..widths[i] {
+                            self.out.push(' ');
+                        }
+                        self.out.push(' ');
+                        self.out.push_str(&params.join(" "));
+                    }
+                    match *block {
+                        Some(ref block) => self.block(block, depth),
+                        None => self.out.push(';')
+                    }
+                    self.comment(comment);
+                    self.out.push('\n');
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block, depth: usize) {
+        if block.items.is_empty() && block.comment.is_none() {
+            self.out.push_str(" {}");
+            return;
+        }
+        match self.style.braces {
+            BraceStyle::SameLine => self.out.push_str(" {"),
+            BraceStyle::NextLine => {
+                self.out.push('\n');
+                self.indent(depth);
+                self.out.push('{');
+            }
+        }
+        self.comment(&block.comment);
+        self.out.push('\n');
+        self.items(&block.items, depth + 1);
+        self.indent(depth);
+        self.out.push('}');
+    }
+
+    fn comment(&mut self, comment: &Option<String>) {
+        if let Some(ref comment) = *comment {
+            self.out.push(' ');
+            self.out.push_str(comment);
+        }
+    }
+
+    fn indent(&mut self, depth: usize) {
+        if self.style.tabs {
+            for _ in 0..depth {
+                self.out.push('\t');
+            }
+        } else {
+            for _ in 0..depth * self.style.indent_width {
+                self.out.push(' ');
+            }
+        }
+    }
+
+    /// Returns the width the name of each item is padded to, which is zero unless
+    /// parameters are aligned
+    fn name_widths(&self, items: &[Item]) -> Vec<usize> {
+        let mut widths = vec![0; items.len()];
+        if !self.style.align {
+            return widths;
+        }
+        let mut group: Vec<usize> = vec![];
+        for (i, item) in items.iter().enumerate() {
+            if item.blank_lines() > 0 || item.is_block() {
+                set_widths(items, &group, &mut widths);
+                group.clear();
+            }
+            if let Item::Directive { block: None, ref params, .. } = *item {
+                if !params.is_empty() {
+                    group.push(i);
+                }
+            }
+        }
+        set_widths(items, &group, &mut widths);
+        widths
+    }
+}
+
+fn set_widths(items: &[Item], group: &[usize], widths: &mut [usize]) {
+    if group.len() < 2 {
+        return;
+    }
+    let width = group.iter().map(|&i| match items[i] {
+        Item::Directive { ref name, .. } => name.chars().count(),
+        Item::Comment { .. } => 0
+    }).max().unwrap_or(0);
+    for &i in group {
+        widths[i] = width;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use options::Conditionals;
+
+    fn fmt(source: &str) -> String {
+        format(source, &Style::new()).unwrap()
+    }
+
+    #[test]
+    fn test_normalizing_layout() {
+        assert_eq!(
+            fmt("server  \"freenode\"{\n\tconnect irc.freenode.net \"6697\";;\n  user \"a b\" {nick x ;};\n}\n\n"),
+            "server freenode {\n    connect irc.freenode.net 6697;\n    user \"a b\" {\n        nick x;\n    }\n}\n");
+        assert_eq!(fmt("a {}\nb { }"), "a {}\nb {}\n");
+        assert_eq!(fmt(""), "");
+    }
+
+    #[test]
+    fn test_keeping_comments() {
+        let source = "# header\n\nserver a { # the server\n  nick b; // inline\n  /* before\n     close */\n} # end\nx /* mid */ y;\n";
+        assert_eq!(fmt(source), "# header\n\nserver a { # the server\n    nick b; // inline\n    /* before\n     close */\n} # end\nx /* mid */ y;\n");
+        assert_eq!(fmt("a b # moved\n c;"), "a b c; # moved\n");
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let source = "a;\n\n\n\nb;\nc { d; }\n# about e\ne { f; }\ng;";
+        assert_eq!(fmt(source), "a;\n\nb;\nc {\n    d;\n}\n# about e\ne {\n    f;\n}\ng;\n");
+        assert_eq!(
+            format(source, &Style::new().max_blank_lines(0).blank_lines_around_blocks(true)).unwrap(),
+            "a;\nb;\n\nc {\n    d;\n}\n\n# about e\ne {\n    f;\n}\n\ng;\n");
+    }
+
+    #[test]
+    fn test_styles() {
+        let source = "server a {\n    connect host 6697;\n    nick bot;\n    channel \"#x\" key;\n    user;\n}";
+        let style = Style::new().indent_width(2).align_params(true).brace_style(BraceStyle::NextLine);
+        assert_eq!(
+            format(source, &style).unwrap(),
+            "server a\n{\n  connect host 6697;\n  nick    bot;\n  channel \"#x\" key;\n  user;\n}\n");
+        assert_eq!(format("a { b { c; } }", &Style::new().tabs(true)).unwrap(), "a {\n\tb {\n\t\tc;\n\t}\n}\n");
+    }
+
+    #[test]
+    fn test_preserving_meaning() {
+        let sources = [
+            "a \"\" \"x y\" \"#c\" \"q\\\"\\\\\";",
+            "server a { connect b; # c\n user \"d\" { e; }; }",
+            "a // b\n{ c; }",
+            "\"my key\" v; \"plain\" { x; }",
+        ];
+        for source in &sources {
+            let formatted = fmt(source);
+            assert_eq!(::parse_str(&formatted).unwrap(), ::parse_str(source).unwrap());
+            assert_eq!(fmt(&formatted), formatted);
+        }
+    }
+
+    #[test]
+    fn test_rejecting_invalid_input() {
+        assert_eq!(format("a {", &Style::new()).unwrap_err().to_string(), "1:3: unexpected end of file, expected }");
+    }
+
+    #[test]
+    fn test_conditions() {
+        let options = ParseOptions::new().conditionals(Conditionals::KeepBranches);
+        let source = "if (a == b) { x; } else if ((a ||  b) && !c) {y;}\nelse { z; }";
+        let formatted = format_with(source, &Style::new(), &options).unwrap();
+        assert_eq!(formatted, "if (a == b) {\n    x;\n}\nelse if ((a || b) && !c) {\n    y;\n}\nelse {\n    z;\n}\n");
+        assert_eq!(format_with(&formatted, &Style::new(), &options).unwrap(), formatted);
+        assert!(format("if (a == b) { x; }", &Style::new()).is_err());
+    }
+
+    #[test]
+    fn test_secret_references() {
+        let options = ParseOptions::new().secrets(true);
+        let formatted = format_with("pw  secret( \"env:X\" ) \"secret(y)\";\nkey secret(/* c */file:/k);", &Style::new(), &options).unwrap();
+        assert_eq!(formatted, "pw secret(\"env:X\") \"secret(y)\";\nkey secret(\"file:/k\");\n");
+        assert_eq!(::parse_string_with(formatted, &options).unwrap().inner()[0].params(), &vec!["secret(env:X)", "secret(y)"]);
+    }
+}
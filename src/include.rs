@@ -0,0 +1,308 @@
+//! Loading files together with the files they include
+//!
+//! Each dialect has its own include option:
+//!
+//! * `include PATTERN;` in native files is resolved relative to the including file
+//! * `include PATTERN;` in nginx is resolved relative to the directory of the main file
+//! * `.include PATH` in PulseAudio scripts is resolved relative to the including file,
+//!   and includes all `.pa` files in alphabetical order if `PATH` is a directory
+//!
+//! Patterns may use `*`, `?` and `[...]` in their last component, with matches included
+//! in alphabetical order. The include options are replaced by the options of the
+//! included files, whose spans refer to the file they were read from.
+//!
+//! ```no_run
+//! use config_parser::ParseOptions;
+//! use config_parser::dialect::Dialect;
+//! use config_parser::include;
+//!
+//! let loaded = include::load("/etc/bot/bot.cfg", Dialect::Native, &ParseOptions::default()).unwrap();
+//! println!("read {} files", loaded.files.len());
+//! ```
+
+use std::{fs, io};
+use std::fmt;
+use std::error;
+use std::path::{Path, PathBuf};
+use super::config::ConfigBlock;
+use super::dialect::Dialect;
+use super::error::{Error, ErrorType};
+use super::options::ParseOptions;
+use super::span::Position;
+
+/// How many files may include each other before loading gives up
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// A config and the files it was read from
+#[derive(Debug)]
+pub struct Loaded {
+    pub config: ConfigBlock,
+    /// The main file followed by the included ones, each listed once
+    pub files: Vec<PathBuf>,
+    /// The patterns of the wildcard includes, like `/etc/bot/conf.d/*.cfg`, each listed
+    /// once. A PulseAudio directory include is listed as `DIR/*.pa`
+    pub patterns: Vec<PathBuf>
+}
+
+/// A failure to read or parse one of the files of a config
+#[derive(Debug)]
+pub struct LoadError {
+    /// The file containing the error, which is the including file if an included one
+    /// cannot be found
+    pub file: PathBuf,
+    pub error: Error
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.error.position() == Position::default() {
+            write!(f, "{}: {}", self.file.display(), self.error.error_type())
+        } else {
+            write!(f, "{}:{}", self.file.display(), self.error)
+        }
+    }
+}
+
+impl error::Error for LoadError {}
+
+/// Loads a file written in the dialect and replaces its include options by the
+/// contents of the included files
+pub fn load<P: AsRef<Path>>(path: P, dialect: Dialect, options: &ParseOptions) -> Result<Loaded, LoadError> {
+    let path = path.as_ref();
+    let mut loader = Loader {
+        dialect,
+        options,
+        root: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        files: vec![],
+        patterns: vec![]
+    };
+    let config = loader.load(path, 0)?;
+    Ok(Loaded { config, files: loader.files, patterns: loader.patterns })
+}
+
+struct Loader<'a> {
+    dialect: Dialect,
+    options: &'a ParseOptions,
+    /// The directory of the main file
+    root: PathBuf,
+    files: Vec<PathBuf>,
+    patterns: Vec<PathBuf>
+}
+
+impl <'a> Loader<'a> {
+    fn load(&mut self, path: &Path, depth: usize) -> Result<ConfigBlock, LoadError> {
+        if !self.files.iter().any(|f| f == path) {
+            self.files.push(path.to_path_buf());
+        }
+        let fail = |error| LoadError { file: path.to_path_buf(), error };
+        let text = fs::read_to_string(path).map_err(|e| fail(Error::new(0, 0, ErrorType::Io(e.kind(), e.to_string()), None)))?;
+        let mut cfg = self.dialect.parse_str(&text, self.options).map_err(fail)?;
+        self.expand(&mut cfg, path, depth)?;
+        Ok(cfg)
+    }
+
+    /// Replaces the include options anywhere in the tree with the contents of their files
+    fn expand(&mut self, block: &mut ConfigBlock, path: &Path, depth: usize) -> Result<(), LoadError> {
+        let directive = match self.dialect {
+            Dialect::PulseAudio => ".include",
+            Dialect::Native | Dialect::Nginx => "include"
+        };
+        let mut i = 0;
+        while i < block.inner().len() {
+            let include = {
+                let child = &block.inner()[i];
+                if child.name() == directive && child.len() == 1 {
+                    Some((child.get(0).to_owned(), child.span().map(|s| s.start).unwrap_or_default()))
+                } else {
+                    None
+                }
+            };
+            let (pattern, position) = match include {
+                Some(include) => include,
+                None => {
+                    self.expand(&mut block.inner_mut()[i], path, depth)?;
+                    i += 1;
+                    continue;
+                }
+            };
+            let fail = |kind, message| LoadError {
+                file: path.to_path_buf(),
+                error: Error::new(position.line, position.col, ErrorType::Io(kind, message), None)
+            };
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(fail(io::ErrorKind::Other, format!("includes nested deeper than {} files", MAX_INCLUDE_DEPTH)));
+            }
+            let base = match self.dialect {
+                Dialect::Nginx => self.root.clone(),
+                Dialect::Native | Dialect::PulseAudio => path.parent().map(Path::to_path_buf).unwrap_or_default()
+            };
+            let files = self.resolve(&base, &pattern).map_err(|e| fail(e.kind(), format!("{}: {}", pattern, e)))?;
+            block.remove_at(i);
+            for file in files {
+                let mut included = self.load(&file, depth + 1)?;
+                for child in included.inner_mut().drain(..) {
+                    block.insert_at(i, child);
+                    i += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the files an include refers to
+    fn resolve(&mut self, base: &Path, pattern: &str) -> io::Result<Vec<PathBuf>> {
+        let path = base.join(pattern);
+        let wildcard = match path.file_name().and_then(|n| n.to_str()) {
+            _ if self.dialect == Dialect::PulseAudio => fs::metadata(&path)?.is_dir(),
+            Some(name) => name.contains(['*', '?', '[']),
+            None => false
+        };
+        if !wildcard {
+            fs::metadata(&path)?;
+            return Ok(vec![path]);
+        }
+        let path = if self.dialect == Dialect::PulseAudio { path.join("*.pa") } else { path };
+        let files = glob(&path);
+        if !self.patterns.contains(&path) {
+            self.patterns.push(path);
+        }
+        files
+    }
+}
+
+/// Returns the files matching a pattern in its last component, in alphabetical order
+pub(crate) fn glob(pattern: &Path) -> io::Result<Vec<PathBuf>> {
+    let name = pattern.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let dir = match pattern.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new(".")
+    };
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let accepted = entry.file_name().to_str().map(|n| wildcard_match(name, n)).unwrap_or(false);
+        if accepted && !entry.file_type()?.is_dir() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Matches a file name against a pattern with `*`, `?` and `[...]` like glob(3)
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to continue after the last `*` if the rest does not match
+    let mut backtrack = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue;
+            },
+            Some('?') => Some(p + 1),
+            Some('[') => match pattern[p..].iter().position(|&c| c == ']') {
+                Some(end) if end > 1 => {
+                    let class = &pattern[p + 1..p + end];
+                    let (negated, class) = match class.first() {
+                        Some('!') | Some('^') => (true, &class[1..]),
+                        _ => (false, class)
+                    };
+                    if class_contains(class, name[n]) != negated { Some(p + end + 1) } else { None }
+                },
+                _ => if name[n] == '[' { Some(p + 1) } else { None }
+            },
+            Some(&c) if c == name[n] => Some(p + 1),
+            _ => None
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            },
+            (None, Some((star, at))) => {
+                backtrack = Some((star, at + 1));
+                p = star + 1;
+                n = at + 1;
+            },
+            (None, None) => return false
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn class_contains(class: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if class[i] <= c && c <= class[i + 2] {
+                return true;
+            }
+            i += 3;
+        } else {
+            if class[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("config-parser-{}-{}", name, ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_native_includes() {
+        let dir = temp_dir("include");
+        fs::create_dir_all(dir.join("servers")).unwrap();
+        fs::write(dir.join("bot.cfg"), "nick bot;\ninclude servers/*.cfg;\n").unwrap();
+        fs::write(dir.join("servers/a.cfg"), "server a { include ../channels.cfg; }").unwrap();
+        fs::write(dir.join("channels.cfg"), "channel x;").unwrap();
+
+        let loaded = load(dir.join("bot.cfg"), Dialect::Native, &ParseOptions::default()).unwrap();
+        assert_eq!(loaded.config, config! { nick bot; server a { channel x; } });
+        assert_eq!(loaded.files, vec![dir.join("bot.cfg"), dir.join("servers/a.cfg"), dir.join("servers/../channels.cfg")]);
+        assert_eq!(loaded.patterns, vec![dir.join("servers/*.cfg")]);
+
+        fs::write(dir.join("channels.cfg"), "channel x").unwrap();
+        let err = load(dir.join("bot.cfg"), Dialect::Native, &ParseOptions::default()).unwrap_err();
+        assert_eq!(err.file, dir.join("servers/../channels.cfg"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pulseaudio_includes() {
+        let dir = temp_dir("include-pa");
+        fs::create_dir_all(dir.join("default.pa.d")).unwrap();
+        fs::write(dir.join("default.pa"), ".include default.pa.d\nload-module a\n").unwrap();
+        fs::write(dir.join("default.pa.d/10-b.pa"), "load-module b\n").unwrap();
+        fs::write(dir.join("default.pa.d/README"), "not a script").unwrap();
+
+        let loaded = load(dir.join("default.pa"), Dialect::PulseAudio, &ParseOptions::default()).unwrap();
+        let modules: Vec<&str> = loaded.config.inner().iter().map(|b| b.get(0)).collect();
+        assert_eq!(modules, vec!["b", "a"]);
+        assert_eq!(loaded.patterns, vec![dir.join("default.pa.d/*.pa")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(wildcard_match("*.conf", "a.conf"));
+        assert!(!wildcard_match("*.conf", "a.conf.bak"));
+        assert!(wildcard_match("site-?[0-9].*", "site-a1.conf"));
+        assert!(!wildcard_match("[!a]*", "abc"));
+        assert!(wildcard_match("a*b*c", "axxbyybc"));
+    }
+}
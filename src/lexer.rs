@@ -1,4 +1,7 @@
 use super::error::{Error, Result, ErrorType, CodePos};
+use super::options::{CommentStyle, ParseOptions};
+use super::span::Position;
+use std::{fmt, mem};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum LexerMode {
@@ -8,282 +11,467 @@ enum LexerMode {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum TokenType {
-    StringLiteral(String),
-    RawLiteral(String),
+pub enum TokenType<S = String> {
+    StringLiteral(S),
+    RawLiteral(S),
     OpenBrace,
     CloseBrace,
     OpenParen,
     CloseParen,
-    Semicolon
+    Semicolon,
+    /// The complete text of a comment, including its delimiters. Only produced when the
+    /// lexer is asked to keep comments
+    Comment(S)
 }
 
+impl <S> TokenType<S> where S: Into<String> {
+    /// Converts the literal contents into owned strings
+    pub fn into_owned(self) -> TokenType {
+        match self {
+            TokenType::StringLiteral(s) => TokenType::StringLiteral(s.into()),
+            TokenType::RawLiteral(s) => TokenType::RawLiteral(s.into()),
+            TokenType::OpenBrace => TokenType::OpenBrace,
+            TokenType::CloseBrace => TokenType::CloseBrace,
+            TokenType::OpenParen => TokenType::OpenParen,
+            TokenType::CloseParen => TokenType::CloseParen,
+            TokenType::Semicolon => TokenType::Semicolon,
+            TokenType::Comment(s) => TokenType::Comment(s.into())
+        }
+    }
+}
+
+impl <S> fmt::Display for TokenType<S> where S: fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenType::StringLiteral(ref s) => write!(f, "string \"{}\"", s),
+            TokenType::RawLiteral(ref s) => write!(f, "`{}`", s),
+            TokenType::OpenBrace => write!(f, "`{{`"),
+            TokenType::CloseBrace => write!(f, "`}}`"),
+            TokenType::OpenParen => write!(f, "`(`"),
+            TokenType::CloseParen => write!(f, "`)`"),
+            TokenType::Semicolon => write!(f, "`;`"),
+            TokenType::Comment(_) => write!(f, "comment")
+        }
+    }
+}
+
+/// A token and the position of its first character
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub struct Token {
-    pub token_type: TokenType,
+pub struct Token<S = String> {
+    pub token_type: TokenType<S>,
     pub line: u32,
     pub col: u16
 }
 
-impl Token {
-    pub fn new(line: u32, col: u16, ty: TokenType) -> Token {
+impl <S> Token<S> {
+    pub fn new(line: u32, col: u16, ty: TokenType<S>) -> Token<S> {
         Token {
-            line: line,
-            col: col,
+            line,
+            col,
             token_type: ty
         }
     }
+
+    /// Returns the position of the first character of this token
+    pub fn position(&self) -> Position {
+        Position::new(self.line, self.col)
+    }
 }
 
-struct LexerState {
+impl <S> Token<S> where S: Into<String> {
+    /// Converts the literal contents into owned strings
+    pub fn into_owned(self) -> Token {
+        Token::new(self.line, self.col, self.token_type.into_owned())
+    }
+}
+
+/// Collects the contents of a literal while it is being lexed
+pub trait Literal {
+    type Value;
+    /// Starts a new literal at the given byte offset of the input
+    fn begin(&mut self, offset: usize);
+    /// Appends a character which appears verbatim in the input. `end` is the byte offset
+    /// right after it
+    fn push(&mut self, c: char, end: usize);
+    /// Appends a character which was produced by an escape sequence
+    fn push_escaped(&mut self, c: char);
+    /// Returns the collected literal
+    fn finish(&mut self) -> Self::Value;
+}
+
+impl Literal for String {
+    type Value = String;
+
+    fn begin(&mut self, _: usize) {
+        self.clear();
+    }
+
+    fn push(&mut self, c: char, _: usize) {
+        String::push(self, c);
+    }
+
+    fn push_escaped(&mut self, c: char) {
+        String::push(self, c);
+    }
+
+    fn finish(&mut self) -> String {
+        mem::take(self)
+    }
+}
+
+/// Splits a stream of chars into tokens on demand
+///
+/// Tokens are produced one at a time as the iterator is advanced, so only the
+/// literal currently being read is held in memory. After the first error the
+/// iterator is exhausted. How the contents of literals are stored is up to the
+/// `Literal` implementation, by default they are copied into owned strings.
+pub struct Lexer<I, L = String> where I: Iterator<Item=char>, L: Literal {
+    input: I,
+    literal: L,
     line: u32,
     col: u16,
-    input: Box<Iterator<Item=char>>,
-    mode: LexerMode,
-    escaped: bool,
-    tmp: String,
-    tokens: Vec<Token>,
-    force_next: Option<char>
+    offset: usize,
+    peeked: Option<char>,
+    pending: Option<Token<L::Value>>,
+    done: bool,
+    comments: Vec<CommentStyle>,
+    max_input_size: Option<usize>,
+    max_literal_length: Option<usize>,
+    too_large: bool,
+    keep_comments: bool,
+    comment_start: Option<(u32, u16)>,
+    before: (u32, u16),
+    token_end: (u32, u16)
 }
 
-impl CodePos for LexerState {
+impl <I, L> CodePos for Lexer<I, L> where I: Iterator<Item=char>, L: Literal {
     fn location(&self) -> (u32, u16) {
         (self.line, self.col)
     }
 }
 
-fn end_token(state: &mut LexerState) {
-    if state.mode != LexerMode::None {
-        let t = match state.mode {
-            LexerMode::None => unreachable!("Invalid mode when generating token"),
-            LexerMode::String => Token::new(state.line, state.col, TokenType::StringLiteral(state.tmp.clone())),
-            LexerMode::Raw => Token::new(state.line, state.col, TokenType::RawLiteral(state.tmp.clone()))
-        };
-        state.mode = LexerMode::None;
-        state.tokens.push(t);
+impl <I> Lexer<I> where I: Iterator<Item=char> {
+    pub fn new(input: I) -> Lexer<I> {
+        Lexer::with_literal(input, String::new())
     }
 }
 
-fn start_token(state: &mut LexerState, mode: LexerMode) {
-    if state.mode != LexerMode::None {
-        end_token(state);
+impl <I, L> Lexer<I, L> where I: Iterator<Item=char>, L: Literal {
+    /// Creates a lexer which collects literals using the given `Literal`
+    pub fn with_literal(input: I, literal: L) -> Lexer<I, L> {
+        Lexer::with_options(input, literal, &ParseOptions::default())
     }
-    state.tmp = String::new();
-    state.mode = mode;
-}
 
-fn token(state: &mut LexerState, t: TokenType) {
-    end_token(state);
-    state.tokens.push(Token::new(state.line, state.col, t));
-}
+    /// Creates a lexer which collects literals using the given `Literal` and obeys the
+    /// comment styles, input size and literal length limits of the options
+    pub fn with_options(input: I, literal: L, options: &ParseOptions) -> Lexer<I, L> {
+        Lexer {
+            input,
+            literal,
+            line: 1,
+            col: 0,
+            offset: 0,
+            peeked: None,
+            pending: None,
+            done: false,
+            comments: options.comments.clone(),
+            max_input_size: options.max_input_size,
+            max_literal_length: options.max_literal_length,
+            too_large: false,
+            keep_comments: false,
+            comment_start: None,
+            before: (1, 0),
+            token_end: (0, 0)
+        }
+    }
 
-pub fn run(input: Box<Iterator<Item=char>>) -> Result<Vec<Token>> {
-    let mut state = LexerState { line: 1, col: 0, input: input, mode: LexerMode::None, escaped: false, tmp: String::new(), tokens: vec![], force_next: None};
-    loop {
-        let c = { next(&mut state) };
-        let mode = state.mode.clone();
-        let esc = state.escaped;
-        match (c, mode, esc) {
-            (Some('"'),  LexerMode::String, false) => {
-                end_token(&mut state);
-            },
-            (Some('"'),  LexerMode::None,   false) => {
-                start_token(&mut state, LexerMode::String);
-            },
-            (Some('\\'), LexerMode::String, false) => {
-                state.escaped = true;
-            },
-            (Some('\\'), LexerMode::String, true ) => {
-                state.tmp.push('\\');
-                state.escaped = false;
-            }
-            (Some('n'),  LexerMode::String, true ) => {
-                state.tmp.push('\n');
-                state.escaped = false;
-            },
-            (Some(x),    LexerMode::String, false) => {
-                state.tmp.push(x);
-            },
-            (None,       LexerMode::String, _    ) => {
-                return fail(&state, ErrorType::UnexpectedEOF)
-            }
-            (Some(' '),  LexerMode::None,   false) => {},
-            (Some(' '),  LexerMode::Raw,    false) => {
-                end_token(&mut state);
-            }
-            (Some('('),  LexerMode::Raw,    false) => {
-                token(&mut state, TokenType::OpenParen);
-            },
-            (Some(')'),  LexerMode::Raw,    false) => {
-                token(&mut state, TokenType::CloseParen);
-            },
-            (Some('{'),  LexerMode::Raw,    false) => {
-                token(&mut state, TokenType::OpenBrace);
-            },
-            (Some('}'),  LexerMode::Raw,    false) => {
-                token(&mut state, TokenType::CloseBrace);
-            },
-            (Some(';'),  LexerMode::Raw, false) => {
-                token(&mut state, TokenType::Semicolon);
-            }
-            (Some('('),  LexerMode::None,   false) => {
-                token(&mut state, TokenType::OpenParen);
-            },
-            (Some(')'),  LexerMode::None,   false) => {
-                token(&mut state, TokenType::CloseParen);
-            },
-            (Some('{'),  LexerMode::None,    false) => {
-                token(&mut state, TokenType::OpenBrace);
-            },
-            (Some('}'),  LexerMode::None,    false) => {
-                token(&mut state, TokenType::CloseBrace);
-            },
-            (Some(';'),  LexerMode::None, false) => {
-                token(&mut state, TokenType::Semicolon);
+    /// Sets whether comments are returned as `TokenType::Comment` instead of being skipped
+    pub fn keep_comments(mut self, keep: bool) -> Lexer<I, L> {
+        self.keep_comments = keep;
+        self
+    }
+
+    /// Returns the number of bytes consumed so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the position of the last character of the most recently returned token
+    pub fn token_end(&self) -> Position {
+        Position::new(self.token_end.0, self.token_end.1)
+    }
+
+    fn lex_token(&mut self) -> Result<Option<Token<L::Value>>> {
+        let mut mode = LexerMode::None;
+        let mut start = (0, 0);
+        let mut length = 0;
+        loop {
+            let c = match mode {
+                LexerMode::String => self.read(),
+                LexerMode::Raw => self.next_token_char(),
+                LexerMode::None => self.next_code_char()
+            };
+            match (c, mode) {
+                (Some('"'),  LexerMode::String) => {
+                    self.token_end = self.location();
+                    return Ok(Some(Token::new(start.0, start.1, TokenType::StringLiteral(self.literal.finish()))));
+                },
+                (Some('\\'), LexerMode::String) => {
+                    let x = match self.read() {
+                        Some('n') => '\n',
+                        Some(x) => x,
+                        None => return fail(self, ErrorType::UnexpectedEOF)
+                    };
+                    length = self.grow_literal(length, x)?;
+                    self.literal.push_escaped(x);
+                },
+                (Some(x),    LexerMode::String) => {
+                    length = self.grow_literal(length, x)?;
+                    self.literal.push(x, self.offset);
+                },
+                (None,       LexerMode::String) => {
+                    return fail(self, ErrorType::UnexpectedEOF);
+                },
+                (Some(' '),  LexerMode::Raw) => {
+                    self.token_end = self.before;
+                    return Ok(Some(Token::new(start.0, start.1, TokenType::RawLiteral(self.literal.finish()))));
+                },
+                (Some(x),    LexerMode::Raw) => {
+                    match special_token(x) {
+                        Some(ty) => {
+                            self.pending = Some(Token::new(self.line, self.col, ty));
+                            self.token_end = self.before;
+                            return Ok(Some(Token::new(start.0, start.1, TokenType::RawLiteral(self.literal.finish()))));
+                        },
+                        None => {
+                            length = self.grow_literal(length, x)?;
+                            self.literal.push(x, self.offset);
+                        }
+                    }
+                },
+                (None,       LexerMode::Raw) => {
+                    self.token_end = self.location();
+                    return Ok(Some(Token::new(start.0, start.1, TokenType::RawLiteral(self.literal.finish()))));
+                },
+                (Some(' '),  LexerMode::None) => {
+                    if let Some(start) = self.comment_start.take() {
+                        return Ok(Some(Token::new(start.0, start.1, TokenType::Comment(self.literal.finish()))));
+                    }
+                },
+                (Some('"'),  LexerMode::None) => {
+                    start = self.location();
+                    mode = LexerMode::String;
+                    self.literal.begin(self.offset);
+                },
+                (Some(x),    LexerMode::None) => {
+                    match special_token(x) {
+                        Some(ty) => {
+                            self.token_end = self.location();
+                            return Ok(Some(Token::new(self.line, self.col, ty)));
+                        },
+                        None => {
+                            start = self.location();
+                            mode = LexerMode::Raw;
+                            self.literal.begin(self.offset - x.len_utf8());
+                            length = self.grow_literal(length, x)?;
+                            self.literal.push(x, self.offset);
+                        }
+                    }
+                },
+                (None,       LexerMode::None) => {
+                    return Ok(None);
+                }
             }
-            (Some(x),    LexerMode::None,   false) => {
-                start_token(&mut state, LexerMode::Raw);
-                state.tmp.push(x);
+        }
+    }
+
+    /// Returns the length of the current literal in bytes after appending the char,
+    /// failing if that exceeds the limit
+    fn grow_literal(&self, length: usize, c: char) -> Result<usize> {
+        let length = length + c.len_utf8();
+        match self.max_literal_length {
+            Some(max) if length > max => fail(self, ErrorType::LiteralTooLong(max)),
+            _ => Ok(length)
+        }
+    }
+
+    /// Reads the next char inside of a raw literal. Like in nginx, comments only start
+    /// between tokens, so `#` and `//` are regular characters here. Whitespace is
+    /// returned as a single space
+    fn next_token_char(&mut self) -> Option<char> {
+        match self.read() {
+            Some(c) if c.is_whitespace() => Some(' '),
+            c => c
+        }
+    }
+
+    /// Reads the next char between tokens, skipping comments. All whitespace and every
+    /// comment is returned as a single space. When comments are kept, the text of the
+    /// comment is collected in the literal and `comment_start` is set
+    fn next_code_char(&mut self) -> Option<char> {
+        let (c, style) = match self.read() {
+            Some('#') if self.allows(CommentStyle::Hash) => ('#', CommentStyle::Hash),
+            Some('/') => {
+                match self.peek() {
+                    Some('/') if self.allows(CommentStyle::DoubleSlash) => ('/', CommentStyle::DoubleSlash),
+                    Some('*') if self.allows(CommentStyle::Block) => ('/', CommentStyle::Block),
+                    _ => return Some('/')
+                }
             },
-            (Some(x),    LexerMode::Raw,    false) => {
-                state.tmp.push(x);
+            Some(c) if c.is_whitespace() => return Some(' '),
+            c => return c
+        };
+        if self.keep_comments {
+            self.comment_start = Some(self.location());
+            self.literal.begin(self.offset - 1);
+            self.literal.push(c, self.offset);
+        }
+        match style {
+            CommentStyle::Block => {
+                self.read();
+                self.comment_char('*');
+                self.skip_multiline();
             },
-            (None,       LexerMode::Raw,    false) => {
-                end_token(&mut state);
-                break;
-            }
-            (None,       LexerMode::None,   false) => {
-                break;
-            }
-            (c,          mode,               esc  ) => {
-                unreachable!("Invalid Parser State Reached: {:?}, {:?}, {:?}", c, mode, esc);
-            }
+            _ => self.skip_line()
         }
+        Some(' ')
     }
-    Ok(state.tokens)
 
-}
+    /// Adds a char to the text of the current comment if comments are kept
+    fn comment_char(&mut self, c: char) {
+        if self.keep_comments {
+            self.literal.push(c, self.offset);
+        }
+    }
 
-fn fail<T>(state: &LexerState, error_type: ErrorType) -> Result<T> {
-    Err(Error::from_state(state, error_type, None))
-}
+    fn allows(&self, style: CommentStyle) -> bool {
+        self.comments.contains(&style)
+    }
 
-#[derive(Debug, Clone, Copy)]
-enum PreProcState {
-    Default,
-    LineComment,
-    MultiComment(u8)
-}
+    fn skip_line(&mut self) {
+        while let Some(c) = self.read() {
+            if c == '\n' {
+                self.token_end = self.before;
+                return;
+            }
+            self.comment_char(c);
+        }
+        self.token_end = self.location();
+    }
 
-fn next_char(state: &mut LexerState) -> Option<char> {
-    match state.force_next {
-        Some(c) => {
-            state.force_next = None;
-            Some(c)
-        },
-        None => state.input.next()
+    /// Skips a possibly nested /* */ comment. The opening /* has already been read
+    fn skip_multiline(&mut self) {
+        let mut level = 1usize;
+        while let Some(c) = self.read() {
+            self.comment_char(c);
+            match (c, self.peek()) {
+                ('*', Some('/')) => {
+                    self.read();
+                    self.comment_char('/');
+                    level -= 1;
+                    if level == 0 {
+                        break;
+                    }
+                },
+                ('/', Some('*')) => {
+                    self.read();
+                    self.comment_char('*');
+                    level += 1;
+                },
+                _ => {}
+            }
+        }
+        self.token_end = self.location();
     }
-}
 
-fn lookahead(state: &mut LexerState) -> Option<char> {
-    match state.force_next {
-        Some(c) => Some(c),
-        None => {
-            let c = state.input.next();
-            state.force_next = c;
-            c
+    fn peek(&mut self) -> Option<char> {
+        if self.peeked.is_none() && !self.too_large {
+            self.peeked = self.input.next();
         }
+        self.peeked
     }
-}
 
-fn next(state: &mut LexerState) -> Option<char> {
-    let mut line = state.line;
-    let mut col = state.col;
-    let mut result: Option<char> = None;
-    let mut ps = PreProcState::Default;
-    loop {    
-        let c = match next_char(state) {
-            Some(c) => c,
-            None => break
+    /// Consumes the next char. Once the input size limit is exceeded, the input is
+    /// treated as if it ended there
+    fn read(&mut self) -> Option<char> {
+        if self.too_large {
+            return None;
+        }
+        self.before = (self.line, self.col);
+        let c = match self.peeked.take() {
+            Some(c) => Some(c),
+            None => self.input.next()
         };
-        match (c, ps) {
-            ('\n', PreProcState::Default) => {
-                line += 1;
-                col = 0;
-                result = Some(' ');
-                break;
-            },
-            ('\r', PreProcState::Default) => {},
-            ('/', PreProcState::Default) => {
-                col += 1;
-                let n = lookahead(state);
-                match n {
-                    Some('/') => ps = PreProcState::LineComment,
-                    Some('*') => ps = PreProcState::MultiComment(1),
-                    _ => {
-                        result = Some(c);
-                        break;
-                    }
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+            if let Some(max) = self.max_input_size {
+                if self.offset > max {
+                    self.too_large = true;
+                    return None;
                 }
+            }
+        }
+        match c {
+            Some('\n') => {
+                self.line = self.line.saturating_add(1);
+                self.col = 0;
             },
-            ('#', PreProcState::Default) => {
-                ps = PreProcState::LineComment;
-                col += 1;
-            },
-            (c, PreProcState::Default) if c.is_whitespace() => {
-                col += 1;
-                result = Some(' ');
-                break;
-            },
-            (_, PreProcState::Default) => {
-                result = Some(c);
-                col += 1;
-                break;
-            },
+            Some(_) => self.col = self.col.saturating_add(1),
+            None => {}
+        }
+        c
+    }
+}
 
-            ('\n', PreProcState::LineComment) => {
-                line += 1;
-                col = 0;
-                result = Some(' ');
-                break;
-            },
-            (_, PreProcState::LineComment) => {
-                col += 1;
-            },
+impl <I, L> Iterator for Lexer<I, L> where I: Iterator<Item=char>, L: Literal {
+    type Item = Result<Token<L::Value>>;
 
-            ('\n', PreProcState::MultiComment(_)) => {
-                line += 1;
-                col = 0;
-            },
-            ('*', PreProcState::MultiComment(level)) => {
-                match lookahead(state) {
-                    Some('/') => {
-                        if level <= 1 {
-                            next(state).unwrap(); // pop the next char
-                            ps = PreProcState::Default
-                        } else {
-                            ps = PreProcState::MultiComment(level - 1)
-                        }
-                    },
-                    _ => {}
-                }
-            },
-            ('/', PreProcState::MultiComment(level)) => {
-                match lookahead(state) {
-                    Some('*') => {
-                        ps = PreProcState::MultiComment(level + 1);
-                    },
-                    _ => {}
-                }
+    fn next(&mut self) -> Option<Result<Token<L::Value>>> {
+        if let Some(t) = self.pending.take() {
+            self.token_end = (t.line, t.col);
+            return Some(Ok(t));
+        }
+        if self.done {
+            return None;
+        }
+        let token = self.lex_token();
+        if self.too_large {
+            self.done = true;
+            let max = self.max_input_size.unwrap_or(0);
+            return Some(fail(self, ErrorType::InputTooLarge(max)));
+        }
+        match token {
+            Ok(Some(t)) => Some(Ok(t)),
+            Ok(None) => {
+                self.done = true;
+                None
             },
-            (_, PreProcState::MultiComment(_)) => {
-                col += 1;
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
             }
         }
     }
-    state.line = line;
-    state.col = col;
-    result
+}
+
+fn special_token<S>(c: char) -> Option<TokenType<S>> {
+    match c {
+        '(' => Some(TokenType::OpenParen),
+        ')' => Some(TokenType::CloseParen),
+        '{' => Some(TokenType::OpenBrace),
+        '}' => Some(TokenType::CloseBrace),
+        ';' => Some(TokenType::Semicolon),
+        _ => None
+    }
+}
+
+/// Lexes the complete input at once
+pub fn run(input: Box<dyn Iterator<Item=char>>) -> Result<Vec<Token>> {
+    Lexer::new(input).collect()
+}
+
+fn fail<T, P: CodePos>(state: &P, error_type: ErrorType) -> Result<T> {
+    Err(Error::from_state(state, error_type, None))
 }
 
 #[cfg(test)]
@@ -306,6 +494,13 @@ mod test {
         assert_eq!(unwrap_tokens(run(Box::new("\"test\"".chars()))), Ok(vec![TokenType::StringLiteral(String::from("test"))]));
     }
 
+    #[test]
+    fn successfully_parses_escapes() {
+        assert_eq!(
+            unwrap_tokens(run(Box::new(r#""a \"b\" \\ c\n""#.chars()))),
+            Ok(vec![TokenType::StringLiteral(String::from("a \"b\" \\ c\n"))]));
+    }
+
     #[test]
     fn successfully_parse_basic_tokens() {
         assert_eq!(
@@ -339,6 +534,14 @@ mod test {
             ]));
     }
 
+    #[test]
+    fn records_token_positions() {
+        let positions: Vec<(u32, u16)> = Lexer::new("option {\n  \"value\";\n}".chars())
+            .map(|t| t.map(|t| (t.line, t.col)).unwrap())
+            .collect();
+        assert_eq!(positions, vec![(1, 1), (1, 8), (2, 3), (2, 10), (3, 1)]);
+    }
+
     #[test]
     fn ignores_comments() {
         assert_eq!(
@@ -348,6 +551,73 @@ mod test {
             Ok(vec![]));
     }
 
+    #[test]
+    fn only_recognizes_enabled_comments() {
+        let options = ParseOptions::new().comments(&[CommentStyle::DoubleSlash]);
+        let lexer = Lexer::with_options("#a /*b*/ // c".chars(), String::new(), &options);
+        let tokens: Result<Vec<Token>> = lexer.collect();
+        assert_eq!(unwrap_tokens(tokens), Ok(vec![
+            TokenType::RawLiteral(String::from("#a")),
+            TokenType::RawLiteral(String::from("/*b*/"))
+        ]));
+    }
+
+    #[test]
+    fn keeps_comments_if_asked() {
+        let lexer = Lexer::new("a; # one\n/* two\n /* three */ */ b // four".chars()).keep_comments(true);
+        let tokens: Result<Vec<Token>> = lexer.collect();
+        assert_eq!(tokens, Ok(vec![
+            Token::new(1, 1, TokenType::RawLiteral(String::from("a"))),
+            Token::new(1, 2, TokenType::Semicolon),
+            Token::new(1, 4, TokenType::Comment(String::from("# one"))),
+            Token::new(2, 1, TokenType::Comment(String::from("/* two\n /* three */ */"))),
+            Token::new(3, 17, TokenType::RawLiteral(String::from("b"))),
+            Token::new(3, 19, TokenType::Comment(String::from("// four")))
+        ]));
+    }
+
+    #[test]
+    fn reports_token_ends() {
+        let mut lexer = Lexer::new("ab \"c\nd\";# x\n{ /* y\n*/".chars()).keep_comments(true);
+        let mut ends = vec![];
+        while let Some(Ok(_)) = lexer.next() {
+            let end = lexer.token_end();
+            ends.push((end.line, end.col));
+        }
+        assert_eq!(ends, vec![(1, 2), (2, 2), (2, 3), (2, 6), (3, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn treats_comment_starts_inside_tokens_as_literal() {
+        assert_eq!(
+            unwrap_tokens(run(Box::new("url http://example.com/a#b; # comment\nc// d /* e */".chars()))),
+            Ok(vec![
+                TokenType::RawLiteral(String::from("url")),
+                TokenType::RawLiteral(String::from("http://example.com/a#b")),
+                TokenType::Semicolon,
+                TokenType::RawLiteral(String::from("c//")),
+                TokenType::RawLiteral(String::from("d")),
+            ]));
+    }
+
+    #[test]
+    fn fails_on_too_large_input() {
+        let options = ParseOptions::new().max_input_size(Some(6));
+        let mut lexer = Lexer::with_options("abc defg".chars(), String::new(), &options);
+        assert_eq!(lexer.next(), Some(Ok(Token::new(1, 1, TokenType::RawLiteral(String::from("abc"))))));
+        assert_eq!(lexer.next(), Some(Err(Error::new(1, 6, ErrorType::InputTooLarge(6), None))));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn fails_on_too_long_literals() {
+        let options = ParseOptions::new().max_literal_length(Some(4));
+        let tokens: Result<Vec<Token>> = Lexer::with_options("abcd \"ab\\nc\"".chars(), String::new(), &options).collect();
+        assert!(tokens.is_ok());
+        let tokens: Result<Vec<Token>> = Lexer::with_options("abcd \"abcde".chars(), String::new(), &options).collect();
+        assert_eq!(tokens, Err(Error::new(1, 11, ErrorType::LiteralTooLong(4), None)));
+    }
+
     #[test]
     fn fails_on_unterminated_string() {
         assert_eq!(
@@ -355,4 +625,12 @@ mod test {
             Err(Error::new(1, 8, ErrorType::UnexpectedEOF, None))
             );
     }
+
+    #[test]
+    fn yields_tokens_before_an_error() {
+        let mut lexer = Lexer::new("test \"yo dawg".chars());
+        assert_eq!(lexer.next(), Some(Ok(Token::new(1, 1, TokenType::RawLiteral(String::from("test"))))));
+        assert_eq!(lexer.next(), Some(Err(Error::new(1, 13, ErrorType::UnexpectedEOF, None))));
+        assert_eq!(lexer.next(), None);
+    }
 }
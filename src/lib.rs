@@ -1,38 +1,151 @@
 #![doc(html_root_url = "https://kilobyte22.de/doc/config_parser/")]
 
+#[cfg(feature = "json")]
+#[cfg_attr(test, macro_use)]
+extern crate serde_json;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "toml")]
+extern crate toml;
+#[cfg(feature = "lsp")]
+extern crate lsp_server;
+#[cfg(feature = "lsp")]
+extern crate lsp_types;
+#[cfg(feature = "lsp")]
+extern crate serde;
+#[cfg(feature = "inotify")]
+extern crate inotify;
+extern crate zeroize;
+
+#[macro_use]
+mod macros;
+
+pub mod borrowed;
+pub mod builder;
+pub mod condition;
 pub mod config;
+#[cfg(feature = "json")]
+pub mod convert;
+pub mod diagnostic;
+pub mod dialect;
+pub mod diff;
 pub mod error;
+pub mod formatter;
+pub mod include;
 pub mod lexer;
+pub mod lint;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod merge;
+pub mod options;
 pub mod parser;
+pub mod patch;
+pub mod path;
+pub mod profile;
+pub mod redact;
+mod reader;
+pub mod schema;
+pub mod secret;
+pub mod span;
+pub mod template;
+pub mod watch;
+pub mod writer;
 
+pub use builder::ConfigBuilder;
 pub use config::ConfigBlock;
+pub use diagnostic::{Diagnostic, Severity};
 pub use error::{Result, Error as ParseError};
+pub use options::{CommentStyle, Conditionals, ParseOptions};
+pub use parser::{Event, Events};
+pub use schema::{DirectiveSchema, Schema};
+pub use span::{Position, Span};
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufRead, BufReader};
 
 /// Parses a configuration file by an iterator of chars
 pub fn parse<T, I>(iter: T) -> Result<ConfigBlock> where 
         T: IntoIterator<Item=char, IntoIter=I> + Sized,
-        I: Iterator<Item=char> + 'static {
-    parser::run(Box::new(try!(lexer::run(Box::new(iter.into_iter()))).into_iter()))
+        I: Iterator<Item=char> {
+    parse_with(iter, &ParseOptions::default())
+}
+
+/// Parses a configuration file by an iterator of chars with the given options
+pub fn parse_with<T, I>(iter: T, options: &ParseOptions) -> Result<ConfigBlock> where
+        T: IntoIterator<Item=char, IntoIter=I> + Sized,
+        I: Iterator<Item=char> {
+    let lexer = lexer::Lexer::with_options(iter.into_iter(), String::new(), options);
+    parser::run_with(Box::new(lexer), options)
+}
+
+/// Parses a configuration file by an iterator of chars without building a tree. The
+/// returned iterator produces one event per block start, directive and block end
+pub fn parse_events<'a, T, I>(iter: T) -> Events<'a> where
+        T: IntoIterator<Item=char, IntoIter=I> + Sized,
+        I: Iterator<Item=char> + 'a {
+    parse_events_with(iter, &ParseOptions::default())
+}
+
+/// Like `parse_events()`, but with the given options. The syntax of conditions and
+/// secret references is accepted, but nothing is evaluated: `if` and `else` options come
+/// through like with `Conditionals::KeepBranches` and references as `secret(...)`
+/// parameters
+pub fn parse_events_with<'a, T, I>(iter: T, options: &ParseOptions) -> Events<'a> where
+        T: IntoIterator<Item=char, IntoIter=I> + Sized,
+        I: Iterator<Item=char> + 'a {
+    let lexer = lexer::Lexer::with_options(iter.into_iter(), String::new(), options);
+    Events::with_options(parser::adapt(Box::new(lexer), options, &Default::default(), &Default::default()), options)
+}
+
+/// Parses a configuration file while reading it. The input is decoded as UTF-8 on the
+/// fly, so it is never held in memory as a whole
+pub fn parse_reader<R: BufRead>(reader: R) -> Result<ConfigBlock> {
+    parse_reader_with(reader, &ParseOptions::default())
+}
+
+/// Like `parse_reader()`, but with the given options
+pub fn parse_reader_with<R: BufRead>(reader: R, options: &ParseOptions) -> Result<ConfigBlock> {
+    let mut chars = reader::ReadChars::new(reader);
+    let result = parse_with(&mut chars, options);
+    match chars.take_error() {
+        Some(e) => Err(e),
+        None => result
+    }
+}
+
+/// Parses a configuration file without copying its contents. See `borrowed` for details
+pub fn parse_str(data: &str) -> Result<borrowed::ConfigBlock<'_>> {
+    borrowed::parse(data)
+}
+
+/// Like `parse_str()`, but with the given options
+pub fn parse_str_with<'a>(data: &'a str, options: &ParseOptions) -> Result<borrowed::ConfigBlock<'a>> {
+    borrowed::parse_with(data, options)
 }
 
 pub fn parse_string(data: String) -> Result<ConfigBlock> {
     parse(OwningChars::new(data))
 }
 
-pub fn parse_file(mut file: File) -> Result<ConfigBlock> {
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
-    parse_string(s)
+/// Like `parse_string()`, but with the given options
+pub fn parse_string_with(data: String, options: &ParseOptions) -> Result<ConfigBlock> {
+    parse_with(OwningChars::new(data), options)
+}
+
+pub fn parse_file(file: File) -> Result<ConfigBlock> {
+    parse_reader(BufReader::new(file))
+}
+
+/// Like `parse_file()`, but with the given options
+pub fn parse_file_with(file: File, options: &ParseOptions) -> Result<ConfigBlock> {
+    parse_reader_with(BufReader::new(file), options)
 }
 
 struct OwningChars { s: String, pos: usize }
 
 impl OwningChars {
     pub fn new(s: String) -> OwningChars {
-        OwningChars { s: s, pos: 0 }
+        OwningChars { s, pos: 0 }
     }
 }
 
@@ -48,7 +161,7 @@ impl Iterator for OwningChars {
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
         let len = self.s.len() - self.pos;
-        ((len + 3) / 4, Some(len)) // see the Chars impl for detail
+        (len.div_ceil(4), Some(len)) // see the Chars impl for detail
     }
 }
 
@@ -0,0 +1,373 @@
+//! Reports questionable but valid constructs in config files
+//!
+//! A `Linter` runs a set of rules over a file. Each rule sees the tokens, including
+//! comments, and every block of the parsed tree, and reports warnings tagged with its
+//! name. Applications can add their own rules by implementing `Rule`:
+//!
+//! ```
+//! use config_parser::{ConfigBlock, Diagnostic};
+//! use config_parser::lint::{Linter, Rule};
+//!
+//! struct NoRootChannels;
+//!
+//! impl Rule for NoRootChannels {
+//!     fn name(&self) -> &str {
+//!         "root-channel"
+//!     }
+//!
+//!     fn check_block(&self, block: &ConfigBlock, path: &[&str], out: &mut Vec<Diagnostic>) {
+//!         if path.is_empty() {
+//!             for channel in block.matching("channel") {
+//!                 out.push(Diagnostic::warning("channels belong into a server block", channel.span()));
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! let warnings = Linter::new().rule(NoRootChannels).lint("channel \"#rust\" \"key\";").unwrap();
+//! assert_eq!(warnings[0].to_string(), "1:1: warning[root-channel]: channels belong into a server block");
+//! assert_eq!(warnings[1].to_string(), "1:17: warning[unnecessary-quotes]: `key` does not need quotes");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use super::config::ConfigBlock;
+use super::diagnostic::Diagnostic;
+use super::error::Result;
+use super::lexer::{Lexer, Token, TokenType};
+use super::options::ParseOptions;
+use super::parser;
+use super::schema::Schema;
+use super::span::Span;
+use super::writer::quote;
+
+/// A token together with the source range it covers
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span
+}
+
+/// A check run by the linter
+///
+/// Both hooks do nothing by default, so a rule only implements what it needs.
+/// Diagnostics reported by a rule get its name as code.
+pub trait Rule {
+    /// A short identifier like `empty-block`, used to disable the rule
+    fn name(&self) -> &str;
+
+    /// Checks the complete token stream of the file, comments included
+    fn check_tokens(&self, _tokens: &[SpannedToken], _out: &mut Vec<Diagnostic>) {}
+
+    /// Checks a block of the tree. Called for the root and every option in it, parents
+    /// first. `path` holds the names of the enclosing options, the root has an empty path
+    fn check_block(&self, _block: &ConfigBlock, _path: &[&str], _out: &mut Vec<Diagnostic>) {}
+}
+
+/// Runs rules over config files
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>
+}
+
+impl Linter {
+    /// Creates a linter with the rules which need no schema: `EmptyBlock`,
+    /// `StraySemicolon` and `UnnecessaryQuotes`
+    pub fn new() -> Linter {
+        Linter::empty()
+            .rule(EmptyBlock)
+            .rule(StraySemicolon)
+            .rule(UnnecessaryQuotes)
+    }
+
+    /// Creates a linter without any rules
+    pub fn empty() -> Linter {
+        Linter { rules: vec![] }
+    }
+
+    /// Adds a rule
+    pub fn rule<R: Rule + 'static>(mut self, rule: R) -> Linter {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Adds the rules driven by a schema: `DuplicateUnique` and `Deprecated`
+    pub fn schema_rules(self, schema: &Schema) -> Linter {
+        self.rule(DuplicateUnique::new(schema.clone()))
+            .rule(Deprecated::new(schema.clone()))
+    }
+
+    /// Removes all rules with the given name
+    pub fn disable(mut self, name: &str) -> Linter {
+        self.rules.retain(|r| r.name() != name);
+        self
+    }
+
+    /// Returns the names of the active rules
+    pub fn rule_names(&self) -> Vec<&str> {
+        self.rules.iter().map(|r| r.name()).collect()
+    }
+
+    /// Lints a file with the default parse options
+    pub fn lint(&self, source: &str) -> Result<Vec<Diagnostic>> {
+        self.lint_with(source, &ParseOptions::default())
+    }
+
+    /// Lints a file. Fails with the parse error if the file is invalid under the given
+    /// options. Warnings are sorted by position
+    pub fn lint_with(&self, source: &str, options: &ParseOptions) -> Result<Vec<Diagnostic>> {
+        let cfg = super::parse_string_with(String::from(source), options)?;
+        let mut lexer = Lexer::with_options(source.chars(), String::new(), options).keep_comments(true);
+        let mut tokens = vec![];
+        while let Some(token) = lexer.next() {
+            let token = token?;
+            let span = Span::new(token.position(), lexer.token_end());
+            tokens.push((token, span));
+        }
+        // Rules see conditions and secret references like the parser does
+        let tokens: Vec<SpannedToken> = parser::adapt_spanned(tokens, options)?.into_iter()
+            .map(|(token, span)| SpannedToken { token, span })
+            .collect();
+
+        let mut out = vec![];
+        for rule in &self.rules {
+            let mut found = vec![];
+            rule.check_tokens(&tokens, &mut found);
+            walk(&cfg, |block, path| rule.check_block(block, path, &mut found));
+            out.extend(found.into_iter().map(|d| match d.code {
+                Some(_) => d,
+                None => d.with_code(rule.name())
+            }));
+        }
+        out.sort_by_key(|d| d.span.map(|s| s.start));
+        Ok(out)
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Linter {
+        Linter::new()
+    }
+}
+
+/// Calls `f` for the root and all options below it, parents first. Iterative, so deeply
+/// nested trees don't overflow the stack
+pub(crate) fn walk<'a, F>(root: &'a ConfigBlock, mut f: F) where F: FnMut(&'a ConfigBlock, &[&'a str]) {
+    f(root, &[]);
+    let mut path: Vec<&str> = vec![];
+    let mut stack: Vec<(&ConfigBlock, usize)> = root.inner().iter().rev().map(|b| (b, 0)).collect();
+    while let Some((block, depth)) = stack.pop() {
+        path.truncate(depth);
+        f(block, &path);
+        path.push(block.name());
+        stack.extend(block.inner().iter().rev().map(|b| (b, depth + 1)));
+    }
+}
+
+/// Warns about blocks without any options or comments, like `server a {}`
+pub struct EmptyBlock;
+
+impl Rule for EmptyBlock {
+    fn name(&self) -> &str {
+        "empty-block"
+    }
+
+    fn check_tokens(&self, tokens: &[SpannedToken], out: &mut Vec<Diagnostic>) {
+        for pair in tokens.windows(2) {
+            if let (&TokenType::OpenBrace, &TokenType::CloseBrace) = (&pair[0].token.token_type, &pair[1].token.token_type) {
+                out.push(Diagnostic::warning("empty block", Some(Span::new(pair[0].span.start, pair[1].span.end))));
+            }
+        }
+    }
+}
+
+/// Warns about semicolons which don't end an option, like the one in `};`
+pub struct StraySemicolon;
+
+impl Rule for StraySemicolon {
+    fn name(&self) -> &str {
+        "stray-semicolon"
+    }
+
+    fn check_tokens(&self, tokens: &[SpannedToken], out: &mut Vec<Diagnostic>) {
+        let mut ends_option = false;
+        for t in tokens {
+            match t.token.token_type {
+                TokenType::Comment(_) => {},
+                TokenType::Semicolon if !ends_option => {
+                    out.push(Diagnostic::warning("unnecessary `;`", Some(t.span)));
+                },
+                TokenType::StringLiteral(_) | TokenType::RawLiteral(_) => ends_option = true,
+                _ => ends_option = false
+            }
+        }
+    }
+}
+
+/// Warns about quoted parameters which would be read the same without quotes
+pub struct UnnecessaryQuotes;
+
+impl Rule for UnnecessaryQuotes {
+    fn name(&self) -> &str {
+        "unnecessary-quotes"
+    }
+
+    fn check_tokens(&self, tokens: &[SpannedToken], out: &mut Vec<Diagnostic>) {
+        for t in tokens {
+            if let TokenType::StringLiteral(ref s) = t.token.token_type {
+                if let Cow::Borrowed(_) = quote(s) {
+                    out.push(Diagnostic::warning(format!("`{}` does not need quotes", s), Some(t.span)));
+                }
+            }
+        }
+    }
+}
+
+/// Warns about repeated options which the schema marks as unique
+pub struct DuplicateUnique {
+    schema: Schema
+}
+
+impl DuplicateUnique {
+    pub fn new(schema: Schema) -> DuplicateUnique {
+        DuplicateUnique { schema }
+    }
+}
+
+impl Rule for DuplicateUnique {
+    fn name(&self) -> &str {
+        "duplicate"
+    }
+
+    fn check_block(&self, block: &ConfigBlock, path: &[&str], out: &mut Vec<Diagnostic>) {
+        let mut full_path = path.to_vec();
+        if !block.name().is_empty() {
+            full_path.push(block.name());
+        }
+        let schema = match self.schema.lookup(&full_path) {
+            Some(schema) => schema,
+            None => return
+        };
+        let mut first: HashMap<&str, &ConfigBlock> = HashMap::new();
+        for item in block.inner() {
+            match schema.get(item.name()) {
+                Some(d) if d.is_unique() => {},
+                _ => continue
+            }
+            match first.get(item.name()) {
+                Some(previous) => {
+                    let message = match previous.span() {
+                        Some(span) => format!("`{}` is repeated, it was first given at {}", item.name(), span.start),
+                        None => format!("`{}` is repeated", item.name())
+                    };
+                    out.push(Diagnostic::warning(message, item.span()));
+                },
+                None => {
+                    first.insert(item.name(), item);
+                }
+            }
+        }
+    }
+}
+
+/// Warns about options which the schema marks as deprecated
+pub struct Deprecated {
+    schema: Schema
+}
+
+impl Deprecated {
+    pub fn new(schema: Schema) -> Deprecated {
+        Deprecated { schema }
+    }
+}
+
+impl Rule for Deprecated {
+    fn name(&self) -> &str {
+        "deprecated"
+    }
+
+    fn check_block(&self, block: &ConfigBlock, path: &[&str], out: &mut Vec<Diagnostic>) {
+        if block.name().is_empty() {
+            return;
+        }
+        let note = match self.schema.lookup(path).and_then(|s| s.get(block.name())).and_then(|d| d.deprecation()) {
+            Some(note) => note,
+            None => return
+        };
+        let message = if note.is_empty() {
+            format!("`{}` is deprecated", block.name())
+        } else {
+            format!("`{}` is deprecated: {}", block.name(), note)
+        };
+        out.push(Diagnostic::warning(message, block.span()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::schema::DirectiveSchema;
+
+    fn lint(linter: &Linter, source: &str) -> Vec<String> {
+        linter.lint(source).unwrap().iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let source = "a {};\nb { # not empty\n}\nc \"x\" \"y z\" \"\";;\n";
+        assert_eq!(lint(&Linter::new(), source), vec![
+            "1:3: warning[empty-block]: empty block",
+            "1:5: warning[stray-semicolon]: unnecessary `;`",
+            "4:3: warning[unnecessary-quotes]: `x` does not need quotes",
+            "4:16: warning[stray-semicolon]: unnecessary `;`"
+        ]);
+        assert!(lint(&Linter::new().disable("stray-semicolon"), source).iter().all(|d| !d.contains("stray")));
+        assert_eq!(Linter::new().rule_names(), vec!["empty-block", "stray-semicolon", "unnecessary-quotes"]);
+    }
+
+    #[test]
+    fn test_schema_rules() {
+        let schema = Schema::new()
+            .directive(DirectiveSchema::new("server").unique(true).block(Schema::new()
+                .directive(DirectiveSchema::new("nick").unique(true).deprecated("use user"))
+                .directive(DirectiveSchema::new("user"))));
+        let source = "server a {\n    nick x;\n    nick y;\n    user z;\n}\nnick q;\nnick r;";
+        assert_eq!(lint(&Linter::empty().schema_rules(&schema), source), vec![
+            "2:5: warning[deprecated]: `nick` is deprecated: use user",
+            "3:5: warning[duplicate]: `nick` is repeated, it was first given at 2:5",
+            "3:5: warning[deprecated]: `nick` is deprecated: use user"
+        ]);
+    }
+
+    #[test]
+    fn test_paths_passed_to_rules() {
+        struct Paths;
+        impl Rule for Paths {
+            fn name(&self) -> &str {
+                "paths"
+            }
+            fn check_block(&self, block: &ConfigBlock, path: &[&str], out: &mut Vec<Diagnostic>) {
+                out.push(Diagnostic::warning(format!("{}/{}", path.join("/"), block.name()), block.span()));
+            }
+        }
+        assert_eq!(lint(&Linter::empty().rule(Paths), "a { b { c; } d; }"), vec![
+            "warning[paths]: /",
+            "1:1: warning[paths]: /a",
+            "1:5: warning[paths]: a/b",
+            "1:9: warning[paths]: a/b/c",
+            "1:14: warning[paths]: a/d"
+        ]);
+    }
+
+    #[test]
+    fn test_adapted_syntax() {
+        let options = ParseOptions::new().secrets(true).conditionals(::options::Conditionals::KeepBranches);
+        let source = "pw secret(\"env:X\");\nif (a == b) { x; };";
+        let found: Vec<String> = Linter::new().lint_with(source, &options).unwrap().iter().map(|d| d.to_string()).collect();
+        assert_eq!(found, vec!["2:19: warning[stray-semicolon]: unnecessary `;`"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Linter::new().lint("a {").is_err());
+    }
+}
@@ -0,0 +1,587 @@
+//! A language server for config files
+//!
+//! The server keeps the open documents in memory and offers:
+//!
+//! * diagnostics from the parser, the schema and the linter, published on every change
+//! * document symbols following the block structure
+//! * go to definition on the file name of an `include` option, resolved relative to
+//!   the including file. The format has no variables, so there is nothing else to jump to
+//! * hover documentation and completion of option names, taken from the schema
+//! * formatting with the `formatter` module
+//!
+//! `Server::run_stdio` serves a client over standard input and output, which is what
+//! the `config-parser-lsp` binary does. Tests can use `Server::run` with
+//! `lsp_server::Connection::memory()`.
+
+use std::collections::HashMap;
+use std::error;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{self, CompletionItem, CompletionItemKind, CompletionItemTag, CompletionParams,
+    CompletionResponse, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location, LogMessageParams,
+    MarkupContent, MarkupKind, MessageType, NumberOrString, OneOf, PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextEdit,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url};
+use lsp_types::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, LogMessage,
+    Notification as _, PublishDiagnostics};
+use lsp_types::request::{Completion, DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, Request as _};
+use serde_json::{self, Value};
+use super::config::ConfigBlock;
+use super::diagnostic::{Diagnostic, Severity};
+use super::formatter::{self, Style};
+use super::lexer::{Lexer, TokenType};
+use super::lint::{self, Linter};
+use super::options::ParseOptions;
+use super::schema::{DirectiveSchema, Schema};
+use super::span::{Position, Span};
+
+pub type Result<T> = ::std::result::Result<T, Box<dyn error::Error + Send + Sync>>;
+
+/// The state of a language server session
+pub struct Server {
+    schema: Option<Schema>,
+    options: ParseOptions,
+    documents: HashMap<Url, String>
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            schema: None,
+            options: ParseOptions::default(),
+            documents: HashMap::new()
+        }
+    }
+
+    /// Sets the schema used for validation, hover and completion
+    pub fn schema(mut self, schema: Schema) -> Server {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Sets the options documents are parsed with
+    pub fn parse_options(mut self, options: ParseOptions) -> Server {
+        self.options = options;
+        self
+    }
+
+    /// Returns what the server supports, as sent in the `initialize` response
+    pub fn capabilities() -> ServerCapabilities {
+        ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            document_symbol_provider: Some(OneOf::Left(true)),
+            definition_provider: Some(OneOf::Left(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            completion_provider: Some(Default::default()),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        }
+    }
+
+    /// Serves a client over standard input and output until it shuts the server down
+    pub fn run_stdio(self) -> Result<()> {
+        let (connection, threads) = Connection::stdio();
+        self.run(&connection)?;
+        threads.join()?;
+        Ok(())
+    }
+
+    /// Initializes the connection and handles messages until the client shuts the
+    /// server down
+    pub fn run(mut self, connection: &Connection) -> Result<()> {
+        connection.initialize(serde_json::to_value(Server::capabilities())?)?;
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    connection.sender.send(Message::Response(self.handle_request(request)))?;
+                },
+                Message::Notification(notification) => {
+                    let method = notification.method.clone();
+                    match self.handle_notification(notification) {
+                        Ok(Some(diagnostics)) => connection.sender.send(Message::Notification(diagnostics))?,
+                        Ok(None) => {},
+                        // Notifications have no response, so the client only gets a log message
+                        Err(e) => {
+                            let params = LogMessageParams { typ: MessageType::WARNING, message: format!("ignoring {}: {}", method, e) };
+                            connection.sender.send(Message::Notification(Notification::new(String::from(LogMessage::METHOD), params)))?;
+                        }
+                    }
+                },
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match &*request.method {
+            DocumentSymbolRequest::METHOD => self.request(request, |s, p: DocumentSymbolParams| s.symbols(p)),
+            GotoDefinition::METHOD => self.request(request, |s, p: GotoDefinitionParams| s.definition(p)),
+            HoverRequest::METHOD => self.request(request, |s, p: HoverParams| s.hover(p)),
+            Completion::METHOD => self.request(request, |s, p: CompletionParams| s.completion(p)),
+            Formatting::METHOD => self.request(request, |s, p: DocumentFormattingParams| s.format(p)),
+            method => return Response::new_err(id, ErrorCode::MethodNotFound as i32, format!("unsupported request {}", method))
+        };
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string())
+        }
+    }
+
+    fn request<P, R, F>(&self, request: Request, f: F) -> Result<Value> where
+            P: ::serde::de::DeserializeOwned,
+            R: ::serde::Serialize,
+            F: FnOnce(&Server, P) -> Option<R> {
+        let params = serde_json::from_value(request.params)?;
+        Ok(serde_json::to_value(f(self, params))?)
+    }
+
+    /// Updates the documents, returning new diagnostics to publish
+    fn handle_notification(&mut self, notification: Notification) -> Result<Option<Notification>> {
+        let uri = match &*notification.method {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.documents.insert(params.text_document.uri.clone(), params.text_document.text);
+                params.text_document.uri
+            },
+            DidChangeTextDocument::METHOD => {
+                let mut params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+                if let Some(change) = params.content_changes.pop() {
+                    self.documents.insert(params.text_document.uri.clone(), change.text);
+                }
+                params.text_document.uri
+            },
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                params.text_document.uri
+            },
+            _ => return Ok(None)
+        };
+        let diagnostics = match self.documents.get(&uri) {
+            Some(text) => self.diagnostics(text).iter().map(|d| to_lsp_diagnostic(text, d)).collect(),
+            None => vec![]
+        };
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        Ok(Some(Notification::new(String::from(PublishDiagnostics::METHOD), params)))
+    }
+
+    /// Returns the problems in a document
+    pub fn diagnostics(&self, text: &str) -> Vec<Diagnostic> {
+        let cfg = match super::parse_string_with(String::from(text), &self.options) {
+            Ok(cfg) => cfg,
+            Err(e) => return vec![Diagnostic::from(&e)]
+        };
+        let mut out = vec![];
+        let mut linter = Linter::new();
+        if let Some(ref schema) = self.schema {
+            out.extend(schema.validate(&cfg));
+            linter = linter.schema_rules(schema);
+        }
+        out.extend(linter.lint_with(text, &self.options).unwrap_or_default());
+        out
+    }
+
+    fn parse(&self, uri: &Url) -> Option<(&str, ConfigBlock)> {
+        let text = self.documents.get(uri)?;
+        let cfg = super::parse_string_with(text.clone(), &self.options).ok()?;
+        Some((text, cfg))
+    }
+
+    fn symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let (text, cfg) = self.parse(&params.text_document.uri)?;
+        Some(DocumentSymbolResponse::Nested(cfg.inner().iter().filter_map(|b| symbol(text, b)).collect()))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (text, cfg) = self.parse(&position.text_document.uri)?;
+        let cursor = from_lsp_position(text, position.position);
+        let mut found = None;
+        lint::walk(&cfg, |block, _| {
+            if block.name() == "include" && block.span().map(|s| s.contains(cursor)).unwrap_or(false) {
+                found = block.get_opt(0).map(String::from);
+            }
+        });
+        let file = position.text_document.uri.to_file_path().ok()?;
+        let target = file.parent()?.join(found?);
+        if !target.exists() {
+            return None;
+        }
+        let start = lsp_types::Position::new(0, 0);
+        Some(GotoDefinitionResponse::Scalar(Location::new(Url::from_file_path(target).ok()?, Range::new(start, start))))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (text, cfg) = self.parse(&position.text_document.uri)?;
+        let schema = self.schema.as_ref()?;
+        let cursor = from_lsp_position(text, position.position);
+        let mut found = None;
+        lint::walk(&cfg, |block, path| {
+            if let Some(span) = name_span(block) {
+                if span.contains(cursor) {
+                    found = schema.lookup(path).and_then(|s| s.get(block.name())).map(|d| (d, span));
+                }
+            }
+        });
+        let (directive, span) = found?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value: describe(directive) }),
+            range: Some(to_lsp_range(text, span))
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let text = self.documents.get(&position.text_document.uri)?;
+        let schema = self.schema.as_ref()?;
+        let path = completion_context(text, from_lsp_position(text, position.position), &self.options)?;
+        let names: Vec<&str> = path.iter().map(|s| &**s).collect();
+        let items = schema.lookup(&names)?.directives().iter().map(|d| CompletionItem {
+            label: String::from(d.name()),
+            kind: Some(if d.block_schema().is_some() { CompletionItemKind::MODULE } else { CompletionItemKind::PROPERTY }),
+            detail: Some(param_summary(d)),
+            documentation: d.documentation().map(|doc| lsp_types::Documentation::String(String::from(doc))),
+            tags: d.deprecation().map(|_| vec![CompletionItemTag::DEPRECATED]),
+            ..Default::default()
+        }).collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn format(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let text = self.documents.get(&params.text_document.uri)?;
+        let style = Style::new()
+            .indent_width(params.options.tab_size as usize)
+            .tabs(!params.options.insert_spaces);
+        let formatted = formatter::format_with(text, &style, &self.options).ok()?;
+        if formatted == *text {
+            return Some(vec![]);
+        }
+        let end = lsp_position(text, text.split('\n').count() as u32, usize::MAX);
+        Some(vec![TextEdit::new(Range::new(lsp_types::Position::new(0, 0), end), formatted)])
+    }
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+/// Returns the names of the blocks enclosing the cursor if it is where an option name
+/// goes, reading the document only up to the cursor so that errors after it don't matter
+fn completion_context(text: &str, cursor: Position, options: &ParseOptions) -> Option<Vec<String>> {
+    let mut lexer = Lexer::with_options(text.chars(), String::new(), options);
+    let mut path = vec![];
+    // The option name of the current statement and whether it is still being typed
+    let mut current: Option<(String, bool)> = None;
+    while let Some(Ok(token)) = lexer.next() {
+        if token.position() >= cursor {
+            break;
+        }
+        let end = lexer.token_end();
+        let touches_cursor = end.line == cursor.line && end.col + 1 >= cursor.col;
+        current = match (token.token_type, current) {
+            (TokenType::RawLiteral(name), None) | (TokenType::StringLiteral(name), None) => Some((name, touches_cursor)),
+            (TokenType::RawLiteral(_), Some((name, _))) | (TokenType::StringLiteral(_), Some((name, _))) => Some((name, false)),
+            (TokenType::OpenBrace, current) => {
+                path.push(current.map(|c| c.0).unwrap_or_default());
+                None
+            },
+            (TokenType::CloseBrace, _) => {
+                path.pop();
+                None
+            },
+            _ => None
+        };
+    }
+    match current {
+        None | Some((_, true)) => Some(path),
+        Some(_) => None
+    }
+}
+
+fn describe(directive: &DirectiveSchema) -> String {
+    let mut out = format!("```\n{}\n```\n{}", directive.name(), param_summary(directive));
+    if let Some(doc) = directive.documentation() {
+        out.push_str("\n\n");
+        out.push_str(doc);
+    }
+    if let Some(note) = directive.deprecation() {
+        out.push_str("\n\n*Deprecated*");
+        if !note.is_empty() {
+            out.push_str(": ");
+            out.push_str(note);
+        }
+    }
+    out
+}
+
+fn param_summary(directive: &DirectiveSchema) -> String {
+    let params = match directive.param_range() {
+        (0, None) => String::from("any number of parameters"),
+        (min, Some(max)) if min == max => format!("{} parameters", min),
+        (min, Some(max)) => format!("{} to {} parameters", min, max),
+        (min, None) => format!("at least {} parameters", min)
+    };
+    match directive.block_schema() {
+        Some(_) => params + ", takes a block",
+        None => params
+    }
+}
+
+fn symbol(text: &str, block: &ConfigBlock) -> Option<DocumentSymbol> {
+    let span = block.span()?;
+    #[allow(deprecated)]
+    Some(DocumentSymbol {
+        name: String::from(block.name()),
+        detail: if block.is_empty() { None } else { Some(block.params().join(" ")) },
+        kind: if block.inner().is_empty() { SymbolKind::PROPERTY } else { SymbolKind::OBJECT },
+        tags: None,
+        deprecated: None,
+        range: to_lsp_range(text, span),
+        selection_range: to_lsp_range(text, name_span(block)?),
+        children: if block.inner().is_empty() {
+            None
+        } else {
+            Some(block.inner().iter().filter_map(|b| symbol(text, b)).collect())
+        }
+    })
+}
+
+/// Returns the range of the option name, which starts every option
+fn name_span(block: &ConfigBlock) -> Option<Span> {
+    let start = block.span()?.start;
+    let len = block.name().chars().count().max(1);
+    Some(Span::new(start, Position::new(start.line, column(start.col as usize + len - 1))))
+}
+
+fn to_lsp_diagnostic(text: &str, d: &Diagnostic) -> lsp_types::Diagnostic {
+    let start = lsp_types::Position::new(0, 0);
+    lsp_types::Diagnostic {
+        range: d.span.map(|s| to_lsp_range(text, s)).unwrap_or(Range::new(start, start)),
+        severity: Some(match d.severity {
+            Severity::Error => lsp_types::DiagnosticSeverity::ERROR,
+            Severity::Warning => lsp_types::DiagnosticSeverity::WARNING
+        }),
+        code: d.code.clone().map(NumberOrString::String),
+        source: Some(String::from("config-parser")),
+        message: d.message.clone(),
+        ..Default::default()
+    }
+}
+
+/// Converts a span, whose end is the last character, into a range with an exclusive end
+fn to_lsp_range(text: &str, span: Span) -> Range {
+    Range::new(
+        lsp_position(text, span.start.line, (span.start.col as usize).saturating_sub(1)),
+        lsp_position(text, span.end.line, span.end.col as usize))
+}
+
+/// Returns the LSP position of the character after the first `chars` characters of the
+/// 1-based `line`. LSP counts UTF-16 code units
+fn lsp_position(text: &str, line: u32, chars: usize) -> lsp_types::Position {
+    let line = line.max(1);
+    let character = text.split('\n').nth(line as usize - 1)
+        .map(|l| l.chars().take(chars).map(char::len_utf16).sum::<usize>())
+        .unwrap_or(0);
+    lsp_types::Position::new(line - 1, character as u32)
+}
+
+/// Converts an LSP position into the position of the character at it
+fn from_lsp_position(text: &str, position: lsp_types::Position) -> Position {
+    let mut units = 0;
+    let mut chars = 0usize;
+    if let Some(line) = text.split('\n').nth(position.line as usize) {
+        for c in line.chars() {
+            if units >= position.character as usize {
+                break;
+            }
+            units += c.len_utf16();
+            chars += 1;
+        }
+    }
+    Position::new(position.line.saturating_add(1), column(chars + 1))
+}
+
+/// Converts a 1-based column, saturating like the lexer does on long lines
+fn column(col: usize) -> u16 {
+    col.min(u16::MAX as usize) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{env, fs, thread};
+    use lsp_server::RequestId;
+
+    const SCHEMA: &str = "directive server {
+        doc \"An IRC server\";
+        params 1;
+        block {
+            directive nick { params 1; doc \"The nickname\"; }
+            directive channel { params 1 2; }
+        }
+    }
+    directive include { params 1; }";
+
+    struct Client {
+        connection: Connection,
+        server: thread::JoinHandle<()>,
+        next_id: i32
+    }
+
+    impl Client {
+        fn start() -> Client {
+            let (server, client) = Connection::memory();
+            let schema = Schema::from_config(&::parse_string(String::from(SCHEMA)).unwrap()).unwrap();
+            let server = thread::spawn(move || Server::new().schema(schema).run(&server).unwrap());
+            let mut client = Client { connection: client, server, next_id: 0 };
+            client.request("initialize", json!({ "capabilities": {} }));
+            client.notify("initialized", json!({}));
+            client
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> Value {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            self.connection.sender.send(Message::Request(Request::new(id.clone(), String::from(method), params))).unwrap();
+            loop {
+                match self.connection.receiver.recv().unwrap() {
+                    Message::Response(r) if r.id == id => {
+                        assert!(r.error.is_none(), "{:?}", r.error);
+                        return r.result.unwrap_or(Value::Null);
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        fn notify(&self, method: &str, params: Value) {
+            self.connection.sender.send(Message::Notification(Notification::new(String::from(method), params))).unwrap();
+        }
+
+        fn open(&self, uri: &str, text: &str) -> Value {
+            self.notify("textDocument/didOpen", json!({
+                "textDocument": { "uri": uri, "languageId": "config", "version": 1, "text": text }
+            }));
+            match self.connection.receiver.recv().unwrap() {
+                Message::Notification(n) => n.params,
+                other => panic!("{:?}", other)
+            }
+        }
+
+        fn stop(mut self) {
+            self.request("shutdown", Value::Null);
+            self.notify("exit", Value::Null);
+            self.server.join().unwrap();
+        }
+    }
+
+    fn at(uri: &str, line: u32, character: u32) -> Value {
+        json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let client = Client::start();
+        let published = client.open("file:///a.cfg", "server a {\n    nick \"b\";\n    user c;\n}");
+        assert_eq!(published["diagnostics"], json!([
+            {
+                "range": { "start": { "line": 2, "character": 4 }, "end": { "line": 2, "character": 11 } },
+                "severity": 1, "source": "config-parser", "message": "unknown option `user`"
+            },
+            {
+                "range": { "start": { "line": 1, "character": 9 }, "end": { "line": 1, "character": 12 } },
+                "severity": 2, "code": "unnecessary-quotes", "source": "config-parser", "message": "`b` does not need quotes"
+            }
+        ]));
+        let published = client.open("file:///b.cfg", "a {");
+        assert_eq!(published["diagnostics"][0]["message"], "unexpected end of file, expected }");
+        client.stop();
+    }
+
+    #[test]
+    fn test_symbols_hover_and_completion() {
+        let mut client = Client::start();
+        client.open("file:///a.cfg", "server a {\n    nick b;\n    \n}");
+        let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": "file:///a.cfg" } }));
+        assert_eq!(symbols[0]["name"], "server");
+        assert_eq!(symbols[0]["detail"], "a");
+        assert_eq!(symbols[0]["range"], json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 3, "character": 1 } }));
+        assert_eq!(symbols[0]["children"][0]["name"], "nick");
+
+        let hover = client.request("textDocument/hover", at("file:///a.cfg", 1, 6));
+        assert_eq!(hover["contents"]["value"], "```\nnick\n```\n1 parameters\n\nThe nickname");
+
+        let completion = client.request("textDocument/completion", at("file:///a.cfg", 2, 4));
+        let labels: Vec<&str> = completion.as_array().unwrap().iter().map(|i| i["label"].as_str().unwrap()).collect();
+        assert_eq!(labels, vec!["nick", "channel"]);
+        let completion = client.request("textDocument/completion", at("file:///a.cfg", 1, 9));
+        assert_eq!(completion, Value::Null);
+        client.stop();
+    }
+
+    #[test]
+    fn test_formatting_and_includes() {
+        let dir = env::temp_dir().join(format!("config-parser-lsp-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("other.cfg"), "nick x;").unwrap();
+        let uri = Url::from_file_path(dir.join("main.cfg")).unwrap().to_string();
+
+        let mut client = Client::start();
+        client.open(&uri, "include other.cfg;\nserver a{nick b;}");
+        let definition = client.request("textDocument/definition", at(&uri, 0, 10));
+        assert_eq!(definition["uri"], Url::from_file_path(dir.join("other.cfg")).unwrap().to_string());
+
+        let edits = client.request("textDocument/formatting", json!({
+            "textDocument": { "uri": uri }, "options": { "tabSize": 2, "insertSpaces": true }
+        }));
+        assert_eq!(edits, json!([{
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 17 } },
+            "newText": "include other.cfg;\nserver a {\n  nick b;\n}\n"
+        }]));
+        client.stop();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_position_conversion() {
+        let text = "a \"😀\" b;";
+        let position = from_lsp_position(text, lsp_types::Position::new(0, 7));
+        assert_eq!(position, Position::new(1, 7));
+        assert_eq!(lsp_position(text, 1, 6), lsp_types::Position::new(0, 7));
+
+        let long = format!("{}abcdef;", " ".repeat(70000));
+        assert_eq!(from_lsp_position(&long, lsp_types::Position::new(u32::MAX, 0)), Position::new(u32::MAX, 1));
+        assert_eq!(from_lsp_position(&long, lsp_types::Position::new(0, 69000)), Position::new(1, u16::MAX));
+    }
+
+    #[test]
+    fn test_bad_notifications() {
+        let mut client = Client::start();
+        client.notify("textDocument/didChange", json!({ "textDocument": { "uri": 3 } }));
+        match client.connection.receiver.recv().unwrap() {
+            Message::Notification(n) => assert_eq!(n.method, "window/logMessage"),
+            other => panic!("{:?}", other)
+        }
+        client.open("file:///a.cfg", "a;");
+        let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": "file:///a.cfg" } }));
+        assert_eq!(symbols[0]["name"], "a");
+        client.stop();
+    }
+
+    #[test]
+    fn test_long_lines() {
+        let mut client = Client::start();
+        client.open("file:///a.cfg", &format!("{}abcdef;", " ".repeat(70000)));
+        let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": "file:///a.cfg" } }));
+        assert_eq!(symbols[0]["name"], "abcdef");
+        client.stop();
+    }
+}
@@ -0,0 +1,63 @@
+/// Constructs a document root ConfigBlock using the config file syntax
+///
+/// Option names and parameters may be identifiers or literals, and numbers may be
+/// negative. Anything else, like host names or the `!name` markers of `merge`, has to be
+/// quoted: `"!channel" x;`. Rust comments are allowed, config comments are not.
+///
+/// The syntax is checked at compile time, but the tree is built when the expression is
+/// evaluated, through the same calls as `ConfigBlock::new` and `add_block`.
+///
+/// ```
+/// #[macro_use]
+/// extern crate config_parser;
+///
+/// # fn main() {
+/// let cfg = config! {
+///     server freenode {
+///         connect "irc.freenode.net" 6697 tls;
+///         nick Bot;
+///     }
+/// };
+///
+/// let server = cfg.matching("server").next().unwrap();
+/// assert_eq!(server.matching("connect").next().unwrap().get(1), "6697");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! config {
+    (@items $block:ident;) => {};
+    (@items $block:ident; ; $($rest:tt)*) => {
+        $crate::config!(@items $block; $($rest)*)
+    };
+    (@items $block:ident; $name:ident $($rest:tt)*) => {
+        $crate::config!(@params $block; String::from(stringify!($name)); []; $($rest)*)
+    };
+    (@items $block:ident; $name:literal $($rest:tt)*) => {
+        $crate::config!(@params $block; $name.to_string(); []; $($rest)*)
+    };
+    (@params $block:ident; $name:expr; [$($param:expr),*]; ; $($rest:tt)*) => {
+        $block.add_block($crate::ConfigBlock::new($name, vec![$($param),*], vec![]));
+        $crate::config!(@items $block; $($rest)*)
+    };
+    (@params $block:ident; $name:expr; [$($param:expr),*]; { $($inner:tt)* } $($rest:tt)*) => {
+        let mut inner = $crate::ConfigBlock::new($name, vec![$($param),*], vec![]);
+        $crate::config!(@items inner; $($inner)*);
+        $block.add_block(inner);
+        $crate::config!(@items $block; $($rest)*)
+    };
+    (@params $block:ident; $name:expr; [$($param:expr),*]; $next:ident $($rest:tt)*) => {
+        $crate::config!(@params $block; $name; [$($param,)* String::from(stringify!($next))]; $($rest)*)
+    };
+    (@params $block:ident; $name:expr; [$($param:expr),*]; - $next:literal $($rest:tt)*) => {
+        $crate::config!(@params $block; $name; [$($param,)* format!("-{}", $next)]; $($rest)*)
+    };
+    (@params $block:ident; $name:expr; [$($param:expr),*]; $next:literal $($rest:tt)*) => {
+        $crate::config!(@params $block; $name; [$($param,)* $next.to_string()]; $($rest)*)
+    };
+    ($($items:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut root = $crate::ConfigBlock::new(String::new(), vec![], vec![]);
+        $crate::config!(@items root; $($items)*);
+        root
+    }};
+}
@@ -0,0 +1,224 @@
+//! Layering configs on top of each other
+//!
+//! Merging an overlay into a base config, like user settings into shipped defaults,
+//! goes through the options of the overlay in order. Each one is matched with an option
+//! of the base by name and first parameter; an option whose name occurs once on both
+//! sides, or which the schema marks as `unique`, is matched by name alone. What happens
+//! then depends on the strategy of the option:
+//!
+//! * `Strategy::Merge` (the default) keeps the parameters of the overlay and merges the
+//!   inner options recursively
+//! * `Strategy::Replace` replaces the base option, inner options included
+//! * `Strategy::Append` never matches, the option is added after the base options
+//!
+//! Unmatched options are added at the end. An option named like another one with a `!`
+//! in front removes options from the base: `!channel;` all `channel` options and
+//! `!channel "#x";` the one whose first parameter is `#x`.
+//!
+//! Schemas choose the strategy per option with `merge replace;`, `merge append;` or
+//! `merge merge;` in a `directive`.
+//!
+//! ```
+//! # #[macro_use] extern crate config_parser;
+//! # use config_parser::merge;
+//! # fn main() {
+//! let defaults = config! { server a { port 6667; channel x; } };
+//! let user = config_parser::parse_string(String::from("server a { port 6697; !channel; channel y; }")).unwrap();
+//! assert_eq!(merge::merge(&defaults, &user), config! { server a { port 6697; channel y; } });
+//! # }
+//! ```
+
+use std::str::FromStr;
+use super::config::ConfigBlock;
+use super::schema::Schema;
+
+/// How an option of the overlay is combined with the base, see the module documentation
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Strategy {
+    Merge,
+    Replace,
+    Append
+}
+
+/// Merges configs with a default strategy and an optional schema
+#[derive(Debug, Clone)]
+pub struct Merger {
+    strategy: Strategy,
+    schema: Option<Schema>
+}
+
+/// Merges an overlay into a base with the default strategy
+pub fn merge(base: &ConfigBlock, overlay: &ConfigBlock) -> ConfigBlock {
+    Merger::new().merge(base, overlay)
+}
+
+impl Merger {
+    pub fn new() -> Merger {
+        Merger { strategy: Strategy::Merge, schema: None }
+    }
+
+    /// Sets the strategy for options the schema says nothing about
+    pub fn strategy(mut self, strategy: Strategy) -> Merger {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the schema which chooses strategies and unique options
+    pub fn schema(mut self, schema: Schema) -> Merger {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Merges an overlay into a base. The result has the name and parameters of the overlay
+    pub fn merge(&self, base: &ConfigBlock, overlay: &ConfigBlock) -> ConfigBlock {
+        self.merge_blocks(base, overlay, self.schema.as_ref())
+    }
+
+    /// Merges each layer into the result of merging the previous ones
+    pub fn merge_all<'a, I>(&self, layers: I) -> Option<ConfigBlock> where I: IntoIterator<Item=&'a ConfigBlock> {
+        let mut layers = layers.into_iter();
+        let first = layers.next()?.clone();
+        Some(layers.fold(first, |merged, layer| self.merge(&merged, layer)))
+    }
+
+    fn merge_blocks(&self, base: &ConfigBlock, overlay: &ConfigBlock, schema: Option<&Schema>) -> ConfigBlock {
+        // Counted before removing anything, so that a list stays a list after removals
+        let count = |blocks: &[ConfigBlock], name: &str| blocks.iter().filter(|b| b.name() == name).count();
+        let mut inner = base.inner().clone();
+        let (markers, options): (Vec<&ConfigBlock>, Vec<&ConfigBlock>) = overlay.inner().iter()
+            .partition(|o| is_marker(o));
+        for marker in markers {
+            let name = &marker.name()[1..];
+            inner.retain(|b| b.name() != name || (marker.get_opt(0).is_some() && b.get_opt(0) != marker.get_opt(0)));
+        }
+
+        let base_count: Vec<usize> = options.iter().map(|o| count(base.inner(), o.name())).collect();
+        let mut claimed = vec![false; inner.len()];
+        for (n, option) in options.iter().enumerate() {
+            let directive = schema.and_then(|s| s.get(option.name()));
+            let strategy = directive.and_then(|d| d.merge_strategy()).unwrap_or(self.strategy);
+            let by_name = directive.map(|d| d.is_unique()).unwrap_or(false)
+                || (base_count[n] == 1 && options.iter().filter(|o| o.name() == option.name()).count() == 1);
+            let target = match strategy {
+                Strategy::Append => None,
+                _ => (0..inner.len()).find(|&i| {
+                    !claimed[i] && inner[i].name() == option.name() && (by_name || inner[i].get_opt(0) == option.get_opt(0))
+                })
+            };
+            let i = match target {
+                Some(i) => i,
+                None => {
+                    inner.push(strip_markers(option));
+                    claimed.push(true);
+                    continue;
+                }
+            };
+            claimed[i] = true;
+            inner[i] = match strategy {
+                Strategy::Merge => {
+                    let child_schema = directive.and_then(|d| d.block_schema());
+                    self.merge_blocks(&inner[i], option, child_schema)
+                },
+                _ => strip_markers(option)
+            };
+        }
+
+        let mut merged = overlay.with_inner(inner);
+        merged.set_span(overlay.span().or_else(|| base.span()));
+        merged
+    }
+}
+
+fn is_marker(option: &ConfigBlock) -> bool {
+    option.name().len() > 1 && option.name().starts_with('!')
+}
+
+/// Copies an overlay option which has nothing to remove from, leaving out the `!name`
+/// markers at any depth
+fn strip_markers(option: &ConfigBlock) -> ConfigBlock {
+    option.with_inner(option.inner().iter().filter(|o| !is_marker(o)).map(strip_markers).collect())
+}
+
+impl Default for Merger {
+    fn default() -> Merger {
+        Merger::new()
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    /// Accepts `merge`, `replace` and `append`
+    fn from_str(s: &str) -> Result<Strategy, String> {
+        match s {
+            "merge" => Ok(Strategy::Merge),
+            "replace" => Ok(Strategy::Replace),
+            "append" => Ok(Strategy::Append),
+            _ => Err(format!("unknown merge strategy `{}`, expected merge, replace or append", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(text: &str) -> ConfigBlock {
+        ::parse_string(String::from(text)).unwrap()
+    }
+
+    #[test]
+    fn test_default_strategy() {
+        let base = parse("
+            nick bot;
+            server a { port 6667; channel x; channel y key; }
+            server b { port 6667; }
+        ");
+        let overlay = parse("
+            nick bot2;
+            server a { channel y; channel z; }
+            server c;
+            !server b;
+        ");
+        assert_eq!(merge(&base, &overlay), parse("
+            nick bot2;
+            server a { port 6667; channel x; channel y; channel z; }
+            server c;
+        "));
+    }
+
+    #[test]
+    fn test_strategies_from_schema() {
+        let schema = Schema::from_config(&parse("
+            directive server {
+                block {
+                    directive channel { merge append; }
+                    directive auth { merge replace; }
+                    directive port { unique; }
+                }
+            }
+        ")).unwrap();
+        let base = parse("server a { channel x; auth { user a; pass b; } port 1; port 2; }");
+        let overlay = parse("server a { channel x; auth { user c; } port 3; }");
+        let merged = Merger::new().schema(schema).merge(&base, &overlay);
+        assert_eq!(merged, parse("server a { channel x; auth { user c; } port 3; port 2; channel x; }"));
+
+        let replaced = Merger::new().strategy(Strategy::Replace).merge(&base, &overlay);
+        assert_eq!(replaced, parse("server a { channel x; auth { user c; } port 3; }"));
+    }
+
+    #[test]
+    fn test_layers() {
+        let layers = [parse("a 1; b 1;"), parse("a 2;"), parse("!b; c;")];
+        assert_eq!(Merger::new().merge_all(&layers), Some(parse("a 2; c;")));
+        assert_eq!(merge(&parse("c x; c y;"), &parse("!c y; c z;")), parse("c x; c z;"));
+        assert_eq!(Merger::new().merge_all(&[]), None);
+    }
+
+    #[test]
+    fn test_markers_without_base() {
+        let overlay = parse("server b { !channel; channel x; user u { !nick; } } auth { !user; pass p; }");
+        let merged = Merger::new().strategy(Strategy::Replace).merge(&parse("auth { user a; }"), &overlay);
+        assert_eq!(merged, parse("auth { pass p; } server b { channel x; user u {} }"));
+    }
+}
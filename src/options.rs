@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+/// A kind of comment recognized by the lexer
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum CommentStyle {
+    /// `# comment` until the end of the line
+    Hash,
+    /// `// comment` until the end of the line
+    DoubleSlash,
+    /// `/* comment */`, which may be nested
+    Block
+}
+
+/// What happens to `if` and `else` options, see the `condition` module
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Conditionals {
+    /// `if` and `else` are ordinary options
+    Off,
+    /// Only the options of the branch whose condition holds end up in the tree
+    Evaluate,
+    /// The conditions are checked for errors, but every branch stays in the tree as an
+    /// `if` or `else` option, for tools which work on all of them
+    KeepBranches
+}
+
+/// Controls how a configuration file is parsed
+///
+/// When parsing untrusted input, all limits should be set. Each of them fails with its
+/// own `ErrorType` once exceeded.
+///
+/// ```
+/// use config_parser::{ParseOptions, CommentStyle};
+///
+/// let options = ParseOptions::new()
+///     .comments(&[CommentStyle::Hash])
+///     .max_depth(Some(16))
+///     .trailing_semicolon(false);
+///
+/// assert!(config_parser::parse_string_with(String::from("a { b; } # c"), &options).is_ok());
+/// assert!(config_parser::parse_string_with(String::from("a { b; };"), &options).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub(crate) strict: bool,
+    pub(crate) comments: Vec<CommentStyle>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_input_size: Option<usize>,
+    pub(crate) max_blocks: Option<usize>,
+    pub(crate) max_params: Option<usize>,
+    pub(crate) max_literal_length: Option<usize>,
+    pub(crate) trailing_semicolon: bool,
+    pub(crate) templates: bool,
+    pub(crate) conditionals: Conditionals,
+    pub(crate) variables: BTreeMap<String, String>,
+    pub(crate) profiles: Option<Vec<String>>,
+    pub(crate) secrets: bool
+}
+
+impl ParseOptions {
+    /// Creates the default options, which match the behaviour of `parse()`
+    pub fn new() -> ParseOptions {
+        ParseOptions {
+            strict: true,
+            comments: vec![CommentStyle::Hash, CommentStyle::DoubleSlash, CommentStyle::Block],
+            max_depth: None,
+            max_input_size: None,
+            max_blocks: None,
+            max_params: None,
+            max_literal_length: None,
+            trailing_semicolon: true,
+            templates: false,
+            conditionals: Conditionals::Off,
+            variables: BTreeMap::new(),
+            profiles: None,
+            secrets: false
+        }
+    }
+
+    /// In strict mode, which is the default, every option has to be terminated by `;`
+    /// or a block. Lenient parsing also accepts a missing `;` before `}` or the end of
+    /// the file and treats parentheses as the end of an option
+    pub fn strict(mut self, strict: bool) -> ParseOptions {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets which kinds of comments are recognized. All of them are by default
+    pub fn comments(mut self, styles: &[CommentStyle]) -> ParseOptions {
+        self.comments = styles.to_vec();
+        self
+    }
+
+    /// Limits how deeply blocks may be nested. Unlimited by default
+    pub fn max_depth(mut self, depth: Option<usize>) -> ParseOptions {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Limits how many bytes of input are read. Unlimited by default
+    pub fn max_input_size(mut self, bytes: Option<usize>) -> ParseOptions {
+        self.max_input_size = bytes;
+        self
+    }
+
+    /// Limits how many blocks and options the whole document may contain. Unlimited by
+    /// default
+    pub fn max_blocks(mut self, blocks: Option<usize>) -> ParseOptions {
+        self.max_blocks = blocks;
+        self
+    }
+
+    /// Limits how many parameters a single option may have. Unlimited by default
+    pub fn max_params(mut self, params: Option<usize>) -> ParseOptions {
+        self.max_params = params;
+        self
+    }
+
+    /// Limits the length of a single literal in bytes, after resolving escape sequences.
+    /// Unlimited by default
+    pub fn max_literal_length(mut self, bytes: Option<usize>) -> ParseOptions {
+        self.max_literal_length = bytes;
+        self
+    }
+
+    /// Sets whether a `;` may follow the `}` of a block. Allowed by default
+    pub fn trailing_semicolon(mut self, allow: bool) -> ParseOptions {
+        self.trailing_semicolon = allow;
+        self
+    }
+
+    /// Sets whether `template` options define templates which others can `extends`, see
+    /// the `template` module. Off by default. Trees borrowing from the input, as
+    /// returned by `parse_str`, never resolve templates
+    pub fn templates(mut self, resolve: bool) -> ParseOptions {
+        self.templates = resolve;
+        self
+    }
+
+    /// Sets how `if` and `else` options are treated. Off by default. Like templates,
+    /// conditions are never evaluated in trees borrowing from the input
+    pub fn conditionals(mut self, mode: Conditionals) -> ParseOptions {
+        self.conditionals = mode;
+        self
+    }
+
+    /// Defines a variable which conditions can test, replacing an earlier one of the
+    /// same name
+    pub fn variable<N, V>(mut self, name: N, value: V) -> ParseOptions where N: Into<String>, V: Into<String> {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Selects the active profiles, see the `profile` module. Later profiles take
+    /// precedence over earlier ones. `profile` options are ordinary options unless this
+    /// is set, even to an empty list
+    pub fn profiles(mut self, names: &[&str]) -> ParseOptions {
+        self.profiles = Some(names.iter().map(|&n| String::from(n)).collect());
+        self
+    }
+
+    /// Sets whether parameters may refer to secrets like `secret("env:PASSWORD")`, see
+    /// the `secret` module. Off by default
+    pub fn secrets(mut self, allow: bool) -> ParseOptions {
+        self.secrets = allow;
+        self
+    }
+
+    /// Returns the default options with the limits of these, for dialects which only
+    /// honour the limits. Conditions, templates, profiles and secret references are off
+    pub(crate) fn limits_only(&self) -> ParseOptions {
+        ParseOptions {
+            max_depth: self.max_depth,
+            max_input_size: self.max_input_size,
+            max_blocks: self.max_blocks,
+            max_params: self.max_params,
+            max_literal_length: self.max_literal_length,
+            ..ParseOptions::new()
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions::new()
+    }
+}
@@ -1,166 +1,365 @@
-use super::lexer;
 use super::lexer::{TokenType, Token};
 use super::config::ConfigBlock;
 use super::error::{Result, ErrorType, Error, CodePos};
+use super::condition;
+use super::options::{Conditionals, ParseOptions};
+use super::profile;
+use super::secret;
+use super::span::{Position, Span};
+use super::template;
 
-macro_rules! expect_token {
-    ($state:expr) => {
-        match next($state) {
-            Some(t) => t,
-            None => return fail($state, ErrorType::UnexpectedEOF, "token")
-        }
-    };
-    ($state:expr, $ty:expr) => {
-        match next($state) {
-            Some(Token {token_type: $ty(..), ..} @ t) => t,
-            Some(t) => return fail($state, ErrorType::Unexpected(t), stringify!($ty)) 
-            None => return fail($state, ErrorType::UnexpectedEOF, stringify!($ty))
-        }
-    }
+/// A single step of parsing a config file
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Event<S = String> {
+    /// A block was opened. The span covers the option name up to the opening brace
+    BlockStart { name: S, params: Vec<S>, span: Span },
+    /// An option without a block. The span covers the option name up to the semicolon
+    Directive { name: S, params: Vec<S>, span: Span },
+    /// The most recently opened block was closed. The span covers the closing brace
+    BlockEnd { span: Span }
 }
 
-struct ParseState {
-    tokens: Box<Iterator<Item=lexer::Token>>,
-    last_token: Option<Token>,
-    force_next: Option<Token>,
-    done: bool
+/// A pull parser producing an `Event` for every block and directive in the token stream
+///
+/// Tokens are only read from the lexer when the next event is requested. Every
+/// `BlockStart` is matched by exactly one `BlockEnd`, unless an error is returned. After
+/// the first error the iterator is exhausted.
+pub struct Events<'a, S = String> {
+    tokens: Box<dyn Iterator<Item=Result<Token<S>>> + 'a>,
+    last_pos: (u32, u16),
+    force_next: Option<Token<S>>,
+    depth: usize,
+    blocks: usize,
+    after_block: bool,
+    done: bool,
+    strict: bool,
+    max_depth: Option<usize>,
+    max_blocks: Option<usize>,
+    max_params: Option<usize>,
+    trailing_semicolon: bool
 }
 
-impl CodePos for ParseState {
+impl <'a, S> CodePos for Events<'a, S> {
     fn location(&self) -> (u32, u16) {
-        match self.last_token {
-            Some(ref t) => (t.line, t.col),
-            None => (0, 0)
-        }
+        self.last_pos
     }
 }
 
-pub fn run(tokens: Box<Iterator<Item=lexer::Token>>) -> Result<ConfigBlock> {
-    let mut state = ParseState {
-        tokens: tokens,
-        last_token: None,
-        force_next: None,
-        done: false
-    };
+impl <'a, S> Events<'a, S> where S: Into<String> {
+    pub fn new(tokens: Box<dyn Iterator<Item=Result<Token<S>>> + 'a>) -> Events<'a, S> {
+        Events::with_options(tokens, &ParseOptions::default())
+    }
 
-    parse_block(&mut state, false, String::from(""), vec![])
-}
+    /// Creates a parser obeying the strictness, the nesting, block and parameter limits
+    /// and the trailing semicolon setting of the options
+    pub fn with_options(tokens: Box<dyn Iterator<Item=Result<Token<S>>> + 'a>, options: &ParseOptions) -> Events<'a, S> {
+        Events {
+            tokens,
+            last_pos: (0, 0),
+            force_next: None,
+            depth: 0,
+            blocks: 0,
+            after_block: false,
+            done: false,
+            strict: options.strict,
+            max_depth: options.max_depth,
+            max_blocks: options.max_blocks,
+            max_params: options.max_params,
+            trailing_semicolon: options.trailing_semicolon
+        }
+    }
+
+    /// Returns the number of blocks which are currently open
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
 
-fn parse_block(state: &mut ParseState, inner: bool, name: String, options: Vec<String>) -> Result<ConfigBlock> {
-    let mut ret = ConfigBlock::new(name, options, vec![]);
-    loop {
-        let tok = if inner {
-            expect_token!(state)
-        } else {
-            match next(state) {
+    fn parse_event(&mut self) -> Result<Option<Event<S>>> {
+        let after_block = self.after_block;
+        self.after_block = false;
+        loop {
+            let tok = match self.next_token()? {
                 Some(t) => t,
-                None => return Ok(ret)
-            }
-        };
-        match tok.clone().token_type {
-            TokenType::RawLiteral(option_name) => {
-                let params = try!(parse_params(state));
-                let t = expect_token!(state);
-                match t.token_type {
-                    TokenType::OpenBrace => {
-                        // Block follows
-                        ret.add_block(try!(parse_block(state, true, option_name, params)));
-                    },
-                    _ => {
-                        // No block. In strict mode this will only ever execute for 
-                        // TokenType::Semicolon as parse_params() will already have
-                        // returned an error for other types
-                        ret.add_block(ConfigBlock::new(option_name, params, vec![]))
+                None if self.depth > 0 => return self.fail(ErrorType::UnexpectedEOF, "}"),
+                None => return Ok(None)
+            };
+            let start = tok.position();
+            match tok.token_type {
+                // Quoted names allow names the writer has to quote, like `"my key" v;`
+                TokenType::RawLiteral(name) | TokenType::StringLiteral(name) => {
+                    self.blocks += 1;
+                    if let Some(max) = self.max_blocks {
+                        if self.blocks > max {
+                            return self.fail(ErrorType::TooManyBlocks(max), "}");
+                        }
+                    }
+                    let params = self.parse_params()?;
+                    if !self.strict {
+                        // A missing semicolon before the end of a block or file is fine
+                        match self.lookahead()? {
+                            Some(&TokenType::CloseBrace) | None => {
+                                let end = self.last_pos;
+                                let span = Span::new(start, Position::new(end.0, end.1));
+                                return Ok(Some(Event::Directive { name, params, span }));
+                            },
+                            _ => {}
+                        }
                     }
+                    let t = match self.next_token()? {
+                        Some(t) => t,
+                        None => return self.fail(ErrorType::UnexpectedEOF, "token")
+                    };
+                    let span = Span::new(start, t.position());
+                    return match t.token_type {
+                        TokenType::OpenBrace => {
+                            // Block follows
+                            self.depth += 1;
+                            if let Some(max) = self.max_depth {
+                                if self.depth > max {
+                                    return self.fail(ErrorType::NestingTooDeep(max), "}");
+                                }
+                            }
+                            Ok(Some(Event::BlockStart { name, params, span }))
+                        },
+                        _ => {
+                            // No block. In strict mode this will only ever execute for
+                            // TokenType::Semicolon as parse_params() will already have
+                            // returned an error for other types
+                            Ok(Some(Event::Directive { name, params, span }))
+                        }
+                    };
+                },
+                TokenType::CloseBrace if self.depth > 0 => {
+                    self.depth -= 1;
+                    self.after_block = true;
+                    return Ok(Some(Event::BlockEnd { span: Span::new(start, start) }));
+                },
+                TokenType::Semicolon if after_block && !self.trailing_semicolon => {
+                    return self.fail(ErrorType::Unexpected(tok.into_owned()), "option");
+                },
+                TokenType::Semicolon => {},
+                _ => {
+                    let expected = if self.depth > 0 { "option or }" } else { "option" };
+                    return self.fail(ErrorType::Unexpected(tok.into_owned()), expected);
                 }
-            },
-            TokenType::CloseBrace if inner => break,
-            TokenType::Semicolon => {}
-            _ => return fail(state, ErrorType::Unexpected(tok.clone()), if inner { "option or }" } else { "option" })
+            }
         }
     }
-    Ok(ret)
-}
 
-fn parse_params(state: &mut ParseState) -> Result<Vec<String>> {
-    let mut ret = vec![];
-    println!("Parsing params");
-    loop {
-        let opt_t = lookahead(state);
-        match opt_t {
-            Some(t) => {
-                match t.token_type {
-                    TokenType::StringLiteral(s) => {
-                        ret.push(s);
-                        pop(state);
-                    },
-                    TokenType::RawLiteral(s) => {
-                        ret.push(s);
-                        pop(state);
-                    },
-                    TokenType::OpenBrace => break,
-                    TokenType::Semicolon => break,
-                    _ => {
-                        if cfg!(feature = "nonstrict") {
-                            break;
-                        } else {
-                            println!("Errored params");
-                            return fail(state, ErrorType::Unexpected(t), "; or {") 
-                        }
+    fn parse_params(&mut self) -> Result<Vec<S>> {
+        let mut ret = vec![];
+        let strict = self.strict;
+        loop {
+            let is_param = match self.lookahead()? {
+                Some(&TokenType::StringLiteral(_)) | Some(&TokenType::RawLiteral(_)) => true,
+                Some(&TokenType::OpenBrace) | Some(&TokenType::Semicolon) => false,
+                Some(_) => {
+                    if !strict {
+                        false
+                    } else {
+                        let t = self.next_token()?.unwrap();
+                        return self.fail(ErrorType::Unexpected(t.into_owned()), "; or {")
                     }
+                },
+                None if !strict => false,
+                None => return self.fail(ErrorType::UnexpectedEOF, "}")
+            };
+            if !is_param {
+                break;
+            }
+            match self.next_token()?.map(|t| t.token_type) {
+                Some(TokenType::StringLiteral(s)) | Some(TokenType::RawLiteral(s)) => ret.push(s),
+                _ => unreachable!("Lookahead token changed")
+            }
+            if let Some(max) = self.max_params {
+                if ret.len() > max {
+                    return self.fail(ErrorType::TooManyParameters(max), "; or {");
                 }
-            },
-            None => return fail(&state, ErrorType::UnexpectedEOF, "}")
+            }
+        }
+        Ok(ret)
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<S>>> {
+        let t = match self.force_next.take() {
+            Some(t) => Some(t),
+            None => self.pull()?
+        };
+        if let Some(ref t) = t {
+            self.last_pos = (t.line, t.col);
+        }
+        Ok(t)
+    }
+
+    fn lookahead(&mut self) -> Result<Option<&TokenType<S>>> {
+        if self.force_next.is_none() {
+            self.force_next = self.pull()?;
         }
+        Ok(self.force_next.as_ref().map(|t| &t.token_type))
+    }
+
+    /// Reads the next token from the lexer, skipping comments
+    fn pull(&mut self) -> Result<Option<Token<S>>> {
+        loop {
+            match self.tokens.next().transpose()? {
+                Some(Token { token_type: TokenType::Comment(_), .. }) => {},
+                t => return Ok(t)
+            }
+        }
+    }
+
+    fn fail<T>(&self, error_type: ErrorType, expected: &'static str) -> Result<T> {
+        Err(Error::from_state(self, error_type, Some(expected)))
     }
-    println!("Exited params");
-    Ok(ret)
 }
 
-fn next(state: &mut ParseState) -> Option<lexer::Token> {
-    let v = match &state.force_next {
-        &Some(ref t) => Some(t.clone()),
-        &None => state.tokens.next()
-    };
-    state.force_next = None;
-    println!("Token {:?}", &v);
-    match v.clone() {
-        Some(_) => {},
-        None => {
-            if state.done {
-                unreachable!("Tried to get another token after end of stream");
-            } else {
-                state.done = true;
+impl <'a, S> Iterator for Events<'a, S> where S: Into<String> {
+    type Item = Result<Event<S>>;
+
+    fn next(&mut self) -> Option<Result<Event<S>>> {
+        if self.done {
+            return None;
+        }
+        match self.parse_event() {
+            Ok(Some(e)) => Some(Ok(e)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
             }
         }
     }
-    v
 }
 
-fn pop(state: &mut ParseState) {
-    println!("popping...");
-    if state.force_next.is_some() {
-        state.force_next = None;
+/// Parses a stream of tokens, pulling them from the lexer as they are needed
+pub fn run<'a>(tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>) -> Result<ConfigBlock> {
+    build(Events::new(tokens))
+}
+
+/// Parses a stream of tokens with the given options
+pub fn run_with<'a>(tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>, options: &ParseOptions) -> Result<ConfigBlock> {
+    let references = secret::References::default();
+    let quoted = condition::Quoted::default();
+    let cfg = build(Events::with_options(adapt(tokens, options, &references, &quoted), options))?;
+    let cfg = match options.conditionals {
+        Conditionals::Off => cfg,
+        _ => condition::resolve_quoted(&cfg, options, &quoted.borrow())?
+    };
+    let cfg = if options.templates { template::resolve(&cfg, options)? } else { cfg };
+    let mut cfg = match options.profiles {
+        Some(ref active) => profile::apply(&cfg, active)?,
+        None => cfg
+    };
+    secret::mark(&mut cfg, &references.borrow());
+    Ok(cfg)
+}
+
+/// Passes tokens through the adapters for the syntax the options enable, which turn
+/// the parentheses of conditions and secret references into literals. The positions
+/// of the references are added to `references`, those of quoted words in conditions to
+/// `quoted`
+pub(crate) fn adapt<'a>(tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>, options: &ParseOptions,
+        references: &secret::References, quoted: &condition::Quoted) -> Box<dyn Iterator<Item=Result<Token>> + 'a> {
+    let tokens: Box<dyn Iterator<Item=Result<Token>> + 'a> = match options.conditionals {
+        Conditionals::Off => tokens,
+        _ => Box::new(condition::Tokens::new(tokens, quoted.clone()))
+    };
+    if options.secrets {
+        Box::new(secret::Tokens::new(tokens, references.clone()))
     } else {
-        state.tokens.next();
+        tokens
     }
 }
 
-fn lookahead(state: &mut ParseState) -> Option<lexer::Token> {
-    let r = match state.force_next.clone() {
-        Some(t) => Some(t),
-        None => {
-            let t = state.tokens.next();
-            state.force_next = t.clone();
-            t
+/// Like `adapt`, for tokens with their spans. A token the adapters combine from several
+/// gets a span covering all of them
+pub(crate) fn adapt_spanned(tokens: Vec<(Token, Span)>, options: &ParseOptions) -> Result<Vec<(Token, Span)>> {
+    let spans: Vec<Span> = tokens.iter().map(|t| t.1).collect();
+    let tokens = Box::new(tokens.into_iter().map(|t| Ok(t.0)));
+    let adapted = adapt(tokens, options, &Default::default(), &Default::default()).collect::<Result<Vec<_>>>()?;
+    let next_starts: Vec<Position> = adapted.iter().skip(1).map(|t| t.position()).collect();
+    let mut spans = spans.into_iter().peekable();
+    Ok(adapted.into_iter().enumerate().map(|(i, token)| {
+        let mut span = spans.next().expect("adapters don't add tokens");
+        while let Some(s) = spans.next_if(|s| Some(&s.start) != next_starts.get(i)) {
+            span.end = s.end;
         }
-    };
-    println!("Lookahead Token {:?}", &r);
-    r
+        (token, span)
+    }).collect())
+}
+
+/// Builds the document tree from a stream of events. A `BlockEnd` without a matching
+/// `BlockStart` and blocks left open at the end of the stream are errors
+pub fn build<I>(events: I) -> Result<ConfigBlock> where I: Iterator<Item=Result<Event>> {
+    build_tree(events)
 }
 
-fn fail<T>(state: &ParseState, error_type: ErrorType, expected: &'static str) -> Result<T> {
-    Err(Error::from_state(state, error_type, Some(expected)))
+/// A tree which `build_tree` can assemble from events carrying values of type `S`
+pub(crate) trait Tree<S>: Sized {
+    /// Creates the document root
+    fn root() -> Self;
+    /// Creates an option without inner options
+    fn option(name: S, params: Vec<S>, span: Span) -> Self;
+    /// Appends an inner option
+    fn add(&mut self, child: Self);
+    /// Extends the span of a block to its closing brace
+    fn close(&mut self, end: Span);
+}
+
+impl Tree<String> for ConfigBlock {
+    fn root() -> ConfigBlock {
+        ConfigBlock::new(String::new(), vec![], vec![])
+    }
+
+    fn option(name: String, params: Vec<String>, span: Span) -> ConfigBlock {
+        let mut block = ConfigBlock::new(name, params, vec![]);
+        block.set_span(Some(span));
+        block
+    }
+
+    fn add(&mut self, child: ConfigBlock) {
+        self.add_block(child);
+    }
+
+    fn close(&mut self, end: Span) {
+        let start = self.span().map(|s| s.start).unwrap_or(end.start);
+        self.set_span(Some(Span::new(start, end.end)));
+    }
+}
+
+/// Builds any kind of tree from a stream of events, see `build`
+pub(crate) fn build_tree<T, S, I>(events: I) -> Result<T> where T: Tree<S>, I: Iterator<Item=Result<Event<S>>> {
+    let mut stack = vec![T::root()];
+    let mut last = Position::default();
+    for event in events {
+        match event? {
+            Event::BlockStart { name, params, span } => {
+                last = span.end;
+                stack.push(T::option(name, params, span));
+            },
+            Event::Directive { name, params, span } => {
+                last = span.end;
+                stack.last_mut().expect("the root block is never closed").add(T::option(name, params, span));
+            },
+            Event::BlockEnd { span } => {
+                if stack.len() < 2 {
+                    let token = Token::new(span.start.line, span.start.col, TokenType::CloseBrace);
+                    return Err(Error::new(span.start.line, span.start.col, ErrorType::Unexpected(token), Some("option")));
+                }
+                let mut block = stack.pop().unwrap();
+                block.close(span);
+                last = span.end;
+                stack.last_mut().unwrap().add(block);
+            }
+        }
+    }
+    if stack.len() != 1 {
+        return Err(Error::new(last.line, last.col, ErrorType::UnexpectedEOF, Some("}")));
+    }
+    Ok(stack.pop().unwrap())
 }
 
 #[cfg(test)]
@@ -168,6 +367,10 @@ mod test {
     use super::*;
     use super::super::lexer::{Token, TokenType};
     use super::super::config::ConfigBlock;
+    use super::super::error::{Error, ErrorType};
+    use super::super::error::Result;
+    use super::super::options::ParseOptions;
+    use super::super::span::{Position, Span};
 
     #[test] 
     fn test_it_parsing_the_most_basic_option() {
@@ -175,7 +378,7 @@ mod test {
             run(Box::new(vec![
                 tok(TokenType::RawLiteral(String::from("test"))),
                 tok(TokenType::Semicolon)
-            ].into_iter())),
+            ].into_iter().map(Ok))),
                 Ok(ConfigBlock::new(
                     String::new(),
                     vec![],
@@ -200,7 +403,7 @@ mod test {
                 tok(TokenType::StringLiteral(String::from("value"))),
                 tok(TokenType::Semicolon),
                 tok(TokenType::CloseBrace),
-            ].into_iter())),
+            ].into_iter().map(Ok))),
                 Ok(ConfigBlock::new(
                 String::new(),
                 vec![],
@@ -220,6 +423,157 @@ mod test {
             )));
     }
 
+    #[test]
+    fn test_it_parsing_quoted_names() {
+        let cfg = ::parse_string(String::from("\"my key\" v; \"\" { \"}\" x; }")).unwrap();
+        assert_eq!(cfg, config! { "my key" v; "" { "}" x; } });
+    }
+
+    #[test]
+    fn test_it_reporting_errors_in_token_order() {
+        assert_eq!(
+            run(Box::new(vec![
+                Ok(tok(TokenType::RawLiteral(String::from("option")))),
+                Ok(tok(TokenType::CloseParen)),
+                Err(Error::new(5, 5, ErrorType::UnexpectedEOF, None))
+            ].into_iter())),
+            Err(Error::new(0, 0, ErrorType::Unexpected(tok(TokenType::CloseParen)), Some("; or {"))));
+
+        assert_eq!(
+            run(Box::new(vec![
+                Ok(tok(TokenType::RawLiteral(String::from("option")))),
+                Err(Error::new(5, 5, ErrorType::UnexpectedEOF, None))
+            ].into_iter())),
+            Err(Error::new(5, 5, ErrorType::UnexpectedEOF, None)));
+    }
+
+    #[test]
+    fn test_it_producing_events() {
+        let events: Vec<Event> = Events::new(Box::new(vec![
+            tok_at(1, 1, TokenType::RawLiteral(String::from("option"))),
+            tok_at(1, 8, TokenType::RawLiteral(String::from("param1"))),
+            tok_at(1, 15, TokenType::OpenBrace),
+            tok_at(2, 5, TokenType::RawLiteral(String::from("inner"))),
+            tok_at(2, 11, TokenType::Semicolon),
+            tok_at(3, 1, TokenType::CloseBrace),
+        ].into_iter().map(Ok))).map(|e| e.unwrap()).collect();
+
+        assert_eq!(events, vec![
+            Event::BlockStart {
+                name: String::from("option"),
+                params: vec![String::from("param1")],
+                span: Span::new(Position::new(1, 1), Position::new(1, 15))
+            },
+            Event::Directive {
+                name: String::from("inner"),
+                params: vec![],
+                span: Span::new(Position::new(2, 5), Position::new(2, 11))
+            },
+            Event::BlockEnd { span: Span::new(Position::new(3, 1), Position::new(3, 1)) }
+        ]);
+    }
+
+    #[test]
+    fn test_it_recording_spans_in_the_tree() {
+        let cfg = run(Box::new(vec![
+            tok_at(1, 1, TokenType::RawLiteral(String::from("option"))),
+            tok_at(1, 8, TokenType::OpenBrace),
+            tok_at(2, 5, TokenType::RawLiteral(String::from("inner"))),
+            tok_at(2, 10, TokenType::Semicolon),
+            tok_at(3, 1, TokenType::CloseBrace),
+        ].into_iter().map(Ok))).unwrap();
+
+        assert_eq!(cfg.span(), None);
+        let option = &cfg.inner()[0];
+        assert_eq!(option.span(), Some(Span::new(Position::new(1, 1), Position::new(3, 1))));
+        assert_eq!(option.inner()[0].span(), Some(Span::new(Position::new(2, 5), Position::new(2, 10))));
+    }
+
+    #[test]
+    fn test_it_failing_on_unclosed_blocks() {
+        let mut events = Events::new(Box::new(vec![
+            tok_at(1, 1, TokenType::RawLiteral(String::from("option"))),
+            tok_at(1, 8, TokenType::OpenBrace),
+        ].into_iter().map(Ok)));
+
+        assert!(events.next().unwrap().is_ok());
+        assert_eq!(events.depth(), 1);
+        assert_eq!(events.next(), Some(Err(Error::new(1, 8, ErrorType::UnexpectedEOF, Some("}")))));
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn test_it_rejecting_unbalanced_events() {
+        let span = Span::new(Position::new(2, 3), Position::new(2, 3));
+        assert_eq!(build(vec![Ok(Event::BlockEnd { span })].into_iter()),
+            Err(Error::new(2, 3, ErrorType::Unexpected(tok_at(2, 3, TokenType::CloseBrace)), Some("option"))));
+        let start = Event::BlockStart { name: String::from("a"), params: vec![], span };
+        assert_eq!(build(vec![Ok(start)].into_iter()), Err(Error::new(2, 3, ErrorType::UnexpectedEOF, Some("}"))));
+    }
+
+    fn parse_with(input: &'static str, options: &ParseOptions) -> Result<ConfigBlock> {
+        run_with(Box::new(super::super::lexer::Lexer::with_options(input.chars(), String::new(), options)), options)
+    }
+
+    #[test]
+    fn test_it_accepting_missing_semicolons_when_lenient() {
+        let lenient = ParseOptions::new().strict(false);
+        assert_eq!(
+            parse_with("a { b c } d", &lenient),
+            parse_with("a { b c; } d;", &ParseOptions::default()));
+        assert_eq!(
+            parse_with("a { b c }", &ParseOptions::default()),
+            Err(Error::new(1, 9, ErrorType::Unexpected(Token::new(1, 9, TokenType::CloseBrace)), Some("; or {"))));
+    }
+
+    #[test]
+    fn test_it_limiting_nesting_depth() {
+        let options = ParseOptions::new().max_depth(Some(2));
+        assert!(parse_with("a { b { c; } }", &options).is_ok());
+        assert_eq!(
+            parse_with("a { b { c { d; } } }", &options),
+            Err(Error::new(1, 11, ErrorType::NestingTooDeep(2), Some("}"))));
+    }
+
+    #[test]
+    fn test_it_limiting_blocks_and_parameters() {
+        let options = ParseOptions::new().max_blocks(Some(3)).max_params(Some(2));
+        assert!(parse_with("a 1 2 { b; } c;", &options).is_ok());
+        assert_eq!(
+            parse_with("a { b; } c; d;", &options),
+            Err(Error::new(1, 13, ErrorType::TooManyBlocks(3), Some("}"))));
+        assert_eq!(
+            parse_with("a 1 2 3;", &options),
+            Err(Error::new(1, 7, ErrorType::TooManyParameters(2), Some("; or {"))));
+    }
+
+    #[test]
+    fn test_it_handling_deeply_nested_input() {
+        let depth = 100000;
+        let input = format!("{}{}", "a {".repeat(depth), "}".repeat(depth));
+        let lexer = super::super::lexer::Lexer::new(input.chars());
+        let cfg = run(Box::new(lexer)).unwrap();
+        assert_eq!(cfg.inner().len(), 1);
+
+        let options = ParseOptions::new().max_depth(Some(64));
+        let lexer = super::super::lexer::Lexer::new(input.chars());
+        assert_eq!(run_with(Box::new(lexer), &options).unwrap_err(), Error::new(1, 195, ErrorType::NestingTooDeep(64), Some("}")));
+    }
+
+    #[test]
+    fn test_it_rejecting_trailing_semicolons() {
+        let options = ParseOptions::new().trailing_semicolon(false);
+        assert!(parse_with("a { b; } c;", &options).is_ok());
+        assert!(parse_with("a { b; };", &ParseOptions::default()).is_ok());
+        assert_eq!(
+            parse_with("a { b; };", &options),
+            Err(Error::new(1, 9, ErrorType::Unexpected(Token::new(1, 9, TokenType::Semicolon)), Some("option"))));
+    }
+
+    fn tok_at(line: u32, col: u16, ty: TokenType) -> Token {
+        Token::new(line, col, ty)
+    }
+
     fn tok(ty: TokenType) -> Token {
         Token::new(0, 0, ty)
     }
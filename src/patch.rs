@@ -0,0 +1,453 @@
+//! Changing trees with a list of operations
+//!
+//! A patch is written in the config syntax, each option being one operation on the
+//! options selected by a path (see the `path` module):
+//!
+//! ```text
+//! add server[a] { channel "#new"; }       # appends the inner options to the block
+//! add / 0 { include defaults.cfg; }       # inserts them at a position instead
+//! remove server[a]/channel[\#old];        # removes every option the path matches
+//! replace server[a]/port { port 6697; }   # replaces an option, inner options included
+//! set server[a]/nick bot2;                # replaces the parameters of an option
+//! ```
+//!
+//! `add`, `replace` and `set` require the path to match exactly one option. `/` is the
+//! root, which can only be added to. With the `json` feature, patches can also be read
+//! from a list of JSON objects like `{"op": "add", "path": "server[a]", "value":
+//! "channel x;"}`, where `value` is config text, `set` takes `params` and `add` an
+//! optional `index`.
+//!
+//! `apply` runs the operations in order on a copy and only replaces the tree if all of
+//! them succeeded. `from_diff` turns a `diff::Diff` into a patch.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::ptr;
+use super::config::ConfigBlock;
+use super::diagnostic::Diagnostic;
+use super::diff::{self, Change, Diff};
+use super::path::{Path, Segment};
+
+/// A list of operations, see the module documentation
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Patch {
+    operations: Vec<Operation>
+}
+
+/// A single change of a tree
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operation {
+    /// Inserts options into the block at the path, at the end without an index
+    Add { path: Path, index: Option<usize>, options: Vec<ConfigBlock> },
+    /// Removes every option the path matches
+    Remove { path: Path },
+    /// Replaces the option at the path
+    Replace { path: Path, option: ConfigBlock },
+    /// Replaces the parameters of the option at the path, keeping its inner options
+    Set { path: Path, params: Vec<String> }
+}
+
+/// An operation that could not be applied
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PatchError {
+    /// The position of the operation in the patch
+    pub operation: usize,
+    message: String
+}
+
+impl Patch {
+    pub fn new() -> Patch {
+        Patch::default()
+    }
+
+    /// Adds an operation at the end
+    pub fn operation(mut self, operation: Operation) -> Patch {
+        self.operations.push(operation);
+        self
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Reads a patch from its config representation, see the module documentation
+    pub fn from_config(cfg: &ConfigBlock) -> Result<Patch, Diagnostic> {
+        let mut patch = Patch::new();
+        for item in cfg.inner() {
+            let path = match item.get_opt(0) {
+                Some(path) => path.parse::<Path>().map_err(|e| Diagnostic::error(e.to_string(), item.span()))?,
+                None => return Err(Diagnostic::error(format!("`{}` expects a path", item.name()), item.span()))
+            };
+            let operation = match (item.name(), item.len()) {
+                ("add", 1) => Operation::Add { path, index: None, options: item.inner().clone() },
+                ("add", 2) => match item.get(1).parse() {
+                    Ok(index) => Operation::Add { path, index: Some(index), options: item.inner().clone() },
+                    Err(_) => return Err(Diagnostic::error(format!("`{}` is not a valid index", item.get(1)), item.span()))
+                },
+                ("add", _) => return Err(Diagnostic::error("`add` expects a path and at most an index", item.span())),
+                ("remove", 1) if item.inner().is_empty() => Operation::Remove { path },
+                ("remove", _) => return Err(Diagnostic::error("`remove` expects only a path", item.span())),
+                ("replace", 1) if item.inner().len() == 1 => Operation::Replace { path, option: item.inner()[0].clone() },
+                ("replace", _) => return Err(Diagnostic::error("`replace` expects a path and one option", item.span())),
+                ("set", _) if item.inner().is_empty() => Operation::Set { path, params: item.params()[1..].to_vec() },
+                ("set", _) => return Err(Diagnostic::error("`set` does not take a block", item.span())),
+                (other, _) => return Err(Diagnostic::error(format!("unknown patch operation `{}`", other), item.span()))
+            };
+            patch.operations.push(operation);
+        }
+        Ok(patch)
+    }
+
+    /// Returns the config representation, which `from_config` reads back unchanged
+    pub fn to_config(&self) -> ConfigBlock {
+        let mut root = ConfigBlock::new(String::new(), vec![], vec![]);
+        for operation in &self.operations {
+            let item = match *operation {
+                Operation::Add { ref path, index, ref options } => {
+                    let mut params = vec![path.to_string()];
+                    params.extend(index.map(|i| i.to_string()));
+                    ConfigBlock::new(String::from("add"), params, options.clone())
+                },
+                Operation::Remove { ref path } => ConfigBlock::new(String::from("remove"), vec![path.to_string()], vec![]),
+                Operation::Replace { ref path, ref option } => {
+                    ConfigBlock::new(String::from("replace"), vec![path.to_string()], vec![option.clone()])
+                },
+                Operation::Set { ref path, ref params } => {
+                    let mut all = vec![path.to_string()];
+                    all.extend(params.iter().cloned());
+                    ConfigBlock::new(String::from("set"), all, vec![])
+                }
+            };
+            root.add_block(item);
+        }
+        root
+    }
+
+    /// Returns a patch turning the old tree of the diff into the new one
+    ///
+    /// Options the diff does not consider changed, like reordered ones, keep their place.
+    pub fn from_diff(diff: &Diff) -> Patch {
+        let mut old_paths = HashMap::new();
+        collect_paths(diff.before(), &Path::root(), &mut old_paths);
+        let old_path = |block: &ConfigBlock| old_paths[&(block as *const ConfigBlock)].clone();
+        let mut changes = vec![];
+        let mut additions = vec![];
+        for change in diff.changes() {
+            match *change {
+                // Until the parameters are set, the option is found by its old ones
+                Change::Changed { old, new, .. } => {
+                    changes.push(Operation::Set { path: old_path(old), params: new.params().clone() });
+                },
+                Change::Removed { old, .. } => changes.push(Operation::Remove { path: old_path(old) }),
+                Change::Added { ref path, new } => {
+                    let parent = match path.segments().split_last() {
+                        Some((_, parent)) => parent.iter().fold(Path::root(), |p, s| p.join(s.clone())),
+                        None => continue
+                    };
+                    let index = parent.get(diff.after())
+                        .and_then(|p| p.inner().iter().position(|b| ptr::eq(b, new)));
+                    additions.push(Operation::Add { path: parent, index, options: vec![new.clone()] });
+                }
+            }
+        }
+        // The paths select options in the old tree. Changing the last ones first keeps
+        // the paths of the earlier ones and of the enclosing blocks valid
+        changes.reverse();
+        changes.extend(additions);
+        Patch { operations: changes }
+    }
+
+    /// Applies all operations or, if one of them fails, none
+    pub fn apply(&self, cfg: &mut ConfigBlock) -> Result<(), PatchError> {
+        let mut patched = cfg.clone();
+        for (i, operation) in self.operations.iter().enumerate() {
+            operation.apply(&mut patched).map_err(|message| PatchError { operation: i, message })?;
+        }
+        *cfg = patched;
+        Ok(())
+    }
+}
+
+impl Operation {
+    pub fn path(&self) -> &Path {
+        match *self {
+            Operation::Add { ref path, .. } | Operation::Remove { ref path }
+                | Operation::Replace { ref path, .. } | Operation::Set { ref path, .. } => path
+        }
+    }
+
+    fn apply(&self, cfg: &mut ConfigBlock) -> Result<(), String> {
+        let path = self.path();
+        if path.segments().is_empty() {
+            if let Operation::Add { .. } = *self {} else {
+                return Err(String::from("only `add` can change the root"));
+            }
+        }
+        if let Operation::Remove { .. } = *self {
+            return match remove(cfg, path.segments()) {
+                0 => Err(format!("`{}` does not match any option", path)),
+                _ => Ok(())
+            };
+        }
+        let target = match path.get_mut(cfg) {
+            Some(target) => target,
+            None => return Err(mismatch(cfg, path))
+        };
+        match *self {
+            Operation::Add { index, ref options, .. } => {
+                let at = index.unwrap_or(target.inner().len());
+                if at > target.inner().len() {
+                    return Err(format!("index {} is out of range, `{}` has {} options", at, path, target.inner().len()));
+                }
+                for (i, option) in options.iter().enumerate() {
+                    target.insert_at(at + i, option.clone());
+                }
+            },
+            Operation::Replace { ref option, .. } => *target = option.clone(),
+            Operation::Set { ref params, .. } => {
+                target.clear_marks();
+                *target.params_mut() = params.clone();
+            },
+            Operation::Remove { .. } => unreachable!("handled above")
+        }
+        Ok(())
+    }
+}
+
+/// Records the path of every option below `block`, by address
+fn collect_paths(block: &ConfigBlock, path: &Path, paths: &mut HashMap<*const ConfigBlock, Path>) {
+    for (inner, segment) in block.inner().iter().zip(diff::segments(block)) {
+        let path = path.join(segment);
+        collect_paths(inner, &path, paths);
+        paths.insert(inner, path);
+    }
+}
+
+/// Explains why a path does not select a single option, naming the first segment which
+/// matches none or several
+fn mismatch(cfg: &ConfigBlock, path: &Path) -> String {
+    let mut current = cfg;
+    let mut prefix = Path::root();
+    for segment in path.segments() {
+        prefix = prefix.join(segment.clone());
+        let indices = segment.indices(current);
+        match indices.len() {
+            1 => current = &current.inner()[indices[0]],
+            0 => return format!("`{}` does not match any option", prefix),
+            n => return format!("`{}` matches {} options, expected one", prefix, n)
+        }
+    }
+    unreachable!("the path selects a single option")
+}
+
+/// Removes the options matched by the segments, returning how many there were
+fn remove(block: &mut ConfigBlock, segments: &[Segment]) -> usize {
+    let (first, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return 0
+    };
+    let indices = first.indices(block);
+    if rest.is_empty() {
+        for &i in indices.iter().rev() {
+            block.remove_at(i);
+        }
+        indices.len()
+    } else {
+        indices.into_iter().map(|i| remove(&mut block.inner_mut()[i], rest)).sum()
+    }
+}
+
+/// Writes the config representation
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_config())
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation {}: {}", self.operation + 1, self.message)
+    }
+}
+
+impl error::Error for PatchError {}
+
+#[cfg(feature = "json")]
+mod json {
+    use serde_json::{self, Value};
+    use convert::ConvertError;
+    use path::Path;
+    use super::{Operation, Patch};
+
+    impl Patch {
+        /// Reads a patch from JSON, see the module documentation
+        pub fn from_json(text: &str) -> Result<Patch, ConvertError> {
+            let value: Value = serde_json::from_str(text).map_err(|e| ConvertError::new("", format!("invalid JSON: {}", e)))?;
+            let items = value.as_array().ok_or_else(|| ConvertError::new("", "expected an array of operations"))?;
+            let mut patch = Patch::new();
+            for (i, item) in items.iter().enumerate() {
+                patch.operations.push(operation(item, &format!("/{}", i))?);
+            }
+            Ok(patch)
+        }
+    }
+
+    fn operation(item: &Value, pointer: &str) -> Result<Operation, ConvertError> {
+        let field = |name: &str| item.get(name).ok_or_else(|| ConvertError::new(pointer, format!("missing `{}`", name)));
+        let string = |name: &str| field(name)?.as_str()
+            .ok_or_else(|| ConvertError::new(&format!("{}/{}", pointer, name), "expected a string"));
+        let path: Path = string("path")?.parse()
+            .map_err(|e: ::path::PathError| ConvertError::new(&format!("{}/path", pointer), e.to_string()))?;
+        let options = || {
+            ::parse_string(string("value")?.to_owned())
+                .map(|mut cfg| ::std::mem::take(cfg.inner_mut()))
+                .map_err(|e| ConvertError::new(&format!("{}/value", pointer), e.to_string()))
+        };
+        Ok(match string("op")? {
+            "add" => {
+                let index = match item.get("index") {
+                    Some(index) => Some(index.as_u64()
+                        .ok_or_else(|| ConvertError::new(&format!("{}/index", pointer), "expected a number"))? as usize),
+                    None => None
+                };
+                Operation::Add { path, index, options: options()? }
+            },
+            "remove" => Operation::Remove { path },
+            "replace" => {
+                let mut options = options()?;
+                if options.len() != 1 {
+                    return Err(ConvertError::new(&format!("{}/value", pointer), "expected one option"));
+                }
+                Operation::Replace { path, option: options.remove(0) }
+            },
+            "set" => {
+                let params = field("params")?.as_array()
+                    .and_then(|params| params.iter().map(|p| p.as_str().map(|p| p.to_owned())).collect::<Option<Vec<_>>>())
+                    .ok_or_else(|| ConvertError::new(&format!("{}/params", pointer), "expected an array of strings"))?;
+                Operation::Set { path, params }
+            },
+            other => return Err(ConvertError::new(&format!("{}/op", pointer), format!("unknown operation `{}`", other)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use diff::diff;
+
+    fn parse(text: &str) -> ConfigBlock {
+        ::parse_string(String::from(text)).unwrap()
+    }
+
+    #[test]
+    fn test_operations() {
+        let patch = Patch::from_config(&parse(r"
+            add server[a] { channel y; }
+            add / 0 { user q; }
+            remove server[a]/channel[x];
+            replace server[a]/port { port 6697 tls; }
+            set server[b] c;
+        ")).unwrap();
+        let mut cfg = config! {
+            server a { port 6667; channel x; }
+            server b;
+        };
+        patch.apply(&mut cfg).unwrap();
+        assert_eq!(cfg, config! {
+            user q;
+            server a { port 6697 tls; channel y; }
+            server c;
+        });
+        assert_eq!(Patch::from_config(&parse(&patch.to_string())).unwrap(), patch);
+    }
+
+    #[test]
+    fn test_atomic_errors() {
+        let mut cfg = config! { server a; server b; };
+        let patch = Patch::new()
+            .operation(Operation::Set { path: "server[a]".parse().unwrap(), params: vec![String::from("c")] })
+            .operation(Operation::Set { path: "server".parse().unwrap(), params: vec![] });
+        assert_eq!(patch.apply(&mut cfg).unwrap_err().to_string(), "operation 2: `server` matches 2 options, expected one");
+        assert_eq!(cfg, config! { server a; server b; });
+
+        let mut cfg = config! { s a { c x; } s a { c x; } };
+        let patch = Patch::new().operation(Operation::Remove { path: "s[a]/c[x]".parse().unwrap() })
+            .operation(Operation::Set { path: "s[a]/c[x]".parse().unwrap(), params: vec![] });
+        assert_eq!(patch.apply(&mut cfg).unwrap_err().to_string(), "operation 2: `s[a]` matches 2 options, expected one");
+
+        let error = |text: &str| {
+            Patch::from_config(&parse(text)).unwrap().apply(&mut config! { a; }).unwrap_err().to_string()
+        };
+        assert_eq!(error("remove b;"), "operation 1: `b` does not match any option");
+        assert_eq!(error("add a 2 { c; }"), "operation 1: index 2 is out of range, `a` has 0 options");
+        assert_eq!(error("set /;"), "operation 1: only `add` can change the root");
+        assert_eq!(Patch::from_config(&parse("move a;")).unwrap_err().to_string(), "1:1: error: unknown patch operation `move`");
+    }
+
+    #[test]
+    fn test_from_diff() {
+        let old = config! {
+            nick bot;
+            network old { host a; }
+            server a { channel x; channel x; channel y; port 1; }
+            server b { channel z; }
+        };
+        let new = config! {
+            nick bot2;
+            network new { host b; }
+            server a { channel w; port 1; channel x; channel v; }
+            server b { channel z; }
+            user q;
+        };
+        let patch = Patch::from_diff(&diff(&old, &new));
+        let mut cfg = old.clone();
+        patch.apply(&mut cfg).unwrap();
+        // The diff ignores the order of matched options, so does the patch
+        assert_eq!(cfg, config! {
+            nick bot2;
+            network new { host b; }
+            server a { channel w; channel x; port 1; channel v; }
+            server b { channel z; }
+            user q;
+        });
+        assert!(diff(&cfg, &new).is_empty());
+    }
+
+    #[test]
+    fn test_from_diff_with_repeated_options() {
+        let cases = [
+            ("u a 1; u a 2; u a 3;", "u a 3x;"),
+            ("x 1; x 1 2;", "x 1 3;"),
+            ("x 1 2; x 1;", "x 1;"),
+            ("s a { c x; } s a { c y; }", "s a { c z; }"),
+            ("s a { c x; } s a { c y; }", "s a { c x; } s a { c z; d; }"),
+            ("server a { port 1; c x; c x; }", "server b { port 2; c x; }"),
+        ];
+        for &(old, new) in &cases {
+            let (old, new) = (parse(old), parse(new));
+            let mut cfg = old.clone();
+            Patch::from_diff(&diff(&old, &new)).apply(&mut cfg).unwrap();
+            assert!(diff(&cfg, &new).is_empty(), "{} -> {}", old, new);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let patch = Patch::from_json(r#"[
+            {"op": "add", "path": "/", "index": 0, "value": "user q;"},
+            {"op": "replace", "path": "server", "value": "server b { port 1; }"},
+            {"op": "set", "path": "user", "params": ["r"]},
+            {"op": "remove", "path": "nick"}
+        ]"#).unwrap();
+        let mut cfg = config! { nick x; server a; };
+        patch.apply(&mut cfg).unwrap();
+        assert_eq!(cfg, config! { user r; server b { port 1; } });
+        assert_eq!(Patch::from_json(r#"[{"op": "set", "path": "a", "params": [1]}]"#).unwrap_err().to_string(),
+            "/0/params: expected an array of strings");
+        assert_eq!(Patch::from_json(r#"[{"op": "replace", "path": "a", "value": "a 1; a 2;"}]"#).unwrap_err().to_string(),
+            "/0/value: expected one option");
+    }
+}
@@ -0,0 +1,332 @@
+//! Addresses options inside a tree
+//!
+//! A path is a list of segments separated by `/`, each selecting inner options of the
+//! blocks matched so far:
+//!
+//! * `name` selects all options called `name`, `*` selects all options
+//! * `name[value]` selects those whose first parameter is `value`
+//! * `name#2` selects the third of the options matched by the rest of the segment
+//!
+//! `\` escapes the next character, so `channel[\#rust]` matches `channel "#rust"`.
+//!
+//! ```
+//! # #[macro_use] extern crate config_parser;
+//! # use config_parser::path::Path;
+//! # fn main() {
+//! let cfg = config! {
+//!     server a { channel x; }
+//!     server b { channel y; channel z; }
+//! };
+//! let path: Path = "server[b]/channel#1".parse().unwrap();
+//! assert_eq!(path.select(&cfg)[0].get(0), "z");
+//! # }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+use std::error;
+use super::config::ConfigBlock;
+
+/// A parsed path, see the module documentation for the syntax
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Path {
+    segments: Vec<Segment>
+}
+
+/// One step of a path
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Segment {
+    /// The option name, `None` for `*`
+    pub name: Option<String>,
+    /// The required first parameter
+    pub param: Option<String>,
+    /// The position among the options matching name and parameter
+    pub index: Option<usize>
+}
+
+/// A malformed path
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PathError {
+    message: String
+}
+
+impl Path {
+    /// Returns the path selecting nothing but the block it is applied to
+    pub fn root() -> Path {
+        Path::default()
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns this path extended by one segment
+    pub fn join(&self, segment: Segment) -> Path {
+        let mut segments = self.segments.clone();
+        segments.push(segment);
+        Path { segments }
+    }
+
+    /// Returns all options the path matches below `root`, in document order
+    pub fn select<'a>(&self, root: &'a ConfigBlock) -> Vec<&'a ConfigBlock> {
+        let mut current = vec![root];
+        for segment in &self.segments {
+            let mut next = vec![];
+            for block in current {
+                next.extend(segment.select(block));
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// Returns the single option the path matches, if there is exactly one
+    pub fn get<'a>(&self, root: &'a ConfigBlock) -> Option<&'a ConfigBlock> {
+        match self.select(root).as_slice() {
+            [block] => Some(*block),
+            _ => None
+        }
+    }
+
+    /// Returns the single option the path matches for modification, if there is exactly one
+    pub fn get_mut<'a>(&self, root: &'a mut ConfigBlock) -> Option<&'a mut ConfigBlock> {
+        let mut current = root;
+        for segment in &self.segments {
+            let indices = segment.indices(current);
+            if indices.len() != 1 {
+                return None;
+            }
+            current = &mut current.inner_mut()[indices[0]];
+        }
+        Some(current)
+    }
+}
+
+impl Segment {
+    /// Creates a segment matching options by name
+    pub fn new<S: Into<String>>(name: S) -> Segment {
+        Segment { name: Some(name.into()), param: None, index: None }
+    }
+
+    /// Creates a segment matching all options
+    pub fn any() -> Segment {
+        Segment { name: None, param: None, index: None }
+    }
+
+    /// Restricts the segment to options with the given first parameter
+    pub fn param<S: Into<String>>(mut self, param: S) -> Segment {
+        self.param = Some(param.into());
+        self
+    }
+
+    /// Restricts the segment to the nth match
+    pub fn index(mut self, index: usize) -> Segment {
+        self.index = Some(index);
+        self
+    }
+
+    /// Returns whether an option matches name and parameter, ignoring the index
+    pub fn matches(&self, block: &ConfigBlock) -> bool {
+        let name = match self.name {
+            Some(ref name) => name == block.name(),
+            None => true
+        };
+        let param = match self.param {
+            Some(ref param) => block.get_opt(0) == Some(&**param),
+            None => true
+        };
+        name && param
+    }
+
+    /// Returns the positions of the matching options in `block.inner()`
+    pub fn indices(&self, block: &ConfigBlock) -> Vec<usize> {
+        let matching = block.inner().iter().enumerate().filter(|&(_, b)| self.matches(b)).map(|(i, _)| i);
+        match self.index {
+            Some(n) => matching.skip(n).take(1).collect(),
+            None => matching.collect()
+        }
+    }
+
+    fn select<'a>(&self, block: &'a ConfigBlock) -> Vec<&'a ConfigBlock> {
+        self.indices(block).into_iter().map(|i| &block.inner()[i]).collect()
+    }
+}
+
+impl FromStr for Path {
+    type Err = PathError;
+
+    fn from_str(s: &str) -> Result<Path, PathError> {
+        let mut segments = vec![];
+        let mut chars = s.chars().peekable();
+        if chars.peek() == Some(&'/') {
+            chars.next();
+        }
+        while chars.peek().is_some() {
+            let (name, escaped) = read_part(&mut chars, &['/', '[', '#']);
+            let mut segment = match &*name {
+                "" => return Err(PathError::new("empty segment")),
+                // `\*` is an option called `*`
+                "*" if !escaped => Segment::any(),
+                _ => Segment::new(name)
+            };
+            if chars.peek() == Some(&'[') {
+                chars.next();
+                segment.param = Some(read_part(&mut chars, &[']']).0);
+                if chars.next() != Some(']') {
+                    return Err(PathError::new("unclosed `[`"));
+                }
+            }
+            if chars.peek() == Some(&'#') {
+                chars.next();
+                let (index, _) = read_part(&mut chars, &['/']);
+                segment.index = match index.parse() {
+                    Ok(i) => Some(i),
+                    Err(_) => return Err(PathError::new(format!("invalid index `{}`", index)))
+                };
+            }
+            match chars.next() {
+                None | Some('/') => {},
+                Some(c) => return Err(PathError::new(format!("unexpected `{}`", c)))
+            }
+            segments.push(segment);
+        }
+        Ok(Path { segments })
+    }
+}
+
+/// Reads up to the next unescaped stop character, returning the unescaped text and
+/// whether it contained escapes
+fn read_part<I: Iterator<Item=char>>(chars: &mut ::std::iter::Peekable<I>, stop: &[char]) -> (String, bool) {
+    let mut out = String::new();
+    let mut escaped = false;
+    while let Some(&c) = chars.peek() {
+        if stop.contains(&c) {
+            break;
+        }
+        chars.next();
+        match c {
+            '\\' => if let Some(c) = chars.next() {
+                escaped = true;
+                out.push(c)
+            },
+            c => out.push(c)
+        }
+    }
+    (out, escaped)
+}
+
+fn write_escaped(f: &mut fmt::Formatter, s: &str, special: &[char]) -> fmt::Result {
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            write!(f, "\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) if name == "*" => f.write_str("\\*")?,
+            Some(ref name) => write_escaped(f, name, &['/', '[', '#'])?,
+            None => f.write_str("*")?
+        }
+        if let Some(ref param) = self.param {
+            f.write_str("[")?;
+            write_escaped(f, param, &[']'])?;
+            f.write_str("]")?;
+        }
+        match self.index {
+            Some(i) => write!(f, "#{}", i),
+            None => Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str("/");
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            write!(f, "{}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+impl PathError {
+    fn new<S: Into<String>>(message: S) -> PathError {
+        PathError { message: message.into() }
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid path: {}", self.message)
+    }
+}
+
+impl error::Error for PathError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> ConfigBlock {
+        config! {
+            server a { channel "#x"; channel "#y" key; }
+            server b { channel "#z"; }
+            user q;
+        }
+    }
+
+    fn names(path: &str) -> Vec<String> {
+        let cfg = sample();
+        let path: Path = path.parse().unwrap();
+        path.select(&cfg).iter().map(|b| format!("{} {}", b.name(), b.params().join(" "))).collect()
+    }
+
+    #[test]
+    fn test_selecting() {
+        assert_eq!(names("server/channel"), vec!["channel #x", "channel #y key", "channel #z"]);
+        assert_eq!(names("server[b]/channel"), vec!["channel #z"]);
+        assert_eq!(names("/server/channel#1"), vec!["channel #y key"]);
+        assert_eq!(names("*#2"), vec!["user q"]);
+        assert_eq!(names("server/channel[\\#y]"), vec!["channel #y key"]);
+        assert_eq!(names("nothing"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut cfg = sample();
+        "server[a]/channel#0".parse::<Path>().unwrap().get_mut(&mut cfg).unwrap().push_param("k");
+        assert_eq!(cfg.inner()[0].inner()[0].params(), &vec!["#x", "k"]);
+        assert!("server/channel".parse::<Path>().unwrap().get_mut(&mut cfg).is_none());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in &["server[a b]/channel#1", "*/x\\/y[\\]]", "/"] {
+            let path: Path = s.parse().unwrap();
+            assert_eq!(path.to_string().parse::<Path>().unwrap(), path);
+        }
+        assert_eq!(Path::root().join(Segment::new("a#b").param("#c")).to_string(), "a\\#b[#c]");
+
+        let star = Path::root().join(Segment::new("*"));
+        assert_eq!(star.to_string(), "\\*");
+        assert_eq!(star.to_string().parse::<Path>().unwrap(), star);
+        assert_ne!(star, "*".parse().unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("a//b".parse::<Path>().unwrap_err().to_string(), "invalid path: empty segment");
+        assert_eq!("a[b".parse::<Path>().unwrap_err().to_string(), "invalid path: unclosed `[`");
+        assert_eq!("a#x".parse::<Path>().unwrap_err().to_string(), "invalid path: invalid index `x`");
+    }
+}
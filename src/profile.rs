@@ -0,0 +1,134 @@
+//! Named overlays
+//!
+//! With `ParseOptions::profiles`, options named `profile` hold settings which only apply
+//! when the caller selects them:
+//!
+//! ```text
+//! server freenode {
+//!     connect irc.freenode.net 6697 tls;
+//!     channel "#test";
+//! }
+//!
+//! profile dev {
+//!     server freenode { nick DevBot; !channel; channel "#bot-dev"; }
+//! }
+//! ```
+//!
+//! Parsed with `ParseOptions::new().profiles(&["dev"])`, the tree contains `server
+//! freenode` with `nick DevBot` and only the `#bot-dev` channel. The definitions are
+//! removed from the tree whether their profile is active or not.
+//!
+//! A profile is merged into the block it is defined in with `merge::merge`, so options
+//! are matched by name and first parameter and `!name` removes options. Active profiles
+//! are applied in the order they were selected, so the last one wins where several set
+//! the same option. Profiles nested in a block only apply to that block, and are applied
+//! before the profiles of the enclosing blocks.
+//!
+//! Profiles are applied after conditions and templates. `available` lists the profiles
+//! of a tree parsed without selecting any.
+
+use std::collections::HashMap;
+use super::config::ConfigBlock;
+use super::error::{Error, ErrorType, Result};
+use super::merge;
+
+/// Returns the names of the profiles defined anywhere in a tree, in the order they
+/// first appear
+pub fn available(cfg: &ConfigBlock) -> Vec<&str> {
+    let mut names = vec![];
+    collect(cfg, &mut names);
+    names
+}
+
+fn collect<'a>(block: &'a ConfigBlock, names: &mut Vec<&'a str>) {
+    for option in block.inner() {
+        if let (true, Some(name)) = (option.name() == "profile", option.get_opt(0)) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        collect(option, names);
+    }
+}
+
+/// Removes the profile definitions from a tree and applies the active profiles in order
+pub fn apply(cfg: &ConfigBlock, active: &[String]) -> Result<ConfigBlock> {
+    let mut profiles = HashMap::new();
+    for profile in cfg.inner().iter().filter(|b| b.name() == "profile") {
+        let name = match profile.get_opt(0) {
+            Some(name) => name,
+            None => return Err(fail(profile, String::from("missing profile name"), None))
+        };
+        if let Some(first) = profiles.insert(name, profile) {
+            return Err(fail(profile, format!("profile `{}` is defined twice", name), Some(first)));
+        }
+    }
+    let inner = cfg.inner().iter()
+        .filter(|b| b.name() != "profile")
+        .map(|b| apply(b, active))
+        .collect::<Result<_>>()?;
+    let mut applied = ConfigBlock::new(cfg.name().to_owned(), cfg.params().clone(), inner);
+    applied.set_span(cfg.span());
+    for name in active {
+        if let Some(profile) = profiles.get(&**name) {
+            let body = apply(profile, active)?;
+            let overlay = ConfigBlock::new(cfg.name().to_owned(), cfg.params().clone(), body.inner().clone());
+            applied = merge::merge(&applied, &overlay);
+        }
+    }
+    Ok(applied)
+}
+
+fn fail(at: &ConfigBlock, message: String, first: Option<&ConfigBlock>) -> Error {
+    let position = |b: &ConfigBlock| b.span().map(|s| s.start).unwrap_or_default();
+    let pos = position(at);
+    Error::new(pos.line, pos.col, ErrorType::Profile(message, first.map(position)), None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use options::ParseOptions;
+
+    const TEXT: &str = "
+        server a {
+            port 6667;
+            channel x;
+            profile tls { port 6697; }
+        }
+        profile dev { server a { nick dev; !channel; channel test; } }
+        profile prod { server a { nick prod; } }
+        nick bot;
+    ";
+
+    fn parse(profiles: &[&str]) -> Result<ConfigBlock> {
+        ::parse_string_with(String::from(TEXT), &ParseOptions::new().profiles(profiles))
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(parse(&[]).unwrap(), config! { server a { port 6667; channel x; } nick bot; });
+        assert_eq!(parse(&["dev", "tls"]).unwrap(), config! {
+            server a { port 6697; nick dev; channel test; }
+            nick bot;
+        });
+        // The last active profile wins
+        assert_eq!(parse(&["prod", "dev"]).unwrap().inner()[0].matching("nick").next().map(|n| n.get(0)), Some("dev"));
+        assert_eq!(parse(&["dev", "prod"]).unwrap().inner()[0].matching("nick").next().map(|n| n.get(0)), Some("prod"));
+    }
+
+    #[test]
+    fn test_available() {
+        let cfg = ::parse_string(String::from(TEXT)).unwrap();
+        assert_eq!(available(&cfg), ["tls", "dev", "prod"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |text: &str| {
+            ::parse_string_with(String::from(text), &ParseOptions::new().profiles(&[])).unwrap_err().to_string()
+        };
+        assert_eq!(message("profile { a; }"), "1:1: missing profile name");
+        assert_eq!(message("a { profile p; profile p; }"), "1:16: profile `p` is defined twice, see 1:5");
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::{BufRead, ErrorKind};
+use std::str;
+use super::error::{Error, ErrorType};
+
+/// Decodes UTF-8 from a reader as it is consumed
+///
+/// Only the reader's buffer is held in memory. The iterator ends on the first I/O error
+/// or invalid byte sequence, which is then available through `take_error()`.
+pub struct ReadChars<R> {
+    reader: R,
+    line: u32,
+    col: u16,
+    error: Option<Error>
+}
+
+impl <R> ReadChars<R> where R: BufRead {
+    pub fn new(reader: R) -> ReadChars<R> {
+        ReadChars {
+            reader,
+            line: 1,
+            col: 0,
+            error: None
+        }
+    }
+
+    /// Returns the error which ended the iteration, if any
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
+    fn fail(&mut self, error_type: ErrorType) -> Option<char> {
+        self.error = Some(Error::new(self.line, self.col, error_type, None));
+        None
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, ErrorType> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok([]) => return Ok(None),
+                Ok(buf) => {
+                    let b = buf[0];
+                    self.reader.consume(1);
+                    return Ok(Some(b));
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(ErrorType::Io(e.kind(), e.to_string()))
+            }
+        }
+    }
+
+    fn decode(&mut self) -> Result<Option<char>, ErrorType> {
+        let first = match self.next_byte()? {
+            Some(b) if b < 0x80 => return Ok(Some(b as char)),
+            Some(b) => b,
+            None => return Ok(None)
+        };
+        let width = match first {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Err(ErrorType::InvalidUtf8)
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for b in bytes.iter_mut().take(width).skip(1) {
+            match self.next_byte()? {
+                Some(next) => *b = next,
+                None => return Err(ErrorType::InvalidUtf8)
+            }
+        }
+        match str::from_utf8(&bytes[..width]) {
+            Ok(s) => Ok(s.chars().next()),
+            Err(_) => Err(ErrorType::InvalidUtf8)
+        }
+    }
+}
+
+impl <R> Iterator for ReadChars<R> where R: BufRead {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.decode() {
+            Ok(Some('\n')) => {
+                self.line = self.line.saturating_add(1);
+                self.col = 0;
+                Some('\n')
+            },
+            Ok(Some(c)) => {
+                self.col = self.col.saturating_add(1);
+                Some(c)
+            },
+            Ok(None) => None,
+            Err(e) => self.fail(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{self, BufReader, Read};
+
+    /// Hands out a single byte per read call
+    struct Trickle<'a>(&'a [u8]);
+
+    impl <'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn decodes_chars_split_across_reads() {
+        let input = "a ö € 𝄞";
+        let mut chars = ReadChars::new(BufReader::with_capacity(1, Trickle(input.as_bytes())));
+        assert_eq!(chars.by_ref().collect::<String>(), input);
+        assert_eq!(chars.take_error(), None);
+    }
+
+    #[test]
+    fn stops_on_invalid_utf8() {
+        let mut chars = ReadChars::new(&b"ab\n\xff\xfe"[..]);
+        assert_eq!(chars.by_ref().collect::<String>(), "ab\n");
+        assert_eq!(chars.take_error(), Some(Error::new(2, 0, ErrorType::InvalidUtf8, None)));
+    }
+}
@@ -0,0 +1,160 @@
+//! Hiding sensitive parameters from logs
+//!
+//! A `Policy` says which parameters are sensitive, by path or through the `sensitive`
+//! flag of a schema, and formats trees with `***` in their place:
+//!
+//! ```
+//! # #[macro_use] extern crate config_parser;
+//! # use config_parser::redact::Policy;
+//! # fn main() {
+//! let cfg = config! { server a { channel "#secret" mypassword; nick bot; } };
+//! let policy = Policy::new().path("server/channel".parse().unwrap(), 1);
+//! assert_eq!(policy.display(&cfg).to_string(), "server a {\n    channel \"#secret\" ***;\n    nick bot;\n}\n");
+//! # }
+//! ```
+//!
+//! Each rule keeps a number of leading parameters visible, like the channel name above,
+//! and hides the rest. Parameters which are secrets, see the `secret` module, are hidden
+//! anyway.
+
+use std::fmt;
+use super::config::{ConfigBlock, REDACTED};
+use super::path::{Path, Segment};
+use super::schema::Schema;
+
+/// Decides which parameters are hidden
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    paths: Vec<(Path, usize)>,
+    schema: Option<Schema>
+}
+
+/// A tree formatted according to a policy. `Display` writes the config syntax
+pub struct Redacted<'a> {
+    policy: &'a Policy,
+    cfg: &'a ConfigBlock
+}
+
+impl Policy {
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// Hides the parameters after the first `keep` ones of all options the path matches
+    pub fn path(mut self, path: Path, keep: usize) -> Policy {
+        self.paths.push((path, keep));
+        self
+    }
+
+    /// Hides the parameters the schema marks as `sensitive`
+    pub fn schema(mut self, schema: Schema) -> Policy {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Returns a copy of the tree with the sensitive parameters replaced by `***`
+    pub fn apply(&self, cfg: &ConfigBlock) -> ConfigBlock {
+        let mut redacted = cfg.clone();
+        for &(ref path, keep) in &self.paths {
+            hide_path(&mut redacted, path.segments(), keep);
+        }
+        if let Some(ref schema) = self.schema {
+            hide_schema(&mut redacted, schema);
+        }
+        redacted
+    }
+
+    /// Wraps a tree for formatting with `{}` or `{:?}`, like in log messages
+    pub fn display<'a>(&'a self, cfg: &'a ConfigBlock) -> Redacted<'a> {
+        Redacted { policy: self, cfg }
+    }
+}
+
+fn hide(block: &mut ConfigBlock, keep: usize) {
+    for i in keep..block.len() {
+        block.set_param(i, REDACTED);
+    }
+}
+
+fn hide_path(block: &mut ConfigBlock, segments: &[Segment], keep: usize) {
+    match segments.split_first() {
+        Some((segment, rest)) => {
+            for i in segment.indices(block) {
+                hide_path(&mut block.inner_mut()[i], rest, keep);
+            }
+        },
+        None => hide(block, keep)
+    }
+}
+
+fn hide_schema(block: &mut ConfigBlock, schema: &Schema) {
+    for inner in block.inner_mut() {
+        let directive = match schema.get(inner.name()) {
+            Some(directive) => directive,
+            None => continue
+        };
+        if let Some(keep) = directive.sensitive_from() {
+            hide(inner, keep);
+        }
+        if let Some(schema) = directive.block_schema() {
+            hide_schema(inner, schema);
+        }
+    }
+}
+
+impl <'a> fmt::Display for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.policy.apply(self.cfg), f)
+    }
+}
+
+impl <'a> fmt::Debug for Redacted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.policy.apply(self.cfg), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(text: &str) -> ConfigBlock {
+        ::parse_string(String::from(text)).unwrap()
+    }
+
+    #[test]
+    fn test_paths() {
+        let cfg = parse("
+            server a { channel x key1; channel y key2; user admin pass; }
+            server b { channel z key3; }
+            password hunter2;
+        ");
+        let policy = Policy::new()
+            .path("server[a]/channel".parse().unwrap(), 1)
+            .path("*/user".parse().unwrap(), 0)
+            .path("password".parse().unwrap(), 0);
+        assert_eq!(policy.apply(&cfg), parse("
+            server a { channel x ***; channel y ***; user *** ***; }
+            server b { channel z key3; }
+            password ***;
+        "));
+        let debug = format!("{:?}", policy.display(&cfg));
+        assert!(!debug.contains("key1") && !debug.contains("hunter2") && debug.contains("key3"));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = Schema::from_config(&parse("
+            directive server {
+                block {
+                    directive channel { params 1 2; sensitive 1; }
+                    directive sasl { params 2; sensitive; }
+                }
+            }
+            directive channel { params 1 2; }
+        ")).unwrap();
+        let cfg = parse("server a { channel x key; sasl bot pass; } channel y key;");
+        assert_eq!(Policy::new().schema(schema).display(&cfg).to_string(),
+            "server a {\n    channel x ***;\n    sasl *** ***;\n}\nchannel y key;\n");
+    }
+}
@@ -0,0 +1,392 @@
+//! Describes which options a config file may contain
+//!
+//! A schema can be built in code or loaded from a file in the config syntax itself:
+//!
+//! ```text
+//! directive server {
+//!     doc "An IRC server to connect to";
+//!     params 1;
+//!     block {
+//!         directive connect { params 2 3; required; unique; }
+//!         directive channel { params 1 2; }
+//!         directive user {
+//!             params 1;
+//!             block { allow_unknown; }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! `params N` requires exactly N parameters, `params MIN MAX` a range and `params MIN *`
+//! at least MIN. Without `params` any number is accepted. `deprecated` takes an optional
+//! note, which the linter reports when the option is used. `merge` sets the strategy
+//! for layering configs, see the `merge` module. `sensitive` hides the parameters of an
+//! option from logs, see the `redact` module; `sensitive 1;` keeps the first one
+//! visible. Options without a `block` must not have inner options. Unknown options are
+//! errors unless `allow_unknown` is given.
+
+use std::collections::HashMap;
+use super::config::ConfigBlock;
+use super::diagnostic::Diagnostic;
+use super::merge::Strategy;
+
+/// The allowed contents of a block or the document root
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Schema {
+    directives: Vec<DirectiveSchema>,
+    allow_unknown: bool
+}
+
+/// Describes a single option
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DirectiveSchema {
+    name: String,
+    min_params: usize,
+    max_params: Option<usize>,
+    required: bool,
+    unique: bool,
+    block: Option<Schema>,
+    doc: Option<String>,
+    deprecated: Option<String>,
+    merge: Option<Strategy>,
+    sensitive: Option<usize>
+}
+
+impl Schema {
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Adds a known option
+    pub fn directive(mut self, directive: DirectiveSchema) -> Schema {
+        self.directives.push(directive);
+        self
+    }
+
+    /// Sets whether options which are not described are accepted
+    pub fn allow_unknown(mut self, allow: bool) -> Schema {
+        self.allow_unknown = allow;
+        self
+    }
+
+    /// Returns the description of an option by name
+    pub fn get(&self, name: &str) -> Option<&DirectiveSchema> {
+        self.directives.iter().find(|d| d.name == name)
+    }
+
+    /// Returns all known options
+    pub fn directives(&self) -> &[DirectiveSchema] {
+        &self.directives
+    }
+
+    pub fn allows_unknown(&self) -> bool {
+        self.allow_unknown
+    }
+
+    /// Reads a schema from its config representation, see the module documentation
+    pub fn from_config(cfg: &ConfigBlock) -> Result<Schema, Diagnostic> {
+        let mut schema = Schema::new();
+        for item in cfg.inner() {
+            match item.name() {
+                "allow_unknown" => schema.allow_unknown = true,
+                "directive" => schema.directives.push(DirectiveSchema::from_config(item)?),
+                other => return Err(Diagnostic::error(format!("unknown schema option `{}`", other), item.span()))
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Returns the schema of the block reached by following the given option names
+    pub fn lookup(&self, path: &[&str]) -> Option<&Schema> {
+        let mut schema = self;
+        for name in path {
+            schema = schema.get(name)?.block_schema()?;
+        }
+        Some(schema)
+    }
+
+    /// Checks a block against this schema, returning everything which doesn't match
+    pub fn validate(&self, cfg: &ConfigBlock) -> Vec<Diagnostic> {
+        let mut out = vec![];
+        self.validate_into(cfg, &mut out);
+        out
+    }
+
+    fn validate_into(&self, cfg: &ConfigBlock, out: &mut Vec<Diagnostic>) {
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for item in cfg.inner() {
+            let count = seen.entry(item.name()).or_insert(0);
+            *count += 1;
+            match self.get(item.name()) {
+                Some(d) => {
+                    if d.unique && *count == 2 {
+                        out.push(Diagnostic::error(format!("`{}` may only be given once", d.name), item.span()));
+                    }
+                    d.validate_into(item, out);
+                },
+                None if self.allow_unknown => {},
+                None => out.push(Diagnostic::error(format!("unknown option `{}`", item.name()), item.span()))
+            }
+        }
+        for d in self.directives.iter().filter(|d| d.required && !seen.contains_key(&*d.name)) {
+            let span = cfg.span();
+            let message = if cfg.name().is_empty() {
+                format!("missing required option `{}`", d.name)
+            } else {
+                format!("missing required option `{}` in `{}`", d.name, cfg.name())
+            };
+            out.push(Diagnostic::error(message, span));
+        }
+    }
+}
+
+impl DirectiveSchema {
+    pub fn new<S: Into<String>>(name: S) -> DirectiveSchema {
+        DirectiveSchema {
+            name: name.into(),
+            min_params: 0,
+            max_params: None,
+            required: false,
+            unique: false,
+            block: None,
+            doc: None,
+            deprecated: None,
+            merge: None,
+            sensitive: None
+        }
+    }
+
+    /// Sets the allowed number of parameters. `None` as maximum means unbounded
+    pub fn params(mut self, min: usize, max: Option<usize>) -> DirectiveSchema {
+        self.min_params = min;
+        self.max_params = max;
+        self
+    }
+
+    /// Sets whether the option has to be present
+    pub fn required(mut self, required: bool) -> DirectiveSchema {
+        self.required = required;
+        self
+    }
+
+    /// Sets whether the option may only appear once per block
+    pub fn unique(mut self, unique: bool) -> DirectiveSchema {
+        self.unique = unique;
+        self
+    }
+
+    /// Allows a block after the option, with the given contents
+    pub fn block(mut self, schema: Schema) -> DirectiveSchema {
+        self.block = Some(schema);
+        self
+    }
+
+    /// Sets a human readable description
+    pub fn doc<S: Into<String>>(mut self, doc: S) -> DirectiveSchema {
+        self.doc = Some(doc.into());
+        self
+    }
+
+    /// Marks the option as deprecated. The note, which may be empty, should tell what to
+    /// use instead
+    pub fn deprecated<S: Into<String>>(mut self, note: S) -> DirectiveSchema {
+        self.deprecated = Some(note.into());
+        self
+    }
+
+    /// Sets how the option is merged when layering configs
+    pub fn merge(mut self, strategy: Strategy) -> DirectiveSchema {
+        self.merge = Some(strategy);
+        self
+    }
+
+    /// Marks the parameters after the first `keep` ones as sensitive, so that a
+    /// `redact::Policy` using the schema hides them
+    pub fn sensitive(mut self, keep: usize) -> DirectiveSchema {
+        self.sensitive = Some(keep);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the minimum and maximum parameter count
+    pub fn param_range(&self) -> (usize, Option<usize>) {
+        (self.min_params, self.max_params)
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    /// Returns the schema of the block following this option, if it takes one
+    pub fn block_schema(&self) -> Option<&Schema> {
+        self.block.as_ref()
+    }
+
+    pub fn documentation(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    /// Returns the deprecation note if the option is deprecated
+    pub fn deprecation(&self) -> Option<&str> {
+        self.deprecated.as_deref()
+    }
+
+    /// Returns the merge strategy, if the schema sets one
+    pub fn merge_strategy(&self) -> Option<Strategy> {
+        self.merge
+    }
+
+    /// Returns the index of the first sensitive parameter, if there are any
+    pub fn sensitive_from(&self) -> Option<usize> {
+        self.sensitive
+    }
+
+    fn from_config(cfg: &ConfigBlock) -> Result<DirectiveSchema, Diagnostic> {
+        let name = match cfg.get_opt(0) {
+            Some(name) if cfg.len() == 1 => name,
+            _ => return Err(Diagnostic::error("`directive` expects exactly one name", cfg.span()))
+        };
+        let mut d = DirectiveSchema::new(name);
+        for item in cfg.inner() {
+            match item.name() {
+                "params" => {
+                    let (min, max) = match item.len() {
+                        1 => {
+                            let n = parse_count(item, 0)?;
+                            (n, Some(n))
+                        },
+                        2 if item.get(1) == "*" => (parse_count(item, 0)?, None),
+                        2 => (parse_count(item, 0)?, Some(parse_count(item, 1)?)),
+                        _ => return Err(Diagnostic::error("`params` expects one or two counts", item.span()))
+                    };
+                    d = d.params(min, max);
+                },
+                "required" => d.required = true,
+                "unique" => d.unique = true,
+                "doc" => d.doc = Some(item.params().join(" ")),
+                "deprecated" => d.deprecated = Some(item.params().join(" ")),
+                "block" => d.block = Some(Schema::from_config(item)?),
+                "merge" => match item.get_opt(0) {
+                    Some(strategy) if item.len() == 1 => {
+                        d.merge = Some(strategy.parse().map_err(|e: String| Diagnostic::error(e, item.span()))?);
+                    },
+                    _ => return Err(Diagnostic::error("`merge` expects a strategy", item.span()))
+                },
+                "sensitive" => d.sensitive = Some(match item.len() {
+                    0 => 0,
+                    1 => parse_count(item, 0)?,
+                    _ => return Err(Diagnostic::error("`sensitive` expects at most one count", item.span()))
+                }),
+                other => return Err(Diagnostic::error(format!("unknown directive option `{}`", other), item.span()))
+            }
+        }
+        Ok(d)
+    }
+
+    fn validate_into(&self, cfg: &ConfigBlock, out: &mut Vec<Diagnostic>) {
+        let count = cfg.len();
+        let too_few = count < self.min_params;
+        let too_many = self.max_params.map(|max| count > max).unwrap_or(false);
+        if too_few || too_many {
+            let expected = match self.max_params {
+                Some(max) if max == self.min_params => format!("{}", max),
+                Some(max) => format!("{} to {}", self.min_params, max),
+                None => format!("at least {}", self.min_params)
+            };
+            out.push(Diagnostic::error(
+                format!("`{}` expects {} parameters, found {}", self.name, expected, count),
+                cfg.span()));
+        }
+        match self.block {
+            Some(ref schema) => schema.validate_into(cfg, out),
+            None if !cfg.inner().is_empty() => {
+                out.push(Diagnostic::error(format!("`{}` does not take a block", self.name), cfg.span()));
+            },
+            None => {}
+        }
+    }
+}
+
+fn parse_count(cfg: &ConfigBlock, i: usize) -> Result<usize, Diagnostic> {
+    cfg.get(i).parse().map_err(|_| {
+        Diagnostic::error(format!("`{}` is not a valid parameter count", cfg.get(i)), cfg.span())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCHEMA: &str = "
+        directive server {
+            doc \"An IRC server\";
+            params 1;
+            block {
+                directive connect { params 2 3; required; unique; }
+                directive channel { params 1 2; merge append; }
+                directive user { params 1; block { allow_unknown; } }
+                directive nick { deprecated use user; }
+            }
+        }
+    ";
+
+    fn schema() -> Schema {
+        Schema::from_config(&::parse_string(String::from(SCHEMA)).unwrap()).unwrap()
+    }
+
+    fn messages(input: &str) -> Vec<String> {
+        let cfg = ::parse_string(String::from(input)).unwrap();
+        schema().validate(&cfg).into_iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_loading_a_schema() {
+        let expected = Schema::new()
+            .directive(DirectiveSchema::new("server")
+                .doc("An IRC server")
+                .params(1, Some(1))
+                .block(Schema::new()
+                    .directive(DirectiveSchema::new("connect").params(2, Some(3)).required(true).unique(true))
+                    .directive(DirectiveSchema::new("channel").params(1, Some(2)).merge(Strategy::Append))
+                    .directive(DirectiveSchema::new("user").params(1, Some(1)).block(Schema::new().allow_unknown(true)))
+                    .directive(DirectiveSchema::new("nick").deprecated("use user"))));
+        assert_eq!(schema(), expected);
+        assert_eq!(schema().lookup(&["server", "user"]), Some(&Schema::new().allow_unknown(true)));
+        assert_eq!(schema().lookup(&["server", "nick"]), None);
+    }
+
+    #[test]
+    fn test_accepting_a_valid_config() {
+        assert!(messages("server a { connect host 6697; channel \"#a\"; user x { anything goes; } }").is_empty());
+    }
+
+    #[test]
+    fn test_reporting_violations() {
+        assert_eq!(messages("server a b {\n  connect x;\n  connect y z;\n  nic q;\n  channel c { x; }\n}\nfoo;"), vec![
+            "1:1: error: `server` expects 1 parameters, found 2",
+            "2:3: error: `connect` expects 2 to 3 parameters, found 1",
+            "3:3: error: `connect` may only be given once",
+            "4:3: error: unknown option `nic`",
+            "5:3: error: `channel` does not take a block",
+            "7:1: error: unknown option `foo`"
+        ]);
+        assert_eq!(messages("server a { }"), vec!["1:1: error: missing required option `connect` in `server`"]);
+    }
+
+    #[test]
+    fn test_rejecting_invalid_schemas() {
+        let cfg = ::parse_string(String::from("directive a { params x; }")).unwrap();
+        assert_eq!(Schema::from_config(&cfg).unwrap_err().message, "`x` is not a valid parameter count");
+        let cfg = ::parse_string(String::from("directive a { merge deep; }")).unwrap();
+        assert_eq!(Schema::from_config(&cfg).unwrap_err().message, "unknown merge strategy `deep`, expected merge, replace or append");
+        let cfg = ::parse_string(String::from("directive a { sensitive 1 2; }")).unwrap();
+        assert_eq!(Schema::from_config(&cfg).unwrap_err().message, "`sensitive` expects at most one count");
+    }
+}
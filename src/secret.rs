@@ -0,0 +1,320 @@
+//! References to secrets
+//!
+//! Passwords and keys don't have to be written into the config. With
+//! `ParseOptions::secrets`, a parameter can refer to them instead:
+//!
+//! ```text
+//! channel "#secret" secret("env:CHANNEL_KEY");
+//! sasl bot secret("file:/run/secrets/sasl");
+//! ```
+//!
+//! Parsing keeps the reference as a `secret(env:CHANNEL_KEY)` parameter and marks it,
+//! see `ConfigBlock::is_reference`. `resolve` then looks the secrets up through a
+//! `Provider`, like `Standard` for the environment and files, and stores them with
+//! `ConfigBlock::set_secret_param`. Only marked parameters are resolved, so a quoted
+//! `"secret(env:X)"`, or one read from JSON or YAML, stays an ordinary string.
+//!
+//! `Debug`, the writer and the converters show secrets as `***`, and their memory is
+//! overwritten with zeros when they are dropped or replaced. Merging keeps them marked;
+//! other copies made from the parameters, like `get(i).to_owned()`, are ordinary strings.
+//!
+//! ```
+//! # use config_parser::{secret, ParseOptions};
+//! let options = ParseOptions::new().secrets(true);
+//! let mut cfg = config_parser::parse_string_with(String::from("key secret(\"vault:irc\");"), &options).unwrap();
+//! secret::resolve(&mut cfg, &|reference: &str| Ok(format!("value of {}", reference))).unwrap();
+//! assert_eq!(cfg.inner()[0].get(0), "value of vault:irc");
+//! assert_eq!(cfg.to_string(), "key ***;\n");
+//! ```
+
+use std::{env, fs};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::result;
+use super::config::ConfigBlock;
+use super::error::{Error, ErrorType, Result};
+use super::lexer::{Token, TokenType};
+use super::span::Position;
+use super::writer::quote;
+
+/// Looks up secrets by reference
+pub trait Provider {
+    /// Returns the secret a reference like `env:PASSWORD` stands for, or a message
+    /// explaining why it is not available
+    fn resolve(&self, reference: &str) -> result::Result<String, String>;
+}
+
+/// Reads `env:NAME` from the environment and `file:PATH` from a file, without a trailing
+/// line break
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Standard;
+
+impl Provider for Standard {
+    fn resolve(&self, reference: &str) -> result::Result<String, String> {
+        if let Some(name) = reference.strip_prefix("env:") {
+            env::var(name).map_err(|e| e.to_string())
+        } else if let Some(path) = reference.strip_prefix("file:") {
+            let mut secret = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            let len = secret.trim_end_matches(&['\r', '\n'][..]).len();
+            secret.truncate(len);
+            Ok(secret)
+        } else {
+            Err(String::from("unknown kind of secret, expected env: or file:"))
+        }
+    }
+}
+
+impl <F> Provider for F where F: Fn(&str) -> result::Result<String, String> {
+    fn resolve(&self, reference: &str) -> result::Result<String, String> {
+        self(reference)
+    }
+}
+
+/// Returns the reference if the parameter is a marked secret reference, like `env:X`
+/// for `secret(env:X)`
+pub fn reference(block: &ConfigBlock, i: usize) -> Option<&str> {
+    if block.is_reference(i) {
+        referenced(block.get(i))
+    } else {
+        None
+    }
+}
+
+/// Returns the reference in the text of a reference parameter
+pub(crate) fn referenced(param: &str) -> Option<&str> {
+    param.strip_prefix("secret(")?.strip_suffix(')')
+}
+
+/// Returns the config syntax of a reference, `secret("env:X")` for `env:X`
+pub(crate) fn call(reference: &str) -> String {
+    match quote(reference) {
+        Cow::Borrowed(reference) => format!("secret(\"{}\")", reference),
+        Cow::Owned(quoted) => format!("secret({})", quoted)
+    }
+}
+
+/// Replaces the secret references in a tree with the secrets. Stops at the first
+/// reference which can't be resolved
+pub fn resolve<P>(cfg: &mut ConfigBlock, provider: &P) -> Result<()> where P: Provider + ?Sized {
+    for i in 0..cfg.len() {
+        let secret = match reference(cfg, i) {
+            Some(r) => provider.resolve(r).map_err(|e| fail(cfg, format!("secret `{}`: {}", r, e)))?,
+            None => continue
+        };
+        cfg.set_secret_param(i, secret);
+    }
+    for inner in cfg.inner_mut() {
+        resolve(inner, provider)?;
+    }
+    Ok(())
+}
+
+fn fail(at: &ConfigBlock, message: String) -> Error {
+    let pos = at.span().map(|s| s.start).unwrap_or_default();
+    Error::new(pos.line, pos.col, ErrorType::Secret(message), None)
+}
+
+/// Where the parser found secret references: the start of the option and the index of
+/// the parameter
+pub(crate) type References = Rc<RefCell<Vec<(Position, usize)>>>;
+
+/// Marks the parameters found by `Tokens` as secret references. Options are recognized
+/// by where they start, so this works after conditions, templates and profiles have
+/// rearranged the tree
+pub(crate) fn mark(cfg: &mut ConfigBlock, references: &[(Position, usize)]) {
+    if references.is_empty() {
+        return;
+    }
+    let mut by_option: HashMap<Position, Vec<usize>> = HashMap::new();
+    for &(pos, i) in references {
+        by_option.entry(pos).or_default().push(i);
+    }
+    let mut stack = vec![cfg];
+    while let Some(block) = stack.pop() {
+        if let Some(indices) = block.span().and_then(|s| by_option.get(&s.start)) {
+            for &i in indices {
+                if block.get_opt(i).and_then(referenced).is_some() {
+                    block.mark_reference(i);
+                }
+            }
+        }
+        stack.extend(block.inner_mut().iter_mut());
+    }
+}
+
+/// Passes tokens on, turning `secret ( "reference" )` in parameters into a single
+/// `secret(reference)` literal, which the parser accepts, and recording where it is
+pub(crate) struct Tokens<'a> {
+    tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>,
+    pending: Option<Result<Token>>,
+    /// Where the current option starts, `None` before its name
+    option: Option<Position>,
+    /// The parameters of the current option so far
+    params: usize,
+    references: References
+}
+
+impl <'a> Tokens<'a> {
+    pub(crate) fn new(tokens: Box<dyn Iterator<Item=Result<Token>> + 'a>, references: References) -> Tokens<'a> {
+        Tokens { tokens, pending: None, option: None, params: 0, references }
+    }
+
+    /// Returns the next token which is not a comment
+    fn next_token(&mut self) -> Result<Option<Token>> {
+        loop {
+            match self.tokens.next().transpose()? {
+                Some(Token { token_type: TokenType::Comment(_), .. }) => {},
+                t => return Ok(t)
+            }
+        }
+    }
+
+    fn reference(&mut self, line: u32, col: u16) -> Result<Token> {
+        let name = match self.next_token()? {
+            Some(Token { token_type: TokenType::StringLiteral(name), .. })
+                | Some(Token { token_type: TokenType::RawLiteral(name), .. }) => name,
+            Some(t) => return Err(Error::new(t.line, t.col, ErrorType::Unexpected(t), Some("secret reference"))),
+            None => return Err(Error::new(line, col, ErrorType::UnexpectedEOF, Some("secret reference")))
+        };
+        match self.next_token()? {
+            Some(Token { token_type: TokenType::CloseParen, .. }) => {
+                Ok(Token::new(line, col, TokenType::RawLiteral(format!("secret({})", name))))
+            },
+            Some(t) => Err(Error::new(t.line, t.col, ErrorType::Unexpected(t), Some(")"))),
+            None => Err(Error::new(line, col, ErrorType::UnexpectedEOF, Some(")")))
+        }
+    }
+}
+
+impl <'a> Iterator for Tokens<'a> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Result<Token>> {
+        let token = match self.pending.take().or_else(|| self.tokens.next())? {
+            Ok(token) => token,
+            Err(e) => return Some(Err(e))
+        };
+        let option = match (&token.token_type, self.option) {
+            (&TokenType::Comment(_), _) => return Some(Ok(token)),
+            (&TokenType::OpenBrace, _) | (&TokenType::CloseBrace, _) | (&TokenType::Semicolon, _) => {
+                self.option = None;
+                return Some(Ok(token));
+            },
+            (&TokenType::StringLiteral(_), None) | (&TokenType::RawLiteral(_), None) => {
+                self.option = Some(token.position());
+                self.params = 0;
+                return Some(Ok(token));
+            },
+            (TokenType::RawLiteral(word), Some(option)) if word == "secret" => option,
+            (&TokenType::StringLiteral(_), Some(_)) | (&TokenType::RawLiteral(_), Some(_)) => {
+                self.params += 1;
+                return Some(Ok(token));
+            },
+            _ => return Some(Ok(token))
+        };
+        self.params += 1;
+        // A `secret` parameter only starts a reference if a parenthesis follows
+        match self.tokens.next() {
+            Some(Ok(Token { token_type: TokenType::OpenParen, .. })) => {
+                self.references.borrow_mut().push((option, self.params - 1));
+                Some(self.reference(token.line, token.col))
+            },
+            next => {
+                self.pending = next;
+                Some(Ok(token))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use options::ParseOptions;
+
+    fn parse(text: &str) -> Result<ConfigBlock> {
+        ::parse_string_with(String::from(text), &ParseOptions::new().secrets(true))
+    }
+
+    #[test]
+    fn test_resolve() {
+        let path: PathBuf = env::temp_dir().join(format!("config-parser-secret-{}", std::process::id()));
+        fs::write(&path, "from file\n").unwrap();
+        env::set_var("CONFIG_PARSER_TEST_SECRET", "from env");
+        let mut cfg = parse(&format!("
+            channel \"#secret\" secret(\"env:CONFIG_PARSER_TEST_SECRET\");
+            server a {{ sasl bot secret(\"file:{}\"); }}
+            password secret;
+        ", path.display())).unwrap();
+        assert_eq!(reference(&cfg.inner()[0], 1), Some("env:CONFIG_PARSER_TEST_SECRET"));
+        resolve(&mut cfg, &Standard).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(cfg, config! {
+            channel "#secret" "from env";
+            server a { sasl bot "from file"; }
+            password secret;
+        });
+
+        let sasl = &cfg.inner()[1].inner()[0];
+        assert!(sasl.is_secret(1) && !sasl.is_secret(0));
+        assert_eq!(cfg.to_string(), "channel \"#secret\" ***;\nserver a {\n    sasl bot ***;\n}\npassword secret;\n");
+        assert!(!format!("{:?}", cfg).contains("from"));
+
+        cfg.inner_mut()[0].set_param(1, "plain");
+        assert!(!cfg.inner()[0].is_secret(1));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut cfg = parse("a {\n  key secret(\"env:CONFIG_PARSER_TEST_UNSET\");\n}").unwrap();
+        assert_eq!(resolve(&mut cfg, &Standard).unwrap_err().to_string(),
+            "2:3: secret `env:CONFIG_PARSER_TEST_UNSET`: environment variable not found");
+        let mut cfg = parse("key secret(\"vault:x\");").unwrap();
+        assert_eq!(resolve(&mut cfg, &Standard).unwrap_err().to_string(),
+            "1:1: secret `vault:x`: unknown kind of secret, expected env: or file:");
+
+        assert_eq!(parse("key secret(;").unwrap_err().to_string(), "1:12: unexpected `;`, expected secret reference");
+        assert_eq!(parse("key secret(a b);").unwrap_err().to_string(), "1:14: unexpected `b`, expected )");
+        assert!(::parse_string(String::from("key secret(\"env:X\");")).is_err());
+    }
+
+    #[test]
+    fn test_only_marked_references() {
+        env::set_var("CONFIG_PARSER_TEST_MARKED", "from env");
+        let mut cfg = parse("a \"secret(env:CONFIG_PARSER_TEST_MARKED)\" secret(\"env:CONFIG_PARSER_TEST_MARKED\");").unwrap();
+        assert!(!cfg.inner()[0].is_reference(0) && cfg.inner()[0].is_reference(1));
+        resolve(&mut cfg, &Standard).unwrap();
+        assert_eq!(cfg.inner()[0].params(), &vec!["secret(env:CONFIG_PARSER_TEST_MARKED)", "from env"]);
+
+        let mut plain = config! { a "secret(env:CONFIG_PARSER_TEST_MARKED)"; };
+        resolve(&mut plain, &Standard).unwrap();
+        assert_eq!(plain.inner()[0].get(0), "secret(env:CONFIG_PARSER_TEST_MARKED)");
+
+        // References survive conditions and templates and are written back as calls
+        let options = ParseOptions::new().secrets(true).templates(true).conditionals(::options::Conditionals::Evaluate).variable("x", "1");
+        let text = "template t { pw secret(\"file:/a b\"); }\nif x { u extends t; }\nv secret(env:V);";
+        let cfg = ::parse_string_with(String::from(text), &options).unwrap();
+        assert_eq!(cfg.to_string(), "u {\n    pw secret(\"file:/a b\");\n}\nv secret(\"env:V\");\n");
+        assert_eq!(::parse_string_with(cfg.to_string(), &options).unwrap(), cfg);
+    }
+
+    #[test]
+    fn test_params_mut_keeps_marks() {
+        env::set_var("CONFIG_PARSER_TEST_KEPT", "from env");
+        let mut cfg = parse("a secret(env:CONFIG_PARSER_TEST_KEPT) y;").unwrap();
+        cfg.inner_mut()[0].set_secret_param(1, "hunter2");
+        cfg.inner_mut()[0].params_mut().push(String::from("c"));
+        assert_eq!(cfg.inner()[0].params(), &vec!["secret(env:CONFIG_PARSER_TEST_KEPT)", "hunter2", "c"]);
+        assert!(cfg.inner()[0].is_reference(0) && cfg.inner()[0].is_secret(1));
+        assert!(!format!("{:?} {}", cfg, cfg).contains("hunter2"));
+
+        cfg.inner_mut()[0].clear_secrets();
+        assert_eq!(cfg.inner()[0].params(), &vec!["secret(env:CONFIG_PARSER_TEST_KEPT)", "", "c"]);
+        assert!(!cfg.inner()[0].is_secret(1));
+        resolve(&mut cfg, &Standard).unwrap();
+        assert_eq!(cfg.inner()[0].get(0), "from env");
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// A line and column in the source. Both start at 1
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
+pub struct Position {
+    pub line: u32,
+    pub col: u16
+}
+
+impl Position {
+    pub fn new(line: u32, col: u16) -> Position {
+        Position {
+            line,
+            col
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// A region in the source, from the first character of `start` up to and including the
+/// character at `end`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span {
+            start,
+            end
+        }
+    }
+
+    /// Returns true if the position lies within this span
+    pub fn contains(&self, pos: Position) -> bool {
+        self.start <= pos && pos <= self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
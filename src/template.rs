@@ -0,0 +1,257 @@
+//! Reusable blocks
+//!
+//! With `ParseOptions::templates`, options named `template` define templates instead of
+//! ending up in the tree, and any option can inherit from them with `extends`:
+//!
+//! ```text
+//! template base_user { allow read; quota 10; }
+//! template admin extends base_user { quota 100; }
+//!
+//! user Bob extends base_user { quota 20; }
+//! user Alice extends admin;
+//! ```
+//!
+//! is read as
+//!
+//! ```text
+//! user Bob { allow read; quota 20; }
+//! user Alice { allow read; quota 100; }
+//! ```
+//!
+//! The parameters after `extends` name the templates, whose contents are merged in that
+//! order and then overridden by the inner options of the option itself, following the
+//! rules of `merge::merge`. A template is visible in the block defining it and all
+//! blocks nested inside, wherever in the block it is defined. Templates are local to
+//! the file they are written in.
+//!
+//! Options copied from a template keep their spans in the template, so later errors
+//! about them point there. Errors while resolving point at the use site and carry the
+//! position of the template in `ErrorType::Template`.
+
+use std::collections::HashMap;
+use super::config::ConfigBlock;
+use super::error::{Error, ErrorType, Result};
+use super::merge;
+use super::options::ParseOptions;
+use super::span::Position;
+
+/// The templates defined in each enclosing block, innermost last
+type Scopes<'a> = Vec<HashMap<&'a str, &'a ConfigBlock>>;
+
+/// Removes the template definitions from a tree and expands the options using them.
+/// Options copied from templates count against the block and depth limits of the options
+pub fn resolve(cfg: &ConfigBlock, options: &ParseOptions) -> Result<ConfigBlock> {
+    let mut resolver = Resolver {
+        bodies: HashMap::new(),
+        expanding: vec![],
+        blocks: measure(cfg).0,
+        max_blocks: options.max_blocks,
+        max_depth: options.max_depth
+    };
+    let inner = resolver.resolve_inner(cfg, &mut vec![])?;
+    let mut resolved = ConfigBlock::new(cfg.name().to_owned(), cfg.params().clone(), inner);
+    resolved.set_span(cfg.span());
+    Ok(resolved)
+}
+
+/// The contents of a template with everything it extends, and its size and height
+struct Body {
+    block: ConfigBlock,
+    size: usize,
+    height: usize
+}
+
+struct Resolver {
+    /// Expanded templates by address, so each one is only expanded once
+    bodies: HashMap<*const ConfigBlock, Body>,
+    /// The templates being expanded, to detect cycles
+    expanding: Vec<*const ConfigBlock>,
+    /// The options in the tree so far, counting those copied from templates
+    blocks: usize,
+    max_blocks: Option<usize>,
+    max_depth: Option<usize>
+}
+
+fn position(block: &ConfigBlock) -> Position {
+    block.span().map(|s| s.start).unwrap_or_default()
+}
+
+fn fail(at: &ConfigBlock, message: String, template: Option<&ConfigBlock>) -> Error {
+    let pos = position(at);
+    Error::new(pos.line, pos.col, ErrorType::Template(message, template.map(position)), None)
+}
+
+/// Returns the number of options below a block and how deeply they nest, 1 for options
+/// without inner options
+fn measure(block: &ConfigBlock) -> (usize, usize) {
+    block.inner().iter().map(measure).fold((0, 0), |(size, height), (s, h)| (size + s + 1, height.max(h + 1)))
+}
+
+impl Resolver {
+    /// Resolves the inner options of a block, which may define templates
+    fn resolve_inner<'a>(&mut self, block: &'a ConfigBlock, scopes: &mut Scopes<'a>) -> Result<Vec<ConfigBlock>> {
+        let mut templates = HashMap::new();
+        for template in block.inner().iter().filter(|b| b.name() == "template") {
+            let name = match template.get_opt(0) {
+                Some(name) if name != "extends" => name,
+                _ => return Err(fail(template, String::from("missing template name"), None))
+            };
+            if let Some(first) = templates.insert(name, template) {
+                return Err(fail(template, format!("template `{}` is defined twice", name), Some(first)));
+            }
+        }
+        scopes.push(templates);
+        let inner = block.inner().iter()
+            .filter(|b| b.name() != "template")
+            .map(|b| self.resolve_option(b, scopes))
+            .collect();
+        scopes.pop();
+        inner
+    }
+
+    fn resolve_option<'a>(&mut self, option: &'a ConfigBlock, scopes: &mut Scopes<'a>) -> Result<ConfigBlock> {
+        let (params, parents) = split_extends(option, 0)?;
+        let mut resolved = ConfigBlock::new(option.name().to_owned(), params.to_vec(), self.resolve_inner(option, scopes)?);
+        resolved.set_span(option.span());
+        if parents.is_empty() {
+            return Ok(resolved);
+        }
+        let (base, height) = self.expand_all(parents, option, scopes)?;
+        // Its own block is opened at depth `scopes.len()`, the template adds `height - 1`
+        if let Some(max) = self.max_depth {
+            if scopes.len() + height > max + 1 {
+                let pos = position(option);
+                return Err(Error::new(pos.line, pos.col, ErrorType::NestingTooDeep(max), None));
+            }
+        }
+        let mut merged = merge::merge(&base, &resolved);
+        merged.set_span(option.span());
+        Ok(merged)
+    }
+
+    /// Merges the contents of the named templates, returning them with their height
+    fn expand_all<'a>(&mut self, names: &[String], at: &'a ConfigBlock, scopes: &mut Scopes<'a>) -> Result<(ConfigBlock, usize)> {
+        let mut base = ConfigBlock::new(String::new(), vec![], vec![]);
+        let mut height = 0;
+        for name in names {
+            let level = match scopes.iter().rposition(|s| s.contains_key(&**name)) {
+                Some(level) => level,
+                None => return Err(fail(at, format!("unknown template `{}`", name), None))
+            };
+            let template = scopes[level][&**name];
+            let key = template as *const ConfigBlock;
+            if !self.bodies.contains_key(&key) {
+                // Templates see the templates of the block they are defined in, not of
+                // the use site, so the expansion is the same wherever they are used
+                let body = self.expand(name, template, at, &mut scopes[..=level].to_vec())?;
+                self.bodies.insert(key, body);
+            }
+            let body = &self.bodies[&key];
+            self.blocks += body.size;
+            if let Some(max) = self.max_blocks {
+                if self.blocks > max {
+                    let pos = position(at);
+                    return Err(Error::new(pos.line, pos.col, ErrorType::TooManyBlocks(max), None));
+                }
+            }
+            height = height.max(body.height);
+            base = merge::merge(&base, &body.block);
+        }
+        Ok((base, height))
+    }
+
+    fn expand<'a>(&mut self, name: &str, template: &'a ConfigBlock, at: &ConfigBlock, defined_in: &mut Scopes<'a>) -> Result<Body> {
+        let key = template as *const ConfigBlock;
+        if self.expanding.contains(&key) {
+            return Err(fail(at, format!("template `{}` extends itself", name), Some(template)));
+        }
+        self.expanding.push(key);
+        let (_, parents) = split_extends(template, 1)?;
+        let body = ConfigBlock::new(String::new(), vec![], self.resolve_inner(template, defined_in)?);
+        let block = if parents.is_empty() {
+            body
+        } else {
+            merge::merge(&self.expand_all(parents, template, defined_in)?.0, &body)
+        };
+        self.expanding.pop();
+        let (size, height) = measure(&block);
+        Ok(Body { block, size, height })
+    }
+}
+
+/// Splits the parameters after the first `skip` ones into those before `extends` and
+/// the template names after it
+fn split_extends(option: &ConfigBlock, skip: usize) -> Result<(&[String], &[String])> {
+    let params = option.params();
+    match params.iter().skip(skip).position(|p| p == "extends") {
+        Some(i) if i + skip + 1 == params.len() => Err(fail(option, String::from("missing template name after `extends`"), None)),
+        Some(i) => Ok((&params[..i + skip], &params[i + skip + 1..])),
+        None => Ok((params, &[]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use options::ParseOptions;
+
+    fn parse(text: &str) -> Result<ConfigBlock> {
+        ::parse_string_with(String::from(text), &ParseOptions::new().templates(true))
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let cfg = parse("
+            template base_user { allow read; quota 10; }
+            template admin extends base_user { quota 100; }
+            user Bob extends base_user { quota 20; }
+            user Alice extends admin;
+            server a {
+                template base_user { deny all; }
+                user Eve extends base_user admin;
+            }
+        ").unwrap();
+        assert_eq!(cfg, config! {
+            user Bob { allow read; quota 20; }
+            user Alice { allow read; quota 100; }
+            server a { user Eve { deny all; allow read; quota 100; } }
+        });
+        // Copied options point into the template
+        assert_eq!(cfg.inner()[0].inner()[0].span().unwrap().start, Position::new(2, 34));
+        assert_eq!(cfg.inner()[0].span().unwrap().start, Position::new(4, 13));
+
+        let plain = ::parse_string(String::from("template a; b extends a;")).unwrap();
+        assert_eq!(plain.inner().len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |text: &str| parse(text).unwrap_err().to_string();
+        assert_eq!(message("a extends b;"), "1:1: unknown template `b`");
+        assert_eq!(message("a extends;"), "1:1: missing template name after `extends`");
+        assert_eq!(message("template t { x; }\ntemplate t;"), "2:1: template `t` is defined twice, see 1:1");
+        assert_eq!(message("template a extends b;\ntemplate b extends a;\nc extends a;"),
+            "2:1: template `a` extends itself, see 1:1");
+        let err = parse("\n  template t { x extends u; }\nc extends t;").unwrap_err();
+        assert_eq!(err.to_string(), "2:16: unknown template `u`");
+    }
+
+    #[test]
+    fn test_limits() {
+        // Each template is expanded once, however often it is used
+        let mut text = String::from("template t0 { a; }\n");
+        for i in 1..40 {
+            text.push_str(&format!("template t{} extends t{} t{} {{ b{}; }}\n", i, i - 1, i - 1, i));
+        }
+        text.push_str("x extends t39;");
+        assert_eq!(parse(&text).unwrap().inner()[0].inner().len(), 40);
+
+        let options = ParseOptions::new().templates(true).max_blocks(Some(10));
+        let text = "template t { a; b; c; }\nx extends t;\ny extends t;\nz extends t;";
+        assert_eq!(::parse_string_with(String::from(text), &options).unwrap_err().to_string(), "3:1: more than 10 options");
+
+        let options = ParseOptions::new().templates(true).max_depth(Some(2));
+        let text = "template t { a { b; } }\nx extends t;\ny { z extends t; }";
+        assert_eq!(::parse_string_with(String::from(text), &options).unwrap_err().to_string(), "3:5: blocks nested deeper than 2 levels");
+    }
+}
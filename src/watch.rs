@@ -0,0 +1,369 @@
+//! Reloading configs when their files change
+//!
+//! A `Watcher` loads a config with `include::load` and keeps watching the main file,
+//! every file it includes and the directories of wildcard includes from a background
+//! thread. Whenever one of them changes, the
+//! config is loaded again and the callback receives either the previous and the new
+//! tree, ready for `diff::diff`, or the error. After an error, `WatchHandle::config`
+//! keeps returning the last config which loaded successfully.
+//!
+//! With the `inotify` feature, changes are noticed through inotify on Linux. Otherwise,
+//! or if inotify cannot be initialized, the files are read every `interval` and
+//! compared with their previous contents.
+//!
+//! ```no_run
+//! use config_parser::diff;
+//! use config_parser::watch::{Event, Watcher};
+//!
+//! let handle = Watcher::new("/etc/bot/bot.cfg").start(|event| match event {
+//!     Event::Reloaded { old, new } => for change in diff::diff(&old, &new).changes() {
+//!         println!("{}", change);
+//!     },
+//!     Event::Failed(e) => eprintln!("keeping the old config: {}", e)
+//! }).unwrap();
+//! let cfg = handle.config();
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::{fs, mem};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use super::config::ConfigBlock;
+use super::dialect::Dialect;
+use super::include::{self, LoadError};
+use super::options::ParseOptions;
+
+/// What the callback of a watcher is told after a change
+#[derive(Debug)]
+pub enum Event {
+    /// The files changed and the config was loaded again. `old` is the config which was
+    /// current before
+    Reloaded { old: Arc<ConfigBlock>, new: Arc<ConfigBlock> },
+    /// The files changed, but loading them failed. The previous config stays current
+    Failed(LoadError)
+}
+
+/// Configures and starts watching a config
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    path: PathBuf,
+    dialect: Dialect,
+    options: ParseOptions,
+    interval: Duration,
+    polling: bool
+}
+
+/// Controls a running watcher. Dropping it stops the watcher
+pub struct WatchHandle {
+    current: Arc<Mutex<Arc<ConfigBlock>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl Watcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Watcher {
+        Watcher {
+            path: path.into(),
+            dialect: Dialect::Native,
+            options: ParseOptions::default(),
+            interval: Duration::from_secs(1),
+            polling: false
+        }
+    }
+
+    /// Sets the syntax of the files. `Dialect::Native` by default
+    pub fn dialect(mut self, dialect: Dialect) -> Watcher {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn options(mut self, options: ParseOptions) -> Watcher {
+        self.options = options;
+        self
+    }
+
+    /// Sets how often the files are read when polling. One second by default
+    pub fn interval(mut self, interval: Duration) -> Watcher {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets whether to poll even if inotify is available
+    pub fn polling(mut self, polling: bool) -> Watcher {
+        self.polling = polling;
+        self
+    }
+
+    /// Loads the config and starts watching it. Fails if the config cannot be loaded, as
+    /// there is no previous config to fall back to
+    pub fn start<F>(self, callback: F) -> Result<WatchHandle, LoadError> where F: FnMut(Event) + Send + 'static {
+        let loaded = include::load(&self.path, self.dialect, &self.options)?;
+        let current = Arc::new(Mutex::new(Arc::new(loaded.config)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let fingerprints = loaded.files.iter().map(fingerprint).collect();
+        let listings = loaded.patterns.iter().map(|p| listing(p)).collect();
+        let mut state = State {
+            watcher: self,
+            files: loaded.files,
+            fingerprints,
+            patterns: loaded.patterns,
+            listings,
+            current: current.clone(),
+            callback
+        };
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            #[cfg(feature = "inotify")]
+            {
+                if !state.watcher.polling && notify::run(&mut state, &stopped).is_ok() {
+                    return;
+                }
+            }
+            poll(&mut state, &stopped);
+        });
+        Ok(WatchHandle { current, stop, thread: Some(thread) })
+    }
+}
+
+impl WatchHandle {
+    /// Returns the last config which loaded successfully
+    pub fn config(&self) -> Arc<ConfigBlock> {
+        self.current.lock().expect("the watcher never panics while holding the lock").clone()
+    }
+
+    /// Stops watching and waits for the background thread to finish
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+struct State<F> {
+    watcher: Watcher,
+    files: Vec<PathBuf>,
+    /// The contents of `files` when they were last loaded
+    fingerprints: Vec<Option<u64>>,
+    /// The wildcard includes, which pick up files added later
+    patterns: Vec<PathBuf>,
+    /// The files matching `patterns` when they were last loaded
+    listings: Vec<Option<u64>>,
+    current: Arc<Mutex<Arc<ConfigBlock>>>,
+    callback: F
+}
+
+impl <F> State<F> where F: FnMut(Event) {
+    fn changed(&self) -> bool {
+        self.files.iter().map(fingerprint).ne(self.fingerprints.iter().cloned())
+            || self.patterns.iter().map(|p| listing(p)).ne(self.listings.iter().cloned())
+    }
+
+    fn reload(&mut self) {
+        // Taken before loading, so that changes made while loading trigger another reload
+        let before: Vec<Option<u64>> = self.files.iter().map(fingerprint).collect();
+        let listed: Vec<Option<u64>> = self.patterns.iter().map(|p| listing(p)).collect();
+        match include::load(&self.watcher.path, self.watcher.dialect, &self.watcher.options) {
+            Ok(loaded) => {
+                self.fingerprints = carry_over(&self.files, &before, &loaded.files, fingerprint);
+                self.files = loaded.files;
+                self.listings = carry_over(&self.patterns, &listed, &loaded.patterns, |p| listing(p));
+                self.patterns = loaded.patterns;
+                let new = Arc::new(loaded.config);
+                let old = mem::replace(&mut *self.current.lock().expect("the watcher never panics while holding the lock"), new.clone());
+                (self.callback)(Event::Reloaded { old, new });
+            },
+            Err(e) => {
+                self.fingerprints = before;
+                self.listings = listed;
+                if !self.files.contains(&e.file) {
+                    self.fingerprints.push(fingerprint(&e.file));
+                    self.files.push(e.file.clone());
+                }
+                (self.callback)(Event::Failed(e));
+            }
+        }
+    }
+}
+
+fn poll<F>(state: &mut State<F>, stop: &AtomicBool) where F: FnMut(Event) {
+    while !stop.load(Ordering::SeqCst) {
+        thread::park_timeout(state.watcher.interval);
+        if !stop.load(Ordering::SeqCst) && state.changed() {
+            state.reload();
+        }
+    }
+}
+
+/// Returns the identities of `paths`, reusing those taken of `old` before loading
+fn carry_over<F>(old: &[PathBuf], before: &[Option<u64>], paths: &[PathBuf], now: F) -> Vec<Option<u64>> where F: Fn(&PathBuf) -> Option<u64> {
+    paths.iter().map(|p| match old.iter().position(|o| o == p) {
+        Some(i) => before[i],
+        None => now(p)
+    }).collect()
+}
+
+/// Identifies the contents of a file, `None` if it cannot be read
+fn fingerprint(path: &PathBuf) -> Option<u64> {
+    let contents = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Identifies the files matching a wildcard include, `None` if its directory cannot be read
+fn listing(pattern: &Path) -> Option<u64> {
+    let files = include::glob(pattern).ok()?;
+    let mut hasher = DefaultHasher::new();
+    files.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[cfg(feature = "inotify")]
+mod notify {
+    use std::collections::HashSet;
+    use std::ffi::{OsStr, OsString};
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use inotify::{Inotify, WatchMask};
+    use super::{include, Event, State};
+
+    /// How often the event queue is checked. A change is reloaded once no further
+    /// events arrived for this long
+    const TICK: Duration = Duration::from_millis(50);
+
+    /// Watches the directories of the files, so that files replaced by editors are
+    /// noticed as well. Fails if inotify is unavailable
+    pub fn run<F>(state: &mut State<F>, stop: &AtomicBool) -> io::Result<()> where F: FnMut(Event) {
+        let mut inotify = Inotify::init()?;
+        let mask = WatchMask::CLOSE_WRITE | WatchMask::MODIFY | WatchMask::CREATE | WatchMask::DELETE
+            | WatchMask::MOVED_TO | WatchMask::MOVED_FROM;
+        let mut watched = HashSet::new();
+        let mut buffer = [0; 4096];
+        let mut pending = false;
+        while !stop.load(Ordering::SeqCst) {
+            for dir in directories(&state.files).into_iter().chain(directories(&state.patterns)) {
+                if watched.insert(dir.clone()) {
+                    inotify.watches().add(&dir, mask)?;
+                    // The files may have changed before the watch was added
+                    pending = true;
+                }
+            }
+            let names: HashSet<OsString> = state.files.iter().filter_map(|f| f.file_name()).map(|n| n.to_owned()).collect();
+            let patterns: Vec<&str> = state.patterns.iter().filter_map(|p| p.file_name()?.to_str()).collect();
+            let relevant_name = |name: &OsStr| names.contains(name)
+                || name.to_str().map(|n| patterns.iter().any(|p| include::wildcard_match(p, n))).unwrap_or(false);
+            let relevant = inotify.read_events(&mut buffer).map(|events| {
+                events.filter(|e| e.name.map(relevant_name).unwrap_or(false)).count() > 0
+            });
+            match relevant {
+                Ok(true) => pending = true,
+                Ok(false) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if pending && state.changed() {
+                        state.reload();
+                    }
+                    pending = false;
+                },
+                Err(e) => return Err(e)
+            }
+            thread::park_timeout(TICK);
+        }
+        Ok(())
+    }
+
+    fn directories(files: &[PathBuf]) -> Vec<PathBuf> {
+        files.iter()
+            .map(|f| match f.parent() {
+                Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+                _ => PathBuf::from(".")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::sync::mpsc;
+
+    fn watch(name: &str, polling: bool) {
+        let dir = env::temp_dir().join(format!("config-parser-watch-{}-{}", name, ::std::process::id()));
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("bot.cfg"), "nick a;\ninclude server.cfg;\ninclude conf.d/*.cfg;\n").unwrap();
+        fs::write(dir.join("server.cfg"), "server x;").unwrap();
+
+        let (sender, events) = mpsc::channel();
+        let handle = Watcher::new(dir.join("bot.cfg"))
+            .interval(Duration::from_millis(20))
+            .polling(polling)
+            .start(move |event| sender.send(event).unwrap())
+            .unwrap();
+        assert_eq!(*handle.config(), config! { nick a; server x; });
+        let next = || events.recv_timeout(Duration::from_secs(10)).expect("no reload");
+
+        fs::write(dir.join("server.cfg"), "server y;").unwrap();
+        match next() {
+            Event::Reloaded { old, new } => {
+                assert_eq!(*old, config! { nick a; server x; });
+                assert_eq!(*new, config! { nick a; server y; });
+            },
+            other => panic!("{:?}", other)
+        }
+
+        fs::write(dir.join("conf.d/extra.cfg"), "channel c;").unwrap();
+        match next() {
+            Event::Reloaded { new, .. } => assert_eq!(*new, config! { nick a; server y; channel c; }),
+            other => panic!("{:?}", other)
+        }
+
+        fs::write(dir.join("bot.cfg"), "nick b").unwrap();
+        match next() {
+            Event::Failed(e) => assert_eq!(e.file, dir.join("bot.cfg")),
+            other => panic!("{:?}", other)
+        }
+        assert_eq!(*handle.config(), config! { nick a; server y; channel c; });
+
+        fs::write(dir.join("bot.cfg"), "nick b;").unwrap();
+        match next() {
+            Event::Reloaded { old, new } => {
+                assert_eq!(*old, config! { nick a; server y; channel c; });
+                assert_eq!(*new, config! { nick b; });
+            },
+            other => panic!("{:?}", other)
+        }
+        assert_eq!(*handle.config(), config! { nick b; });
+        handle.stop();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_polling() {
+        watch("poll", true);
+    }
+
+    #[cfg(feature = "inotify")]
+    #[test]
+    fn test_inotify() {
+        watch("inotify", false);
+    }
+}
@@ -0,0 +1,117 @@
+//! Writes configuration trees back in the config syntax
+//!
+//! `ConfigBlock` implements `Display`, producing the canonical layout: one option per
+//! line, blocks indented by four spaces and parameters quoted only where needed. The
+//! root block (the one with an empty name, as returned by the parse functions) writes
+//! just its inner options. Parsing the output yields an equal tree, except that secret
+//! parameters are written as `***`. Secret references are written as `secret("...")`,
+//! which is read back with `ParseOptions::secrets`.
+
+use std::borrow::Cow;
+use std::fmt;
+use super::config::{ConfigBlock, REDACTED};
+use super::secret;
+
+const INDENT: &str = "    ";
+
+/// Returns the value as it has to be written to be read back unchanged, quoting and
+/// escaping it if necessary
+pub fn quote(value: &str) -> Cow<'_, str> {
+    if !needs_quotes(value) {
+        return Cow::Borrowed(value);
+    }
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    Cow::Owned(out)
+}
+
+fn needs_quotes(value: &str) -> bool {
+    value.is_empty()
+        || value.starts_with('"')
+        || value.starts_with('#')
+        || value.starts_with("//")
+        || value.starts_with("/*")
+        || value.chars().any(|c| c.is_whitespace() || matches!(c, '(' | ')' | '{' | '}' | ';'))
+}
+
+fn write_block(f: &mut fmt::Formatter, block: &ConfigBlock, depth: usize) -> fmt::Result {
+    for _ in 0..depth {
+        f.write_str(INDENT)?;
+    }
+    f.write_str(&quote(block.name()))?;
+    for (i, param) in block.params().iter().enumerate() {
+        if block.is_secret(i) {
+            write!(f, " {}", REDACTED)?;
+        } else if let Some(reference) = secret::reference(block, i) {
+            write!(f, " {}", secret::call(reference))?;
+        } else {
+            write!(f, " {}", quote(param))?;
+        }
+    }
+    if block.inner().is_empty() {
+        return f.write_str(";\n");
+    }
+    f.write_str(" {\n")?;
+    for inner in block.inner() {
+        write_block(f, inner, depth + 1)?;
+    }
+    for _ in 0..depth {
+        f.write_str(INDENT)?;
+    }
+    f.write_str("}\n")
+}
+
+impl fmt::Display for ConfigBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name().is_empty() && self.params().is_empty() {
+            for inner in self.inner() {
+                write_block(f, inner, 0)?;
+            }
+            Ok(())
+        } else {
+            write_block(f, self, 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a\"b"), "a\"b");
+        assert_eq!(quote(""), "\"\"");
+        assert_eq!(quote("two words"), "\"two words\"");
+        assert_eq!(quote("#chan"), "\"#chan\"");
+        assert_eq!(quote("\"x\\\ny"), "\"\\\"x\\\\\\ny\"");
+        assert_eq!(quote("a{b"), "\"a{b\"");
+    }
+
+    #[test]
+    fn test_canonical_output() {
+        let cfg = ::parse_string(String::from("server  freenode{connect irc.freenode.net 6697 ;channel \"#rust\" ; user x{ nick \"a b\";}}")).unwrap();
+        assert_eq!(cfg.to_string(), "server freenode {\n    connect irc.freenode.net 6697;\n    channel \"#rust\";\n    user x {\n        nick \"a b\";\n    }\n}\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cfg = config! {
+            a "" "x y" "#c" "\"q\\\n";
+            b { c "{}"; }
+            "my key" v;
+            "" { "}" x; }
+        };
+        assert_eq!(::parse_string(cfg.to_string()).unwrap(), cfg);
+    }
+}
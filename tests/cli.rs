@@ -0,0 +1,144 @@
+#![cfg(feature = "cli")]
+
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_config-parser"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Commands rejected before reading their input close stdin early
+    if let Err(e) = child.stdin.take().unwrap().write_all(stdin.as_bytes()) {
+        assert_eq!(e.kind(), ErrorKind::BrokenPipe);
+    }
+    child.wait_with_output().unwrap()
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("config-parser-cli-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn test_check() {
+    assert_eq!(run(&["check"], "server a { nick b; }").status.code(), Some(0));
+
+    let out = run(&["check", "-"], "server a {\n    nick b )\n}");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stderr(&out), "error: unexpected `)`, expected ; or {\n --> <stdin>:2:12\n  |\n2 |     nick b )\n  |            ^\n");
+
+    let schema = temp_file("schema.cfg", "directive server { params 1; block { directive nick { params 1; } } }");
+    let out = run(&["check", "--schema", schema.to_str().unwrap()], "server a { nick b; }\nuser c;");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(stderr(&out).starts_with("error: unknown option `user`\n --> <stdin>:2:1\n"));
+    fs::remove_file(schema).unwrap();
+}
+
+#[test]
+fn test_fmt() {
+    let out = run(&["fmt"], "a  b{c;}");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out), "a b {\n    c;\n}\n");
+
+    let file = temp_file("fmt.cfg", "a  b{c;}");
+    assert_eq!(run(&["fmt", "--check", file.to_str().unwrap()], "").status.code(), Some(1));
+    assert_eq!(run(&["fmt", file.to_str().unwrap()], "").status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a b {\n    c;\n}\n");
+    assert_eq!(run(&["fmt", "--check", file.to_str().unwrap()], "").status.code(), Some(0));
+    fs::remove_file(file).unwrap();
+
+    let out = run(&["fmt", "--indent", "2", "--align"], "# keep me\na {b c; dd e;} // and me");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out), "# keep me\na {\n  b  c;\n  dd e;\n} // and me\n");
+    assert_eq!(run(&["fmt"], "a {").status.code(), Some(1));
+}
+
+#[test]
+fn test_get() {
+    let input = "server a { channel \"#x y\" key; }\nserver b { channel z; }";
+    let out = run(&["get", "server/channel"], input);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out), "\"#x y\" key\nz\n");
+    assert_eq!(run(&["get", "server[c]"], input).status.code(), Some(1));
+    assert_eq!(run(&["get", "server["], input).status.code(), Some(2));
+}
+
+#[test]
+fn test_diff() {
+    let old = temp_file("old.cfg", "server a {\n    port 1;\n}\n");
+    let new = temp_file("new.cfg", "server a { port 2; }");
+    let out = run(&["diff", old.to_str().unwrap(), new.to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout(&out), format!("--- {}\n+++ {}\n@@ server[a] @@\n server a {{\n-    port 1;\n+    port 2;\n }}\n",
+        old.display(), new.display()));
+    assert_eq!(run(&["diff", old.to_str().unwrap(), old.to_str().unwrap()], "").status.code(), Some(0));
+    assert_eq!(run(&["diff", old.to_str().unwrap()], "").status.code(), Some(2));
+
+    let out = run(&["diff", "--patch", old.to_str().unwrap(), new.to_str().unwrap()], "");
+    assert_eq!(stdout(&out), "set server[a]/port[1] 2;\n");
+    let patch = temp_file("patch.cfg", &stdout(&out));
+    let out = run(&["patch", patch.to_str().unwrap(), old.to_str().unwrap()], "");
+    assert_eq!(stdout(&out), "server a {\n    port 2;\n}\n");
+    let out = run(&["patch", patch.to_str().unwrap()], "server b;");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stderr(&out), format!("{}: operation 1: `server[a]` does not match any option\n", patch.display()));
+    for file in &[old, new, patch] {
+        fs::remove_file(file).unwrap();
+    }
+}
+
+#[test]
+fn test_merge() {
+    let base = temp_file("base.cfg", "nick bot;\nchannel a;\nchannel b;\n");
+    let user = temp_file("user.cfg", "nick me;\n!channel b;\nchannel c;\n");
+    let out = run(&["merge", base.to_str().unwrap(), user.to_str().unwrap()], "");
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(stdout(&out), "nick me;\nchannel a;\nchannel c;\n");
+    assert_eq!(run(&["merge", base.to_str().unwrap()], "").status.code(), Some(2));
+    fs::remove_file(base).unwrap();
+    fs::remove_file(user).unwrap();
+}
+
+#[test]
+fn test_convert() {
+    let out = run(&["convert", "--to", "json"], "a b;");
+    assert_eq!(stdout(&out), "{\n  \"name\": \"\",\n  \"params\": [],\n  \"children\": [\n    {\n      \"name\": \"a\",\n      \"params\": [\n        \"b\"\n      ],\n      \"children\": []\n    }\n  ]\n}\n");
+    assert!(stdout(&run(&["convert", "--to", "toml"], "a b;")).contains("[[children]]\nname = \"a\""));
+    assert!(stdout(&run(&["convert", "--to", "yaml"], "a b;")).contains("- name: a\n"));
+    assert_eq!(run(&["convert", "--to", "xml"], "a b;").status.code(), Some(2));
+    assert_eq!(stdout(&run(&["convert", "--to", "json", "--idiomatic"], "a b; c { d; }")), "{\n  \"a\": \"b\",\n  \"c\": {\n    \"d\": []\n  }\n}\n");
+    assert_eq!(stdout(&run(&["convert", "--from", "json", "--idiomatic"], r#"{"a": "b c", "d": {"e": 1}}"#)), "a \"b c\";\nd {\n    e 1;\n}\n");
+    let out = run(&["convert", "--from", "yaml", "--idiomatic"], "a:\n  b: [1, 2]\n");
+    assert_eq!(stdout(&out), "a {\n    b 1 2;\n}\n");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("warning: /a/b/0: a number becomes a string"));
+    assert_eq!(stdout(&run(&["convert", "--to", "toml", "--idiomatic"], "a b;")), "a = \"b\"\n");
+    let out = run(&["convert", "--from", "json"], "[]");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("expected an object"));
+}
+
+#[test]
+fn test_dialects() {
+    let out = run(&["--dialect", "nginx", "get", "http/server/listen"], "http { server { listen 80; } } # ok");
+    assert_eq!(stdout(&out), "80\n");
+    let out = run(&["--dialect", "pulseaudio", "get", "load-module"], "load-module module-null-sink sink_name=x\n");
+    assert_eq!(stdout(&out), "module-null-sink sink_name=x\n");
+    assert_eq!(run(&["--dialect", "nginx", "fmt"], "a;").status.code(), Some(2));
+    assert_eq!(run(&["--dialect", "ini", "check"], "a;").status.code(), Some(2));
+}
@@ -12,3 +12,58 @@ fn test_parse_typical_file() {
     assert_eq!(i.len(), 1);
     assert_eq!(i.get(0), "shit");
 }
+
+#[test]
+fn test_streaming_events() {
+    use config_parser::Event;
+
+    let names: Vec<String> = config_parser::parse_events("server a { nick b; } blah;".chars())
+        .map(|e| match e.unwrap() {
+            Event::BlockStart { name, .. } => format!("start {}", name),
+            Event::Directive { name, .. } => name,
+            Event::BlockEnd { .. } => String::from("end")
+        })
+        .collect();
+    assert_eq!(names, vec!["start server", "nick", "end", "blah"]);
+}
+
+#[test]
+fn test_streaming_adapted_syntax() {
+    use config_parser::{Conditionals, Event, ParseOptions};
+
+    let options = ParseOptions::new().conditionals(Conditionals::Evaluate);
+    let events: Vec<Event> = config_parser::parse_events_with("if (a) { b; }".chars(), &options)
+        .collect::<Result<_, _>>().unwrap();
+    match events[0] {
+        Event::BlockStart { ref name, ref params, .. } => assert_eq!((&**name, params.join(" ")), ("if", String::from("( a )"))),
+        ref other => panic!("{:?}", other)
+    }
+
+    let options = ParseOptions::new().secrets(true);
+    let events: Vec<Event> = config_parser::parse_events_with("key secret(\"env:X\");".chars(), &options)
+        .collect::<Result<_, _>>().unwrap();
+    match events[0] {
+        Event::Directive { ref params, .. } => assert_eq!(params, &vec![String::from("secret(env:X)")]),
+        ref other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_reader() {
+    use std::io::{self, BufReader, Read};
+
+    let cfg = config_parser::parse_reader(&b"server \"fr\xc3\xa9enode\" { nick Bot; }"[..]).unwrap();
+    assert_eq!(cfg.inner()[0].get(0), "fréenode");
+
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"))
+        }
+    }
+
+    let err = config_parser::parse_reader(BufReader::new(io::Cursor::new(&b"server a {"[..]).chain(Broken)));
+    assert_eq!(
+        err,
+        Err(config_parser::ParseError::new(1, 10, config_parser::error::ErrorType::Io(io::ErrorKind::BrokenPipe, String::from("pipe closed")), None)));
+}